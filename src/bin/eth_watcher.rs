@@ -1,42 +1,218 @@
 use eth_watcher::config;
 use eth_watcher::eth_client::EthereumGateway;
-use eth_watcher::eth_watch::{EthHttpClient, EthWatch, EthWatchRequest};
+use eth_watcher::eth_watch::{EthHttpClient, EthWatchBuilder, EthWatchRequest, GatewayHandle};
+use eth_watcher::utils::panic_notify::ThreadPanicNotify;
 use fluidex_common::non_blocking_tracing;
-use futures::{channel::mpsc, SinkExt};
-use tokio::{runtime::Runtime, time};
+use futures::{channel::mpsc, SinkExt, StreamExt};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::runtime::Runtime;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::time;
+
+/// Amount of times in a row the watcher is restarted after a panic before the process gives up.
+const MAX_WATCHER_RESTARTS: usize = 5;
+/// The watcher running this long without a panic is considered healthy, and the restarts are counted anew.
+const HEALTHY_RUN_PERIOD: Duration = Duration::from_secs(600);
+/// Delay before restarting the panicked watcher.
+const WATCHER_RESTART_DELAY: Duration = Duration::from_secs(5);
+/// How often the config file is checked for changes.
+const CONFIG_CHECK_INTERVAL: Duration = Duration::from_secs(2);
 
 fn main() {
-    let mut main_runtime = Runtime::new().expect("main runtime start");
+    let exit_code = {
+        let mut main_runtime = Runtime::new().expect("main runtime start");
+
+        dotenv::dotenv().ok();
+        // Log sinks are flushed when the guard is dropped, which must happen before `process::exit`.
+        let _guard = non_blocking_tracing::setup();
+        log::info!("ETH watcher started");
+
+        // All the problems with the settings are reported at once, before anything is started.
+        let settings = config::load_settings().and_then(|settings| {
+            main_runtime.block_on(settings.validate_with_endpoints())?;
+            Ok(settings)
+        });
+        match settings {
+            Ok(settings) => {
+                log::debug!("{:?}", settings);
+                if settings.log_level.is_some() {
+                    apply_log_level(&settings);
+                }
+                let exit_code = main_runtime.block_on(run_supervised(settings));
+                log::info!("ETH watcher stopped");
+                exit_code
+            }
+            Err(error) => {
+                log::error!("Unable to load the settings: {:#}", error);
+                1
+            }
+        }
+    };
+    std::process::exit(exit_code);
+}
 
-    dotenv::dotenv().ok();
-    let _guard = non_blocking_tracing::setup();
-    log::info!("ETH watcher started");
+/// Runs the watcher, restarting it if it panics, and applying the settings reloaded on the fly.
+/// Returns the exit code for the process.
+async fn run_supervised(mut settings: config::Settings) -> i32 {
+    let (panic_sender, mut panic_receiver) = mpsc::channel(1);
+    let shutdown = shutdown_signal();
+    tokio::pin!(shutdown);
+    let mut reload_requests = reload_requests(config::loader::config_file_path());
 
-    let mut conf = config_rs::Config::new();
-    let config_file = dotenv::var("CONFIG_FILE").unwrap();
-    conf.merge(config_rs::File::with_name(&config_file)).unwrap();
-    let settings: config::Settings = conf.try_into().unwrap();
-    log::debug!("{:?}", settings);
+    let mut restarts = 0;
+    loop {
+        let client = EthereumGateway::read_only_from_config(&settings);
+        let eth_client = EthHttpClient::new(client, settings.contracts.contract_addr);
+        let gateway = eth_client.gateway_handle();
+        let watcher = EthWatchBuilder::from_config(eth_client, &settings.eth_watch).build();
+        let started_at = Instant::now();
 
-    let client = EthereumGateway::from_config(&settings);
+        // The watcher polls the node on its own, the sender is used to reconfigure it and to stop it on shutdown.
+        let (mut eth_req_sender, eth_req_receiver) = mpsc::channel(256);
+        let panic_notify = ThreadPanicNotify(panic_sender.clone());
+        let watcher_handle = tokio::spawn(async move {
+            // Tokio drops the future of a panicked task while unwinding, which triggers the notification.
+            let _panic_notify = panic_notify;
+            watcher.run(eth_req_receiver).await;
+        });
 
-    let (eth_req_sender, eth_req_receiver) = mpsc::channel(256);
+        loop {
+            tokio::select! {
+                _ = &mut shutdown => {
+                    // Dropping the sender stops the watcher, which persists its state before exiting.
+                    drop(eth_req_sender);
+                    if let Err(error) = watcher_handle.await {
+                        log::error!("ETH watcher failed during shutdown: {}", error);
+                        return 1;
+                    }
+                    return 0;
+                }
+                _ = panic_receiver.next() => {
+                    if started_at.elapsed() >= HEALTHY_RUN_PERIOD {
+                        restarts = 0;
+                    }
+                    restarts += 1;
+                    if restarts > MAX_WATCHER_RESTARTS {
+                        log::error!("ETH watcher panicked {} times in a row, giving up", restarts);
+                        return 1;
+                    }
+                    log::warn!(
+                        "ETH watcher panicked, restarting in {} seconds ({}/{})",
+                        WATCHER_RESTART_DELAY.as_secs(),
+                        restarts,
+                        MAX_WATCHER_RESTARTS
+                    );
+                    tokio::select! {
+                        _ = &mut shutdown => return 0,
+                        _ = time::delay_for(WATCHER_RESTART_DELAY) => {}
+                    }
+                    break;
+                }
+                Some(()) = reload_requests.next() => {
+                    if let Some(new_settings) = reload_settings(&settings).await {
+                        apply_settings(&settings, &new_settings, &gateway, &mut eth_req_sender).await;
+                        settings = new_settings;
+                    }
+                }
+            }
+        }
+    }
+}
 
-    let eth_client = EthHttpClient::new(client, settings.contracts.contract_addr);
-    let watcher = EthWatch::new(eth_client, settings.eth_watch.confirmations_for_eth_event);
+/// Resolves once SIGINT or SIGTERM is received.
+async fn shutdown_signal() {
+    let mut sigterm = signal(SignalKind::terminate()).expect("failed to install SIGTERM handler");
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => log::info!("SIGINT received, shutting down"),
+        _ = sigterm.recv() => log::info!("SIGTERM received, shutting down"),
+    }
+}
 
-    main_runtime.spawn(watcher.run(eth_req_receiver));
-    let poll_interval = settings.eth_watch.poll_interval();
-    main_runtime.block_on(async move {
-        let mut timer = time::interval(poll_interval);
+/// Yields whenever the settings should be reloaded: on SIGHUP, or when the config file is modified.
+fn reload_requests(config_file: Option<String>) -> mpsc::Receiver<()> {
+    let (mut sender, receiver) = mpsc::channel(1);
+    let mut sighup = signal(SignalKind::hangup()).expect("failed to install SIGHUP handler");
 
+    tokio::spawn(async move {
+        let mut modified = config_file.as_deref().and_then(modification_time);
+        let mut check_interval = time::interval(CONFIG_CHECK_INTERVAL);
         loop {
-            timer.tick().await;
-            eth_req_sender
-                .clone()
-                .send(EthWatchRequest::PollETHNode)
-                .await
-                .expect("ETH watch receiver dropped");
+            tokio::select! {
+                _ = sighup.recv() => log::info!("SIGHUP received, reloading the settings"),
+                _ = check_interval.tick() => {
+                    let current = config_file.as_deref().and_then(modification_time);
+                    if current == modified {
+                        continue;
+                    }
+                    modified = current;
+                    log::info!("Config file changed, reloading the settings");
+                }
+            }
+            // If the channel is full, a reload is already pending.
+            if sender.try_send(()).is_err() && sender.is_closed() {
+                break;
+            }
         }
     });
+    receiver
+}
+
+fn modification_time(path: &str) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+/// Loads and checks the new settings. Returns `None` if they are unchanged or can't be applied.
+async fn reload_settings(current: &config::Settings) -> Option<config::Settings> {
+    let new_settings = match config::load_settings() {
+        Ok(settings) => settings,
+        Err(error) => {
+            log::error!("Unable to reload the settings, keeping the current ones: {:#}", error);
+            return None;
+        }
+    };
+    if new_settings == *current {
+        log::info!("Settings are not changed");
+        return None;
+    }
+
+    let checked = match current.check_reload(&new_settings) {
+        Ok(()) => new_settings.validate_with_endpoints().await,
+        Err(error) => Err(error),
+    };
+    if let Err(error) = checked {
+        log::error!("Reloaded settings are rejected, keeping the current ones: {}", error);
+        return None;
+    }
+    Some(new_settings)
+}
+
+async fn apply_settings(
+    current: &config::Settings,
+    new: &config::Settings,
+    gateway: &GatewayHandle,
+    eth_req_sender: &mut mpsc::Sender<EthWatchRequest>,
+) {
+    log::debug!("{:?}", new);
+
+    if new.eth_client != current.eth_client {
+        gateway.replace(EthereumGateway::read_only_from_config(new));
+        log::info!("Switched to the Ethereum nodes {:?}", new.eth_client.web3_url);
+    }
+
+    if new.eth_watch != current.eth_watch {
+        let request = EthWatchRequest::Reconfigure {
+            config: new.eth_watch.clone(),
+        };
+        if eth_req_sender.send(request).await.is_err() {
+            // The watcher has panicked, it will be restarted with the new settings.
+            log::warn!("ETH watcher is not running, unable to reconfigure it");
+        }
+    }
+
+    apply_log_level(new);
+}
+
+fn apply_log_level(settings: &config::Settings) {
+    // If no level is set, the messages are only filtered by the tracing setup.
+    log::set_max_level(settings.log_level_filter().unwrap_or(log::LevelFilter::Trace));
 }
@@ -1,16 +1,34 @@
+use crate::eth_signer::raw_ethereum_tx::TransactionType;
 use serde::Deserialize;
+use std::collections::HashMap;
+
+/// Margin added to the estimated gas limit, since the state may change before the transaction is executed.
+pub const DEFAULT_GAS_LIMIT_MULTIPLIER: f64 = 1.2;
 
 /// Configuration for the Ethereum gateways.
 #[derive(Debug, Deserialize, Clone, PartialEq)]
 pub struct ETHClientConfig {
     /// Numeric identifier of the L1 network (e.g. `9` for localhost).
-    pub chain_id: u8,
+    pub chain_id: u64,
     /// How much do we want to increase gas price provided by the network?
     /// Normally it's 1, we use the network-provided price (and limit it with the gas adjuster in eth sender).
     /// However, it can be increased to speed up the transaction mining time.
     pub gas_price_factor: f64,
     /// Address of the Ethereum node API.
     pub web3_url: Vec<String>,
+    /// Type of the transactions sent to the network: `legacy`, `eip2930` or `eip1559`.
+    #[serde(default)]
+    pub transaction_type: TransactionType,
+    /// The gas limits estimated with `eth_estimateGas` are multiplied by this factor, since the state
+    /// may change before the transaction is executed.
+    pub gas_limit_multiplier: f64,
+    /// Gas limits of the Fluidex contract functions by name (e.g. `commitBlock`), used if the estimation fails.
+    #[serde(default)]
+    pub default_gas_limits: HashMap<String, u64>,
+    /// Whether the independent requests (e.g. the event queries of a single poll) are sent
+    /// in a single JSON-RPC batch. Not every node provider supports the batches.
+    #[serde(default)]
+    pub batch_requests: bool,
 }
 
 impl ETHClientConfig {
@@ -1,5 +1,6 @@
 use crate::types::{Address, H256};
 use serde::Deserialize;
+use std::fmt;
 use std::time::Duration;
 
 /// Configuration for the Ethereum sender crate.
@@ -11,10 +12,90 @@ pub struct ETHSenderConfig {
     pub gas_price_limit: GasLimit,
 }
 
+/// Source of the signatures of the operator account.
 #[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SignerConfig {
+    /// Transactions are signed with `operator_private_key`.
+    PrivateKey,
+    /// Transactions are signed with the key from the encrypted V3 keystore file. The passphrase is read
+    /// from `passphrase_file`, or from the `ETH_WATCH_KEYSTORE_PASSPHRASE` variable if no file is set.
+    Keystore {
+        path: String,
+        #[serde(default)]
+        passphrase_file: Option<String>,
+    },
+    /// Transactions are signed by the account managed by an Ethereum node (`eth_signTransaction`).
+    JsonRpc {
+        url: String,
+        /// Account to sign with, the first account of the node by default.
+        #[serde(default)]
+        address: Option<Address>,
+    },
+    /// Transactions are signed by a dedicated remote signer, Web3Signer or Clef.
+    Remote(RemoteSignerConfig),
+}
+
+/// API of the remote signer.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum RemoteSignerKind {
+    /// Web3Signer, signing the data with the `/api/v1/eth1/sign/{public_key}` endpoint.
+    Web3signer,
+    /// Clef, signing the data with the `account_sign*` JSON-RPC methods.
+    Clef,
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct RemoteSignerConfig {
+    /// Address of the signer API, e.g. `https://127.0.0.1:9000`.
+    pub url: String,
+    pub api: RemoteSignerKind,
+    /// Public key identifying the operator key in Web3Signer, hex-encoded.
+    #[serde(default)]
+    pub public_key: Option<String>,
+    /// Time given to the signer to answer a single request in seconds. Clef may wait for the manual approval,
+    /// so the default is generous.
+    #[serde(default = "RemoteSignerConfig::default_request_timeout")]
+    pub request_timeout: u64,
+    /// PKCS#12 archive with the client certificate and key, used to authenticate to the signer.
+    #[serde(default)]
+    pub client_identity_file: Option<String>,
+    /// File containing the password of the PKCS#12 archive.
+    #[serde(default)]
+    pub client_identity_password_file: Option<String>,
+    /// PEM certificate of the CA which issued the signer certificate, if it's not trusted by the system.
+    #[serde(default)]
+    pub ca_cert_file: Option<String>,
+}
+
+impl RemoteSignerConfig {
+    fn default_request_timeout() -> u64 {
+        30
+    }
+
+    /// Converts `self.request_timeout` into `Duration`.
+    pub fn request_timeout(&self) -> Duration {
+        Duration::from_secs(self.request_timeout)
+    }
+}
+
+impl Default for SignerConfig {
+    fn default() -> Self {
+        Self::PrivateKey
+    }
+}
+
+#[derive(Deserialize, Clone, PartialEq)]
 pub struct Sender {
-    /// Private key of the operator account.
-    pub operator_private_key: H256,
+    /// How the transactions are signed.
+    #[serde(default)]
+    pub signer: SignerConfig,
+    /// Private key of the operator account, required by the `private_key` signer.
+    /// Instead of storing it in the config file, it can be provided by the `ETH_WATCH__ETH_SENDER__SENDER__OPERATOR_PRIVATE_KEY`
+    /// variable, or read from the file named by the `operator_private_key_file` setting.
+    #[serde(default)]
+    pub operator_private_key: Option<H256>,
     /// Address of the operator account.
     pub operator_commit_eth_addr: Address,
     /// mount of confirmations required to consider L1 transaction committed.
@@ -27,6 +108,27 @@ pub struct Sender {
     pub max_txs_in_flight: u64,
     /// Whether sender should interact with L1 or not.
     pub is_enabled: bool,
+    /// Path to the journal of the sender operations. If not set, the operations which are not
+    /// completed yet are lost on restart.
+    #[serde(default)]
+    pub journal_file: Option<String>,
+}
+
+// The private key is not printed, since the settings are dumped into the logs.
+impl fmt::Debug for Sender {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Sender")
+            .field("signer", &self.signer)
+            .field("operator_private_key", &"<redacted>")
+            .field("operator_commit_eth_addr", &self.operator_commit_eth_addr)
+            .field("wait_confirmations", &self.wait_confirmations)
+            .field("expected_wait_time_block", &self.expected_wait_time_block)
+            .field("tx_poll_period", &self.tx_poll_period)
+            .field("max_txs_in_flight", &self.max_txs_in_flight)
+            .field("is_enabled", &self.is_enabled)
+            .field("journal_file", &self.journal_file)
+            .finish()
+    }
 }
 
 impl Sender {
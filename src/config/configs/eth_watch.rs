@@ -1,6 +1,13 @@
 use serde::Deserialize;
 use std::time::Duration;
 
+/// Amount of new blocks found by a single poll, above which the watcher is considered
+/// to be behind the chain head, and polls again right away.
+pub const DEFAULT_IMMEDIATE_REPOLL_THRESHOLD: u64 = 16;
+
+/// Maximum amount of blocks processed by a single poll used when none is provided.
+pub const DEFAULT_MAX_BLOCKS_PER_BATCH: u64 = 1000;
+
 /// Configuration for the Ethereum sender crate.
 #[derive(Debug, Deserialize, Clone, PartialEq)]
 pub struct ETHWatchConfig {
@@ -10,6 +17,31 @@ pub struct ETHWatchConfig {
     /// How often we want to poll the Ethereum node.
     /// Value in milliseconds.
     pub eth_node_poll_interval: u64,
+    /// If a single poll finds more new blocks than this threshold, the watcher is considered
+    /// to be behind the chain head and polls the node again without waiting for the poll interval.
+    #[serde(default = "default_immediate_repoll_threshold")]
+    pub immediate_repoll_threshold: u64,
+    /// Maximum amount of blocks processed by a single poll. When the watcher is further
+    /// behind the chain head, it catches up in batches of this size.
+    #[serde(default = "default_max_blocks_per_batch")]
+    pub max_blocks_per_batch: u64,
+    /// Path to the file the watcher state is persisted to on shutdown.
+    /// If not set, the state is restored from the Ethereum node on every start.
+    #[serde(default)]
+    pub state_file: Option<String>,
+    /// Path to the file the registry of the tokens added to the network is persisted to.
+    /// If not set, the registry is lost on restart, and only the tokens whose `NewToken` events are
+    /// processed again are registered: the blocks before the last `PRIORITY_EXPIRATION` ones are never rescanned.
+    #[serde(default)]
+    pub token_registry_file: Option<String>,
+}
+
+fn default_immediate_repoll_threshold() -> u64 {
+    DEFAULT_IMMEDIATE_REPOLL_THRESHOLD
+}
+
+fn default_max_blocks_per_batch() -> u64 {
+    DEFAULT_MAX_BLOCKS_PER_BATCH
 }
 
 impl ETHWatchConfig {
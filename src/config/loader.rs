@@ -0,0 +1,246 @@
+//! Layered loading of the `Settings`.
+//!
+//! Every field can be provided by any of the following sources, each one overriding the previous:
+//!
+//! 1. Built-in defaults (see `set_defaults`).
+//! 2. The YAML file passed via `--config` or named by the `CONFIG_FILE` variable.
+//! 3. Environment variables named `ETH_WATCH__<SECTION>__<FIELD>`, e.g.
+//!    `ETH_WATCH__ETH_CLIENT__CHAIN_ID=9`.
+//! 4. Command line flags named after the field path, e.g. `--eth_client.chain_id=9`.
+//!
+//! List fields (`eth_client.web3_url`) may be provided as a comma-separated string by the
+//! environment variables and the command line flags. The operator private key may be read
+//! from the file named by `eth_sender.sender.operator_private_key_file`, so that it doesn't
+//! have to be stored in the config file.
+
+use super::Settings;
+use crate::config::configs::eth_client::DEFAULT_GAS_LIMIT_MULTIPLIER;
+use anyhow::{bail, Context};
+use config_rs::{Config, Environment, File};
+use std::env;
+
+/// Prefix of the environment variables overriding the settings.
+pub const ENV_PREFIX: &str = "ETH_WATCH_";
+/// Separator of the path segments in the environment variables names.
+pub const ENV_SEPARATOR: &str = "__";
+/// Variable naming the config file, used if no `--config` flag is provided.
+pub const CONFIG_FILE_VAR: &str = "CONFIG_FILE";
+
+const WEB3_URL_KEY: &str = "eth_client.web3_url";
+const PRIVATE_KEY_KEY: &str = "eth_sender.sender.operator_private_key";
+const PRIVATE_KEY_FILE_KEY: &str = "eth_sender.sender.operator_private_key_file";
+
+/// Settings provided via the command line.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct CliArgs {
+    /// Path to the config file.
+    pub config_file: Option<String>,
+    /// Overridden fields as `(path, value)` pairs, in the order of appearance.
+    pub overrides: Vec<(String, String)>,
+}
+
+impl CliArgs {
+    /// Parses the command line arguments, excluding the program name.
+    ///
+    /// Accepted forms are `--config <path>`, `--<path> <value>` and `--<path>=<value>`.
+    pub fn parse(args: impl IntoIterator<Item = String>) -> anyhow::Result<Self> {
+        let mut result = Self::default();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            let flag = match arg.strip_prefix("--") {
+                Some(flag) if !flag.is_empty() => flag,
+                _ => bail!("Unexpected argument '{}', expected '--<setting>=<value>'", arg),
+            };
+
+            let (key, value) = match flag.find('=') {
+                Some(pos) => (flag[..pos].to_string(), flag[pos + 1..].to_string()),
+                None => {
+                    let value = args.next().with_context(|| format!("Missing value for '--{}'", flag))?;
+                    (flag.to_string(), value)
+                }
+            };
+
+            if key == "config" {
+                result.config_file = Some(value);
+            } else {
+                result.overrides.push((key, value));
+            }
+        }
+
+        Ok(result)
+    }
+}
+
+/// Path to the config file, taken from the `--config` flag or the `CONFIG_FILE` variable.
+pub fn config_file_path() -> Option<String> {
+    let cli = CliArgs::parse(env::args().skip(1)).ok()?;
+    resolve_config_file(&cli)
+}
+
+fn resolve_config_file(cli: &CliArgs) -> Option<String> {
+    cli.config_file.clone().or_else(|| env::var(CONFIG_FILE_VAR).ok())
+}
+
+/// Loads the settings from all the sources, using the process arguments and environment.
+pub fn load_settings() -> anyhow::Result<Settings> {
+    let cli = CliArgs::parse(env::args().skip(1))?;
+    let config_file = resolve_config_file(&cli);
+
+    let mut conf = Config::new();
+    set_defaults(&mut conf)?;
+    if let Some(config_file) = &config_file {
+        conf.merge(File::with_name(config_file))
+            .with_context(|| format!("Unable to read the config file {}", config_file))?;
+    }
+    conf.merge(Environment::with_prefix(ENV_PREFIX).separator(ENV_SEPARATOR))?;
+
+    apply_overrides(conf, &cli.overrides)
+}
+
+/// Defaults for the fields which have a reasonable value for most of the setups.
+fn set_defaults(conf: &mut Config) -> anyhow::Result<()> {
+    conf.set_default("eth_client.gas_price_factor", 1.0)?
+        .set_default("eth_client.gas_limit_multiplier", DEFAULT_GAS_LIMIT_MULTIPLIER)?
+        .set_default("eth_watch.confirmations_for_eth_event", 0i64)?
+        .set_default("eth_watch.eth_node_poll_interval", 300i64)?;
+    Ok(())
+}
+
+/// Defaults for the `eth_sender` section. They are only applied if the section is provided by
+/// any of the sources, since the section is optional.
+fn set_sender_defaults(conf: &mut Config) -> anyhow::Result<()> {
+    conf.set_default("eth_sender.sender.wait_confirmations", 1i64)?
+        .set_default("eth_sender.sender.expected_wait_time_block", 30i64)?
+        .set_default("eth_sender.sender.tx_poll_period", 3i64)?
+        .set_default("eth_sender.sender.max_txs_in_flight", 3i64)?
+        .set_default("eth_sender.sender.is_enabled", true)?
+        .set_default("eth_sender.gas_price_limit.default", 400_000_000_000i64)?
+        .set_default("eth_sender.gas_price_limit.update_interval", 150i64)?
+        .set_default("eth_sender.gas_price_limit.sample_interval", 15i64)?
+        .set_default("eth_sender.gas_price_limit.scale_factor", 1.0)?;
+    Ok(())
+}
+
+/// Applies the command line overrides on top of the already merged sources and
+/// converts the result into the `Settings`.
+fn apply_overrides(mut conf: Config, overrides: &[(String, String)]) -> anyhow::Result<Settings> {
+    for (key, value) in overrides {
+        conf.set(key, value.as_str())
+            .with_context(|| format!("Invalid command line setting '{}'", key))?;
+    }
+
+    // Lists can't be expressed by a single environment variable or flag, so they are comma-separated there.
+    if let Ok(urls) = conf.get_str(WEB3_URL_KEY) {
+        let urls: Vec<String> = urls
+            .split(',')
+            .map(str::trim)
+            .filter(|url| !url.is_empty())
+            .map(String::from)
+            .collect();
+        conf.set(WEB3_URL_KEY, urls)?;
+    }
+
+    if let Ok(key_file) = conf.get_str(PRIVATE_KEY_FILE_KEY) {
+        let private_key =
+            std::fs::read_to_string(&key_file).with_context(|| format!("Unable to read the operator private key from {}", key_file))?;
+        conf.set(PRIVATE_KEY_KEY, private_key.trim())?;
+    }
+
+    if conf.get_table("eth_sender").is_ok() {
+        set_sender_defaults(&mut conf)?;
+    }
+
+    conf.try_into().context("Invalid settings")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use config_rs::FileFormat;
+
+    const CONFIG: &str = r#"
+contracts:
+  contract_addr: "0x5FbDB2315678afecb367f032d93F642f64180aa3"
+eth_client:
+  chain_id: 9
+  web3_url:
+    - "http://127.0.0.1:8545"
+eth_watch:
+  confirmations_for_eth_event: 5
+"#;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_cli_args() {
+        let cli = CliArgs::parse(args(&[
+            "--config",
+            "config.yaml",
+            "--eth_client.chain_id=5",
+            "--eth_watch.state_file",
+            "state.json",
+        ]))
+        .unwrap();
+        assert_eq!(cli.config_file.as_deref(), Some("config.yaml"));
+        assert_eq!(
+            cli.overrides,
+            vec![
+                ("eth_client.chain_id".to_string(), "5".to_string()),
+                ("eth_watch.state_file".to_string(), "state.json".to_string()),
+            ]
+        );
+
+        assert!(CliArgs::parse(args(&["config.yaml"])).is_err());
+        assert!(CliArgs::parse(args(&["--eth_client.chain_id"])).is_err());
+    }
+
+    #[test]
+    fn test_layering() {
+        let key_file = env::temp_dir().join(format!("eth_watch_test_key_{}", std::process::id()));
+        std::fs::write(&key_file, "0x27593fea79697e947890ecbecce7901b0008345e5d7259710d0dd5e500d040be\n").unwrap();
+
+        let mut conf = Config::new();
+        set_defaults(&mut conf).unwrap();
+        conf.merge(File::from_str(CONFIG, FileFormat::Yaml)).unwrap();
+        let overrides = vec![
+            ("eth_client.chain_id".to_string(), "1".to_string()),
+            ("eth_client.web3_url".to_string(), "http://a:8545, http://b:8545".to_string()),
+            (
+                "eth_sender.sender.operator_commit_eth_addr".to_string(),
+                "0xde03a0B5963f75f1C8485B355fF6D30f3093BDE7".to_string(),
+            ),
+            (PRIVATE_KEY_FILE_KEY.to_string(), key_file.to_string_lossy().into_owned()),
+        ];
+        let settings = apply_overrides(conf, &overrides).unwrap();
+        std::fs::remove_file(&key_file).unwrap();
+
+        // Overridden by the flags.
+        assert_eq!(settings.eth_client.chain_id, 1);
+        assert_eq!(settings.eth_client.web3_url, vec!["http://a:8545", "http://b:8545"]);
+        // Taken from the file.
+        assert_eq!(settings.eth_watch.confirmations_for_eth_event, 5);
+        // Taken from the defaults.
+        assert_eq!(settings.eth_watch.eth_node_poll_interval, 300);
+        let sender = &settings.eth_sender.as_ref().unwrap().sender;
+        assert_eq!(sender.max_txs_in_flight, 3);
+        // Read from the key file, and not exposed by the debug output.
+        assert_eq!(
+            format!("{:?}", sender.operator_private_key.unwrap()),
+            "0x27593fea79697e947890ecbecce7901b0008345e5d7259710d0dd5e500d040be"
+        );
+        assert!(!format!("{:?}", settings).contains("27593fea"));
+    }
+
+    #[test]
+    fn test_eth_sender_is_optional() {
+        let mut conf = Config::new();
+        set_defaults(&mut conf).unwrap();
+        conf.merge(File::from_str(CONFIG, FileFormat::Yaml)).unwrap();
+
+        let settings = apply_overrides(conf, &[]).unwrap();
+        assert!(settings.eth_sender.is_none());
+    }
+}
@@ -1,13 +1,29 @@
 use serde::Deserialize;
 
 pub use crate::config::configs::{ContractsConfig, ETHClientConfig, ETHSenderConfig, ETHWatchConfig};
+pub use crate::config::loader::{load_settings, CliArgs};
+pub use crate::config::validation::ValidationError;
 
 pub mod configs;
+pub mod loader;
+pub mod validation;
 
 #[derive(Debug, Deserialize, Clone, PartialEq)]
 pub struct Settings {
     pub contracts: ContractsConfig,
     pub eth_client: ETHClientConfig,
-    pub eth_sender: ETHSenderConfig,
+    /// Only required by the components which send transactions, the watcher itself is read-only.
+    #[serde(default)]
+    pub eth_sender: Option<ETHSenderConfig>,
     pub eth_watch: ETHWatchConfig,
+    /// Maximum level of the log messages (e.g. `info` or `debug`), can be changed without restart.
+    #[serde(default)]
+    pub log_level: Option<String>,
+}
+
+impl Settings {
+    /// Parsed `log_level`, `None` if it's not set or is invalid.
+    pub fn log_level_filter(&self) -> Option<log::LevelFilter> {
+        self.log_level.as_deref().and_then(|level| level.parse().ok())
+    }
 }
@@ -0,0 +1,321 @@
+//! Semantic checks of the `Settings`, performed before the watcher starts.
+
+use super::configs::eth_sender::{RemoteSignerKind, SignerConfig};
+use super::Settings;
+use crate::contracts::fluidex_contract;
+use crate::types::{Address, U256};
+use std::fmt;
+use std::time::Duration;
+use tokio::time;
+
+/// Time given to every Ethereum node to answer a single probe request.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// All the problems found in the settings.
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub struct ValidationError {
+    pub problems: Vec<String>,
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid settings:")?;
+        for problem in &self.problems {
+            write!(f, "\n  - {}", problem)?;
+        }
+        Ok(())
+    }
+}
+
+fn into_result(problems: Vec<String>) -> Result<(), ValidationError> {
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(ValidationError { problems })
+    }
+}
+
+impl Settings {
+    /// Checks the values of the fields, without accessing the network.
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        into_result(self.field_problems())
+    }
+
+    /// Checks the values of the fields, and then probes every Ethereum node from `eth_client.web3_url`
+    /// to make sure it serves the configured chain and the Fluidex contract is deployed there.
+    pub async fn validate_with_endpoints(&self) -> Result<(), ValidationError> {
+        let mut problems = self.field_problems();
+        for web3_url in &self.eth_client.web3_url {
+            problems.extend(self.endpoint_problems(web3_url).await);
+        }
+        into_result(problems)
+    }
+
+    fn field_problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        let mut check = |ok: bool, problem: &str| {
+            if !ok {
+                problems.push(problem.to_string());
+            }
+        };
+
+        check(
+            self.contracts.contract_addr != Address::zero(),
+            "contracts.contract_addr must not be zero",
+        );
+
+        let eth_client = &self.eth_client;
+        check(eth_client.chain_id != 0, "eth_client.chain_id must not be zero");
+        check(
+            eth_client.gas_price_factor.is_finite() && eth_client.gas_price_factor > 0.0,
+            "eth_client.gas_price_factor must be a positive number",
+        );
+        check(
+            eth_client.gas_limit_multiplier.is_finite() && eth_client.gas_limit_multiplier >= 1.0,
+            "eth_client.gas_limit_multiplier must be at least 1",
+        );
+        // The contract ABI is only loaded if needed, since reading it may fail.
+        let contract = (!eth_client.default_gas_limits.is_empty()).then(fluidex_contract);
+        for (name, gas_limit) in &eth_client.default_gas_limits {
+            check(
+                contract.as_ref().map_or(false, |contract| contract.function(name).is_ok()),
+                &format!("eth_client.default_gas_limits.{} is not a function of the Fluidex contract", name),
+            );
+            check(*gas_limit > 0, &format!("eth_client.default_gas_limits.{} must not be zero", name));
+        }
+        check(!eth_client.web3_url.is_empty(), "eth_client.web3_url must contain at least one URL");
+        for web3_url in &eth_client.web3_url {
+            check(
+                web3_url.starts_with("http://") || web3_url.starts_with("https://"),
+                &format!("eth_client.web3_url '{}' must be an HTTP(S) URL", web3_url),
+            );
+        }
+
+        if let Some(eth_sender) = &self.eth_sender {
+            let sender = &eth_sender.sender;
+            match &sender.signer {
+                SignerConfig::PrivateKey => check(
+                    sender.operator_private_key.is_some(),
+                    "eth_sender.sender.operator_private_key must be set for the private_key signer",
+                ),
+                SignerConfig::Keystore { path, .. } => check(!path.is_empty(), "eth_sender.sender.signer.path must not be empty"),
+                SignerConfig::JsonRpc { url, .. } => check(
+                    url.starts_with("http://") || url.starts_with("https://"),
+                    "eth_sender.sender.signer.url must be an HTTP(S) URL",
+                ),
+                SignerConfig::Remote(remote) => {
+                    check(
+                        remote.url.starts_with("http://") || remote.url.starts_with("https://"),
+                        "eth_sender.sender.signer.url must be an HTTP(S) URL",
+                    );
+                    check(
+                        remote.api != RemoteSignerKind::Web3signer || remote.public_key.is_some(),
+                        "eth_sender.sender.signer.public_key must be set for Web3Signer",
+                    );
+                    check(
+                        remote.request_timeout > 0,
+                        "eth_sender.sender.signer.request_timeout must be positive",
+                    );
+                }
+            }
+            check(
+                sender.wait_confirmations > 0,
+                "eth_sender.sender.wait_confirmations must be positive",
+            );
+            check(
+                sender.expected_wait_time_block > 0,
+                "eth_sender.sender.expected_wait_time_block must be positive",
+            );
+            check(sender.tx_poll_period > 0, "eth_sender.sender.tx_poll_period must be positive");
+            check(sender.max_txs_in_flight > 0, "eth_sender.sender.max_txs_in_flight must be positive");
+            check(
+                sender.journal_file.as_ref().map_or(true, |journal_file| !journal_file.is_empty()),
+                "eth_sender.sender.journal_file must not be empty",
+            );
+
+            let gas_price_limit = &eth_sender.gas_price_limit;
+            check(gas_price_limit.default > 0, "eth_sender.gas_price_limit.default must be positive");
+            check(
+                gas_price_limit.update_interval > 0,
+                "eth_sender.gas_price_limit.update_interval must be positive",
+            );
+            check(
+                gas_price_limit.sample_interval > 0,
+                "eth_sender.gas_price_limit.sample_interval must be positive",
+            );
+            check(
+                gas_price_limit.sample_interval <= gas_price_limit.update_interval,
+                "eth_sender.gas_price_limit.sample_interval must not exceed update_interval",
+            );
+            check(
+                gas_price_limit.scale_factor.is_finite() && gas_price_limit.scale_factor > 0.0,
+                "eth_sender.gas_price_limit.scale_factor must be a positive number",
+            );
+        }
+
+        let eth_watch = &self.eth_watch;
+        check(
+            eth_watch.eth_node_poll_interval > 0,
+            "eth_watch.eth_node_poll_interval must be positive",
+        );
+        check(
+            eth_watch.max_blocks_per_batch > 0,
+            "eth_watch.max_blocks_per_batch must be positive",
+        );
+        check(
+            eth_watch.state_file.as_ref().map_or(true, |state_file| !state_file.is_empty()),
+            "eth_watch.state_file must not be empty",
+        );
+        check(
+            eth_watch.token_registry_file.as_ref().map_or(true, |file| !file.is_empty()),
+            "eth_watch.token_registry_file must not be empty",
+        );
+
+        check(
+            self.log_level.is_none() || self.log_level_filter().is_some(),
+            "log_level must be one of off, error, warn, info, debug, trace",
+        );
+
+        problems
+    }
+
+    /// Checks that the running watcher can switch to the `new` settings without restart.
+    pub fn check_reload(&self, new: &Settings) -> Result<(), ValidationError> {
+        let mut problems = Vec::new();
+        // The watcher state describes the operations of a particular contract on a particular chain.
+        if new.contracts.contract_addr != self.contracts.contract_addr {
+            problems.push(format!(
+                "contracts.contract_addr can't be changed from {:?} to {:?} without restart",
+                self.contracts.contract_addr, new.contracts.contract_addr
+            ));
+        }
+        if new.eth_client.chain_id != self.eth_client.chain_id {
+            problems.push(format!(
+                "eth_client.chain_id can't be changed from {} to {} without restart",
+                self.eth_client.chain_id, new.eth_client.chain_id
+            ));
+        }
+        into_result(problems)
+    }
+
+    async fn endpoint_problems(&self, web3_url: &str) -> Vec<String> {
+        let transport = match web3::transports::Http::new(web3_url) {
+            Ok(transport) => transport,
+            // The malformed URL is already reported by the field checks.
+            Err(_) => return Vec::new(),
+        };
+        let eth = web3::Web3::new(transport).eth();
+        let mut problems = Vec::new();
+
+        match time::timeout(PROBE_TIMEOUT, eth.chain_id()).await {
+            Ok(Ok(chain_id)) if chain_id == U256::from(self.eth_client.chain_id) => {}
+            Ok(Ok(chain_id)) => problems.push(format!(
+                "{} serves chain {}, while eth_client.chain_id is {}",
+                web3_url, chain_id, self.eth_client.chain_id
+            )),
+            Ok(Err(error)) => problems.push(format!("{} failed to answer eth_chainId: {}", web3_url, error)),
+            Err(_) => problems.push(format!("{} didn't answer eth_chainId in time", web3_url)),
+        }
+
+        let contract_addr = self.contracts.contract_addr;
+        match time::timeout(PROBE_TIMEOUT, eth.code(contract_addr, None)).await {
+            Ok(Ok(code)) if !code.0.is_empty() => {}
+            Ok(Ok(_)) => problems.push(format!("{} has no contract deployed at {:?}", web3_url, contract_addr)),
+            Ok(Err(error)) => problems.push(format!("{} failed to answer eth_getCode: {}", web3_url, error)),
+            Err(_) => problems.push(format!("{} didn't answer eth_getCode in time", web3_url)),
+        }
+
+        problems
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::configs::eth_sender::{GasLimit, Sender, SignerConfig};
+    use crate::config::{ContractsConfig, ETHClientConfig, ETHSenderConfig, ETHWatchConfig};
+    use crate::types::H256;
+
+    fn settings() -> Settings {
+        Settings {
+            contracts: ContractsConfig {
+                contract_addr: Address::from_low_u64_be(1),
+            },
+            eth_client: ETHClientConfig {
+                chain_id: 9,
+                gas_price_factor: 1.0,
+                web3_url: vec!["http://127.0.0.1:8545".into()],
+                transaction_type: Default::default(),
+                gas_limit_multiplier: 1.2,
+                default_gas_limits: Default::default(),
+                batch_requests: false,
+            },
+            eth_sender: Some(ETHSenderConfig {
+                sender: Sender {
+                    signer: SignerConfig::PrivateKey,
+                    operator_private_key: Some(H256::from_low_u64_be(1)),
+                    operator_commit_eth_addr: Address::from_low_u64_be(2),
+                    wait_confirmations: 1,
+                    expected_wait_time_block: 30,
+                    tx_poll_period: 3,
+                    max_txs_in_flight: 3,
+                    is_enabled: true,
+                    journal_file: None,
+                },
+                gas_price_limit: GasLimit {
+                    default: 400_000_000_000,
+                    update_interval: 150,
+                    sample_interval: 15,
+                    scale_factor: 1.0,
+                },
+            }),
+            eth_watch: ETHWatchConfig {
+                confirmations_for_eth_event: 0,
+                eth_node_poll_interval: 300,
+                immediate_repoll_threshold: 16,
+                max_blocks_per_batch: 1000,
+                state_file: None,
+                token_registry_file: None,
+            },
+            log_level: None,
+        }
+    }
+
+    #[test]
+    fn test_reports_all_problems() {
+        assert_eq!(settings().validate(), Ok(()));
+
+        let mut settings = settings();
+        settings.eth_client.web3_url.clear();
+        settings.eth_client.gas_limit_multiplier = 0.5;
+        settings.eth_watch.eth_node_poll_interval = 0;
+        settings.eth_sender.as_mut().unwrap().gas_price_limit.scale_factor = f64::NAN;
+
+        let error = settings.validate().unwrap_err();
+        assert_eq!(
+            error.problems,
+            vec![
+                "eth_client.gas_limit_multiplier must be at least 1",
+                "eth_client.web3_url must contain at least one URL",
+                "eth_sender.gas_price_limit.scale_factor must be a positive number",
+                "eth_watch.eth_node_poll_interval must be positive",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rejects_unsafe_reload() {
+        let current = settings();
+
+        let mut new = settings();
+        new.eth_client.web3_url.push("http://127.0.0.1:8546".into());
+        new.eth_watch.eth_node_poll_interval = 1000;
+        new.log_level = Some("debug".into());
+        assert_eq!(current.check_reload(&new), Ok(()));
+
+        new.contracts.contract_addr = Address::from_low_u64_be(2);
+        let error = current.check_reload(&new).unwrap_err();
+        assert_eq!(error.problems.len(), 1);
+        assert!(error.problems[0].starts_with("contracts.contract_addr can't be changed"));
+    }
+}
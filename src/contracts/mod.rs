@@ -1,10 +1,16 @@
-use ethabi::Contract;
+use ethabi::{Contract, Param, ParamType};
+use parity_crypto::Keccak256;
+use serde::Deserialize;
 use std::fs;
 use std::io;
 use std::str::FromStr;
 
 const FLUIDEX_CONTRACT_FILE: &str = "contracts/artifacts/contracts/Fluidex.sol/Fluidex.json";
 
+/// ABI entries understood by `ethabi`, which fails to load the ABI with any other entries
+/// (e.g. the custom errors of Solidity 0.8).
+const ETHABI_ENTRY_TYPES: [&str; 4] = ["constructor", "function", "event", "fallback"];
+
 fn read_file_to_json_value(path: &str) -> io::Result<serde_json::Value> {
     let fluidex_home = std::env::var("FLUIDEX_HOME").unwrap_or_else(|_| ".".into());
     let path = std::path::Path::new(&fluidex_home).join(path);
@@ -13,11 +19,59 @@ fn read_file_to_json_value(path: &str) -> io::Result<serde_json::Value> {
     Ok(val)
 }
 
-pub fn fluidex_contract() -> Contract {
-    let abi_string = read_file_to_json_value(FLUIDEX_CONTRACT_FILE)
+fn fluidex_abi_entries() -> Vec<serde_json::Value> {
+    read_file_to_json_value(FLUIDEX_CONTRACT_FILE)
         .expect("couldn't read FLUIDEX_CONTRACT_FILE")
         .get("abi")
+        .and_then(serde_json::Value::as_array)
+        .cloned()
         .expect("couldn't get abi from FLUIDEX_CONTRACT_FILE")
-        .to_string();
+}
+
+fn entry_type(entry: &serde_json::Value) -> &str {
+    entry.get("type").and_then(serde_json::Value::as_str).unwrap_or_default()
+}
+
+pub fn fluidex_contract() -> Contract {
+    let entries: Vec<_> = fluidex_abi_entries()
+        .into_iter()
+        .filter(|entry| ETHABI_ENTRY_TYPES.contains(&entry_type(entry)))
+        .collect();
+    let abi_string = serde_json::Value::Array(entries).to_string();
     Contract::load(abi_string.as_bytes()).expect("fluidex contract abi")
 }
+
+/// Custom error declared by the contract, e.g. `error InvalidBlock(uint256 blockId)`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ContractError {
+    pub name: String,
+    pub inputs: Vec<Param>,
+}
+
+impl ContractError {
+    pub fn param_types(&self) -> Vec<ParamType> {
+        self.inputs.iter().map(|param| param.kind.clone()).collect()
+    }
+
+    /// First 4 bytes of the keccak256 hash of the error signature, which the revert data starts with.
+    pub fn selector(&self) -> [u8; 4] {
+        let types: Vec<String> = self
+            .inputs
+            .iter()
+            .map(|param| ethabi::param_type::Writer::write(&param.kind))
+            .collect();
+        let hash = format!("{}({})", self.name, types.join(",")).as_bytes().keccak256();
+        let mut selector = [0u8; 4];
+        selector.copy_from_slice(&hash[..4]);
+        selector
+    }
+}
+
+/// Custom errors of the Fluidex contract.
+pub fn fluidex_errors() -> Vec<ContractError> {
+    fluidex_abi_entries()
+        .into_iter()
+        .filter(|entry| entry_type(entry) == "error")
+        .map(|entry| serde_json::from_value(entry).expect("fluidex contract error abi"))
+        .collect()
+}
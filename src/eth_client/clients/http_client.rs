@@ -1,37 +1,152 @@
+use crate::config::configs::eth_client::DEFAULT_GAS_LIMIT_MULTIPLIER;
+use crate::contracts::ContractError;
 use crate::eth_client::ethereum_gateway::{ExecutedTxStatus, FailureInfo, SignedCallResult};
-use crate::eth_signer::{raw_ethereum_tx::RawTransaction, EthereumSigner};
+use crate::eth_client::nonce_manager::{NonceManager, SendOutcome};
+use crate::eth_signer::{
+    raw_ethereum_tx::{AccessListItem, RawTransaction, TransactionType},
+    EthereumSigner, ReadOnly,
+};
+use futures::future::{join_all, try_join_all};
+use serde::Deserialize;
+use std::collections::HashMap;
 use std::fmt;
+use std::future::Future;
 // use std::time::Instant;
 use web3::{
     contract::{
         tokens::{Detokenize, Tokenize},
         Contract, Options,
     },
-    transports::Http,
-    types::{Address, BlockId, BlockNumber, Bytes, Filter, Log, TransactionReceipt, H160, H256, U256, U64},
-    Web3,
+    transports::{Batch, Http},
+    types::{Address, BlockId, BlockNumber, Bytes, CallRequest, Filter, Log, TransactionReceipt, H160, H256, U256, U64},
+    Transport, Web3,
 };
 
 /// Gas limit value to be used in transaction if for some reason
-/// gas limit was not set for it.
+/// gas limit was neither set nor estimated for it.
 ///
 /// This is an emergency value, which will not be used normally.
 const FALLBACK_GAS_LIMIT: u64 = 3_000_000;
 
+/// Amount of the latest blocks used to estimate the EIP-1559 fees.
+const FEE_HISTORY_BLOCKS: u64 = 10;
+/// Percentile of the priority fees paid in the latest blocks, which is offered by our transactions.
+const FEE_HISTORY_REWARD_PERCENTILE: f64 = 50.0;
+/// Priority fee used if the latest blocks contain no transactions to learn it from.
+const FALLBACK_PRIORITY_FEE_PER_GAS: u64 = 1_000_000_000;
+
+/// Minimal increase of the fees of the transaction replacing a pending one, accepted by the nodes.
+const REPLACEMENT_FEE_BUMP_PERCENT: u64 = 10;
+
+/// The lowest fee of the replacement transaction, which is accepted instead of the transaction with `fee`.
+pub fn min_replacement_fee(fee: U256) -> U256 {
+    let bump = (fee * REPLACEMENT_FEE_BUMP_PERCENT + 99) / 100;
+    fee + bump.max(U256::one())
+}
+
+/// Revert data of the reverted `eth_call` or `eth_estimateGas`, attached to the error by the node.
+/// Empty if the node reports the revert without the data.
+pub(crate) fn revert_data(error: &web3::Error) -> Option<Vec<u8>> {
+    let rpc_error = match error {
+        web3::Error::Rpc(rpc_error) => rpc_error,
+        _ => return None,
+    };
+    let data = rpc_error.data.as_ref().and_then(|data| data.as_str()).unwrap_or_default();
+    match hex::decode(data.strip_prefix("0x").unwrap_or(data)) {
+        Ok(revert_data) if !revert_data.is_empty() => Some(revert_data),
+        _ if rpc_error.message.contains("revert") => Some(Vec::new()),
+        _ => None,
+    }
+}
+
+/// Status of the transaction given its receipt and the current block number.
+/// `None` if the transaction is not executed yet.
+fn tx_status(receipt: TransactionReceipt, block_number: U64) -> Option<ExecutedTxStatus> {
+    let (tx_block_number, status) = (receipt.block_number?, receipt.status?);
+    let confirmations = block_number.saturating_sub(tx_block_number).as_u64();
+    let success = status.as_u64() == 1;
+
+    // Set the receipt only for failures.
+    let receipt = if success { None } else { Some(receipt) };
+
+    Some(ExecutedTxStatus {
+        confirmations,
+        success,
+        receipt,
+    })
+}
+
+/// Response of the `eth_feeHistory` method.
+#[derive(Debug, Deserialize)]
+struct FeeHistory {
+    /// Base fees of the requested blocks, followed by the base fee of the next block.
+    #[serde(rename = "baseFeePerGas")]
+    base_fee_per_gas: Vec<U256>,
+    /// Priority fees of the requested percentiles, for every requested block.
+    #[serde(default)]
+    reward: Vec<Vec<U256>>,
+}
+
+/// Response of the `eth_createAccessList` method.
+#[derive(Debug, Deserialize)]
+struct AccessListWithGasUsed {
+    #[serde(rename = "accessList")]
+    access_list: Vec<AccessListItem>,
+    /// Set if the transaction reverts, in which case the access list is incomplete.
+    #[serde(default)]
+    error: Option<String>,
+}
+
+/// Fees of the EIP-1559 transaction.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Eip1559Fees {
+    pub max_fee_per_gas: U256,
+    pub max_priority_fee_per_gas: U256,
+}
+
+impl Eip1559Fees {
+    /// Applies the max fee per gas set by the caller and the lowest priority fee accepted for the replacement
+    /// transaction. The nodes only accept the replacement with both fees bumped, so the max fee is raised
+    /// to fit the bumped priority fee rather than the priority fee being capped below it.
+    fn constrained(mut self, max_fee_per_gas: Option<U256>, min_priority_fee: Option<U256>) -> Self {
+        let min_priority_fee = min_priority_fee.unwrap_or_default();
+        self.max_fee_per_gas = max_fee_per_gas.unwrap_or(self.max_fee_per_gas).max(min_priority_fee);
+        self.max_priority_fee_per_gas = self.max_priority_fee_per_gas.max(min_priority_fee).min(self.max_fee_per_gas);
+        self
+    }
+}
+
+/// Client interacting with a single Ethereum node.
+///
+/// Methods which sign or send transactions on behalf of the operator account are only
+/// available if `S` is an `EthereumSigner`, so a client created with `new_read_only`
+/// can't be used to send anything.
 #[derive(Clone)]
-pub struct ETHDirectClient<S: EthereumSigner> {
+pub struct ETHDirectClient<S> {
     eth_signer: S,
+    /// Operator account. Not used by the read-only clients.
     sender_account: Address,
     pub contract_addr: H160,
     contract: ethabi::Contract,
-    pub chain_id: u8,
+    pub chain_id: u64,
     pub gas_price_factor: f64,
+    pub transaction_type: TransactionType,
+    /// Allocates the nonces locally if set, otherwise they are requested from the node.
+    nonce_manager: Option<NonceManager>,
+    /// The estimated gas limits are multiplied by this factor.
+    pub gas_limit_multiplier: f64,
+    /// Gas limits used if the estimation fails, by the selectors of the Fluidex contract functions.
+    default_gas_limits: HashMap<[u8; 4], U256>,
+    /// Custom errors of the contract, used to decode the revert data.
+    contract_errors: Vec<ContractError>,
+    /// Whether the independent requests are sent in a single JSON-RPC batch.
+    pub batch_requests: bool,
     // It's public only for testkit
     // TODO avoid public (ZKS-376)
     pub web3: Web3<Http>,
 }
 
-impl<S: EthereumSigner> fmt::Debug for ETHDirectClient<S> {
+impl<S> fmt::Debug for ETHDirectClient<S> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         // We do not want to have a private key in the debug representation.
 
@@ -40,6 +155,7 @@ impl<S: EthereumSigner> fmt::Debug for ETHDirectClient<S> {
             .field("contract_addr", &self.contract_addr)
             .field("chain_id", &self.chain_id)
             .field("gas_price_factor", &self.gas_price_factor)
+            .field("transaction_type", &self.transaction_type)
             .finish()
     }
 }
@@ -51,7 +167,7 @@ impl<S: EthereumSigner> ETHDirectClient<S> {
         operator_eth_addr: H160,
         eth_signer: S,
         contract_eth_addr: H160,
-        chain_id: u8,
+        chain_id: u64,
         gas_price_factor: f64,
     ) -> Self {
         Self {
@@ -61,16 +177,46 @@ impl<S: EthereumSigner> ETHDirectClient<S> {
             chain_id,
             contract,
             gas_price_factor,
+            transaction_type: TransactionType::Legacy,
+            nonce_manager: None,
+            gas_limit_multiplier: DEFAULT_GAS_LIMIT_MULTIPLIER,
+            default_gas_limits: HashMap::new(),
+            contract_errors: Vec::new(),
+            batch_requests: false,
             web3: Web3::new(transport),
         }
     }
 
-    pub fn main_contract_with_address(&self, address: Address) -> Contract<Http> {
-        Contract::new(self.web3.eth(), address, self.contract.clone())
+    /// Sets the type of the signed transactions.
+    pub fn with_transaction_type(mut self, transaction_type: TransactionType) -> Self {
+        self.transaction_type = transaction_type;
+        self
     }
 
-    pub fn main_contract(&self) -> Contract<Http> {
-        self.main_contract_with_address(self.contract_addr)
+    /// Allocates the nonces of the transactions signed without the explicit nonce with the manager,
+    /// instead of requesting the pending nonce from the node.
+    pub fn with_nonce_manager(mut self, nonce_manager: NonceManager) -> Self {
+        self.nonce_manager = Some(nonce_manager);
+        self
+    }
+
+    pub fn nonce_manager(&self) -> Option<&NonceManager> {
+        self.nonce_manager.as_ref()
+    }
+
+    /// Sets the multiplier of the estimated gas limits, and the limits of the Fluidex contract functions
+    /// (by name) used when the estimation fails.
+    pub fn with_gas_limits(mut self, gas_limit_multiplier: f64, default_gas_limits: &HashMap<String, u64>) -> Self {
+        self.gas_limit_multiplier = gas_limit_multiplier;
+        for (name, gas_limit) in default_gas_limits {
+            match self.contract.function(name) {
+                Ok(function) => {
+                    self.default_gas_limits.insert(function.short_signature(), (*gas_limit).into());
+                }
+                Err(_) => log::warn!("Gas limit is set for the unknown contract function {}", name),
+            }
+        }
+        self
     }
 
     pub async fn pending_nonce(&self) -> Result<U256, anyhow::Error> {
@@ -95,58 +241,121 @@ impl<S: EthereumSigner> ETHDirectClient<S> {
         Ok(nonce)
     }
 
-    pub async fn block_number(&self) -> Result<U64, anyhow::Error> {
-        // let start = Instant::now();
-        let block_number = self.web3.eth().block_number().await?;
-        // metrics::histogram!("eth_client.direct.current_nonce", start.elapsed());
-        Ok(block_number)
+    pub async fn sender_eth_balance(&self) -> Result<U256, anyhow::Error> {
+        self.eth_balance(self.sender_account).await
     }
 
-    pub async fn get_gas_price(&self) -> Result<U256, anyhow::Error> {
+    pub async fn allowance(&self, token_address: Address, erc20_abi: ethabi::Contract) -> Result<U256, anyhow::Error> {
         // let start = Instant::now();
-        let mut network_gas_price = self.web3.eth().gas_price().await?;
-        let percent_gas_price_factor = U256::from((self.gas_price_factor * 100.0).round() as u64);
-        network_gas_price = (network_gas_price * percent_gas_price_factor) / U256::from(100);
-        // metrics::histogram!("eth_client.direct.get_gas_price", start.elapsed());
-        Ok(network_gas_price)
+        let res = self
+            .call_contract_function(
+                "allowance",
+                (self.sender_account, self.contract_addr),
+                None,
+                Options::default(),
+                None,
+                token_address,
+                erc20_abi,
+            )
+            .await?;
+        // metrics::histogram!("eth_client.direct.allowance", start.elapsed());
+        Ok(res)
     }
 
     pub async fn sign_prepared_tx(&self, data: Vec<u8>, options: Options) -> Result<SignedCallResult, anyhow::Error> {
         self.sign_prepared_tx_for_addr(data, self.contract_addr, options).await
     }
 
+    /// Signs the transaction of the configured type.
+    ///
+    /// For the EIP-1559 transactions, `options.gas_price` (if set) is used as the max fee per gas,
+    /// and the fees are otherwise suggested by `eth_feeHistory`. The access lists of the typed
+    /// transactions sent to the Fluidex contract are populated with `eth_createAccessList`.
     pub async fn sign_prepared_tx_for_addr(
         &self,
         data: Vec<u8>,
         contract_addr: H160,
         options: Options,
+    ) -> Result<SignedCallResult, anyhow::Error> {
+        self.sign_tx(data, contract_addr, options, None).await
+    }
+
+    /// Signs the transaction replacing the `replaced` one sent to the Fluidex contract: with the same
+    /// nonce and, for the EIP-1559 transactions, the priority fee bumped enough for the nodes to accept it.
+    ///
+    /// The bumped gas price (max fee per gas) is expected to be set in `options`.
+    pub async fn sign_replacement_tx(
+        &self,
+        data: Vec<u8>,
+        mut options: Options,
+        replaced: &SignedCallResult,
+    ) -> Result<SignedCallResult, anyhow::Error> {
+        options.nonce = Some(replaced.nonce);
+        let min_priority_fee = replaced.max_priority_fee_per_gas.map(min_replacement_fee);
+        self.sign_tx(data, self.contract_addr, options, min_priority_fee).await
+    }
+
+    async fn sign_tx(
+        &self,
+        data: Vec<u8>,
+        contract_addr: H160,
+        options: Options,
+        min_priority_fee: Option<U256>,
     ) -> Result<SignedCallResult, anyhow::Error> {
         // fetch current gas_price
         // let start = Instant::now();
 
-        let gas_price = match options.gas_price {
-            Some(gas_price) => gas_price,
-            None => self.get_gas_price().await?,
+        let (gas_price, eip1559_fees) = match self.transaction_type {
+            TransactionType::Legacy | TransactionType::Eip2930 => {
+                let gas_price = match options.gas_price {
+                    Some(gas_price) => gas_price,
+                    None => self.get_gas_price().await?,
+                };
+                (gas_price, None)
+            }
+            TransactionType::Eip1559 => {
+                let fees = self.suggest_eip1559_fees().await?.constrained(options.gas_price, min_priority_fee);
+                (fees.max_fee_per_gas, Some(fees))
+            }
         };
 
-        let nonce = match options.nonce {
-            Some(nonce) => nonce,
-            None => self.pending_nonce().await?,
+        let value = options.value.unwrap_or_default();
+        let gas = match options.gas {
+            // The transaction with the set nonce may replace a pending one, and would revert on the pending state
+            // which already includes the replaced transaction.
+            Some(gas) if options.nonce.is_some() => gas,
+            Some(gas) => {
+                // The estimation executes the transaction as well, so the dry run is only needed with the set limit.
+                if let Some(failure) = self.dry_run(contract_addr, &data, value, Some(gas)).await? {
+                    return Err(failure.into());
+                }
+                gas
+            }
+            None => self.estimate_gas_limit(contract_addr, &data, value).await?,
         };
 
-        let gas = match options.gas {
-            Some(gas) => gas,
-            None => {
-                // Verbosity level is set to `error`, since we expect all the transactions to have
-                // a set limit, but don't want to crush the application if for some reason in some
-                // place limit was not set.
-                log::error!(
-                    "No gas limit was set for transaction, using the default limit: {}",
-                    FALLBACK_GAS_LIMIT
-                );
+        let access_list = if !self.transaction_type.has_access_list() {
+            None
+        } else if contract_addr == self.contract_addr {
+            let access_list = self
+                .create_access_list(self.sender_account, contract_addr, &data, value)
+                .await
+                .unwrap_or_else(|err| {
+                    // The list only makes the transaction cheaper, so it's fine to send it without one.
+                    log::warn!("Unable to create the access list, sending the transaction without it: {}", err);
+                    Vec::new()
+                });
+            Some(access_list)
+        } else {
+            Some(Vec::new())
+        };
 
-                U256::from(FALLBACK_GAS_LIMIT)
-            }
+        // The nonce is allocated last, so it's only to be released if the signing fails.
+        let allocating_manager = self.nonce_manager.as_ref().filter(|_| options.nonce.is_none());
+        let nonce = match (options.nonce, allocating_manager) {
+            (Some(nonce), _) => nonce,
+            (None, Some(manager)) => manager.allocate(self.current_nonce().await?)?,
+            (None, None) => self.pending_nonce().await?,
         };
 
         // form and sign tx
@@ -154,14 +363,24 @@ impl<S: EthereumSigner> ETHDirectClient<S> {
             chain_id: self.chain_id,
             nonce,
             to: Some(contract_addr),
-            value: options.value.unwrap_or_default(),
+            value,
             gas_price,
             gas,
             data,
+            max_fee_per_gas: eip1559_fees.map(|fees| fees.max_fee_per_gas),
+            max_priority_fee_per_gas: eip1559_fees.map(|fees| fees.max_priority_fee_per_gas),
+            access_list,
         };
 
-        let signed_tx = self.eth_signer.sign_transaction(tx).await?;
-        let hash = self.web3.web3().sha3(Bytes(signed_tx.clone())).await?;
+        let (signed_tx, hash) = match self.sign_and_hash(tx).await {
+            Ok(signed) => signed,
+            Err(err) => {
+                if let Some(manager) = allocating_manager {
+                    manager.release(nonce);
+                }
+                return Err(err);
+            }
+        };
 
         // metrics::histogram!(
         //     "eth_client.direct.sign_prepared_tx_for_addr",
@@ -170,11 +389,85 @@ impl<S: EthereumSigner> ETHDirectClient<S> {
         Ok(SignedCallResult {
             raw_tx: signed_tx,
             gas_price,
+            max_priority_fee_per_gas: eip1559_fees.map(|fees| fees.max_priority_fee_per_gas),
             nonce,
             hash,
         })
     }
 
+    /// Estimates the gas limit of the transaction with `eth_estimateGas`, multiplied by `gas_limit_multiplier`.
+    ///
+    /// Fails with the `FailureInfo` error if the transaction reverts. If the estimation fails for
+    /// another reason, the default limit of the called function is used.
+    async fn estimate_gas_limit(&self, contract_addr: H160, data: &[u8], value: U256) -> Result<U256, anyhow::Error> {
+        let request = self.call_request(contract_addr, data, value, None);
+        let error = match self.web3.eth().estimate_gas(request, Some(BlockNumber::Pending)).await {
+            Ok(estimate) => {
+                let percent_multiplier = U256::from((self.gas_limit_multiplier * 100.0).round() as u64);
+                return Ok(estimate * percent_multiplier / U256::from(100));
+            }
+            Err(error) => error,
+        };
+
+        if let Some(failure) = self.dry_run(contract_addr, data, value, None).await? {
+            return Err(failure.into());
+        }
+        let selector = data.get(..4).filter(|_| contract_addr == self.contract_addr);
+        match selector.and_then(|selector| self.default_gas_limits.get(selector)) {
+            Some(gas_limit) => {
+                log::warn!("Unable to estimate the gas limit, using the default limit {}: {}", gas_limit, error);
+                Ok(*gas_limit)
+            }
+            None => {
+                // Verbosity level is set to `error`, since we expect all the transactions to have
+                // a set or estimated limit, but don't want to crush the application if for some
+                // reason it's not available.
+                log::error!(
+                    "Unable to estimate the gas limit, using the fallback limit {}: {}",
+                    FALLBACK_GAS_LIMIT,
+                    error
+                );
+                Ok(U256::from(FALLBACK_GAS_LIMIT))
+            }
+        }
+    }
+
+    /// Executes the transaction with `eth_call` without sending it, returning the failure
+    /// if it reverts. The call is executed on the pending state, so that the transactions in flight
+    /// are taken into account.
+    pub async fn dry_run(
+        &self,
+        contract_addr: H160,
+        data: &[u8],
+        value: U256,
+        gas: Option<U256>,
+    ) -> Result<Option<FailureInfo>, anyhow::Error> {
+        let request = self.call_request(contract_addr, data, value, gas);
+        match self.web3.eth().call(request, Some(BlockNumber::Pending.into())).await {
+            Ok(_) => Ok(None),
+            Err(error) => match revert_data(&error) {
+                Some(revert_data) => Ok(Some(FailureInfo::from_revert_data(
+                    &revert_data,
+                    &self.contract_errors,
+                    None,
+                    gas.unwrap_or_default(),
+                ))),
+                None => Err(error.into()),
+            },
+        }
+    }
+
+    fn call_request(&self, contract_addr: H160, data: &[u8], value: U256, gas: Option<U256>) -> CallRequest {
+        CallRequest {
+            from: Some(self.sender_account),
+            to: Some(contract_addr),
+            gas,
+            gas_price: None,
+            value: Some(value),
+            data: Some(Bytes(data.to_vec())),
+        }
+    }
+
     pub async fn send_raw_tx(&self, tx: Vec<u8>) -> Result<H256, anyhow::Error> {
         // let start = Instant::now();
         let tx = self.web3.eth().send_raw_transaction(Bytes(tx)).await?;
@@ -182,6 +475,203 @@ impl<S: EthereumSigner> ETHDirectClient<S> {
         Ok(tx)
     }
 
+    /// Sends the signed transaction, keeping the nonce manager (if any) up to date.
+    ///
+    /// The nonce related errors of the node are returned with the `NonceError` context:
+    /// the transaction must then be signed again with a new nonce or a higher fee.
+    pub async fn send_signed_tx(&self, tx: &SignedCallResult) -> Result<H256, anyhow::Error> {
+        let result = self.send_raw_tx(tx.raw_tx.clone()).await;
+        let outcome = SendOutcome::of(tx.nonce, &result);
+        self.complete_send(tx, result, outcome)
+    }
+
+    /// Records the outcome of sending the transaction in the nonce manager (if any).
+    pub(crate) fn complete_send(
+        &self,
+        tx: &SignedCallResult,
+        result: Result<H256, anyhow::Error>,
+        outcome: SendOutcome,
+    ) -> Result<H256, anyhow::Error> {
+        if let Some(manager) = &self.nonce_manager {
+            manager.record_send(tx.nonce, &outcome);
+        }
+        match (result, outcome) {
+            (Ok(hash), _) => Ok(hash),
+            // The node already has the very same transaction, e.g. when it's sent again after a restart.
+            (Err(_), SendOutcome::Accepted) => Ok(tx.hash),
+            (Err(err), SendOutcome::Nonce(nonce_error)) => Err(err.context(nonce_error)),
+            (Err(err), _) => Err(err),
+        }
+    }
+
+    async fn sign_and_hash(&self, tx: RawTransaction) -> Result<(Vec<u8>, H256), anyhow::Error> {
+        let signed_tx = self.eth_signer.sign_transaction(tx).await?;
+        let hash = self.web3.web3().sha3(Bytes(signed_tx.clone())).await?;
+        Ok((signed_tx, hash))
+    }
+}
+
+impl ETHDirectClient<ReadOnly> {
+    /// Creates a client which is only able to read from the Ethereum node.
+    pub fn new_read_only(
+        transport: Http,
+        contract: ethabi::Contract,
+        contract_eth_addr: H160,
+        chain_id: u64,
+        gas_price_factor: f64,
+    ) -> Self {
+        Self {
+            sender_account: Address::zero(),
+            eth_signer: ReadOnly,
+            contract_addr: contract_eth_addr,
+            chain_id,
+            contract,
+            gas_price_factor,
+            transaction_type: TransactionType::Legacy,
+            nonce_manager: None,
+            gas_limit_multiplier: DEFAULT_GAS_LIMIT_MULTIPLIER,
+            default_gas_limits: HashMap::new(),
+            contract_errors: Vec::new(),
+            batch_requests: false,
+            web3: Web3::new(transport),
+        }
+    }
+}
+
+impl<S> ETHDirectClient<S> {
+    /// Sets the custom errors of the contract, so the failure reasons of the transactions include them.
+    pub fn with_contract_errors(mut self, contract_errors: Vec<ContractError>) -> Self {
+        self.contract_errors = contract_errors;
+        self
+    }
+
+    /// Enables sending the independent requests (e.g. the receipts of several transactions)
+    /// in a single JSON-RPC batch, i.e. a single HTTP request.
+    pub fn with_batch_requests(mut self, batch_requests: bool) -> Self {
+        self.batch_requests = batch_requests;
+        self
+    }
+
+    /// Sends the requests issued by `requests` in a single batch, and returns their results in the same order.
+    ///
+    /// The requests are queued by the batch transport as soon as they are issued, and their
+    /// futures are resolved once the batch response is received. The transport matches the responses
+    /// to the requests by their position, relying on the node answering in the order of the requests
+    /// like geth does. The failures of the individual requests are returned in their results.
+    async fn send_batch_results<R, F>(&self, requests: impl FnOnce(&Web3<Batch<Http>>) -> Vec<F>) -> anyhow::Result<Vec<web3::Result<R>>>
+    where
+        F: Future<Output = web3::Result<R>>,
+    {
+        let batch = Batch::new(self.web3.transport().clone());
+        let requests = requests(&Web3::new(batch.clone()));
+        if requests.is_empty() {
+            return Ok(Vec::new());
+        }
+        batch.submit_batch().await?;
+        Ok(join_all(requests).await)
+    }
+
+    /// Same as `send_batch_results`, but the whole batch fails if any of the requests fails.
+    async fn send_batch<R, F>(&self, requests: impl FnOnce(&Web3<Batch<Http>>) -> Vec<F>) -> anyhow::Result<Vec<R>>
+    where
+        F: Future<Output = web3::Result<R>>,
+    {
+        let results = self.send_batch_results(requests).await?;
+        Ok(results.into_iter().collect::<web3::Result<_>>()?)
+    }
+
+    pub fn main_contract_with_address(&self, address: Address) -> Contract<Http> {
+        Contract::new(self.web3.eth(), address, self.contract.clone())
+    }
+
+    pub fn main_contract(&self) -> Contract<Http> {
+        self.main_contract_with_address(self.contract_addr)
+    }
+
+    pub async fn block_number(&self) -> Result<U64, anyhow::Error> {
+        // let start = Instant::now();
+        let block_number = self.web3.eth().block_number().await?;
+        // metrics::histogram!("eth_client.direct.current_nonce", start.elapsed());
+        Ok(block_number)
+    }
+
+    pub async fn get_gas_price(&self) -> Result<U256, anyhow::Error> {
+        // let start = Instant::now();
+        let mut network_gas_price = self.web3.eth().gas_price().await?;
+        let percent_gas_price_factor = U256::from((self.gas_price_factor * 100.0).round() as u64);
+        network_gas_price = (network_gas_price * percent_gas_price_factor) / U256::from(100);
+        // metrics::histogram!("eth_client.direct.get_gas_price", start.elapsed());
+        Ok(network_gas_price)
+    }
+
+    /// Suggests the EIP-1559 fees based on the latest blocks: the median priority fee paid in them
+    /// (scaled by `gas_price_factor`), and the max fee allowing the base fee to double.
+    pub async fn suggest_eip1559_fees(&self) -> Result<Eip1559Fees, anyhow::Error> {
+        // let start = Instant::now();
+        let params = vec![
+            serde_json::json!(U256::from(FEE_HISTORY_BLOCKS)),
+            serde_json::json!("latest"),
+            serde_json::json!([FEE_HISTORY_REWARD_PERCENTILE]),
+        ];
+        let response = self.web3.transport().execute("eth_feeHistory", params).await?;
+        let history: FeeHistory = serde_json::from_value(response)?;
+
+        let next_base_fee = history
+            .base_fee_per_gas
+            .last()
+            .copied()
+            .ok_or_else(|| anyhow::anyhow!("eth_feeHistory returned no base fees"))?;
+
+        let mut rewards: Vec<U256> = history
+            .reward
+            .iter()
+            .filter_map(|block_rewards| block_rewards.first().copied())
+            .filter(|reward| !reward.is_zero())
+            .collect();
+        rewards.sort();
+        let priority_fee = rewards
+            .get(rewards.len() / 2)
+            .copied()
+            .unwrap_or_else(|| U256::from(FALLBACK_PRIORITY_FEE_PER_GAS));
+        let percent_gas_price_factor = U256::from((self.gas_price_factor * 100.0).round() as u64);
+        let max_priority_fee_per_gas = priority_fee * percent_gas_price_factor / U256::from(100);
+
+        // metrics::histogram!("eth_client.direct.suggest_eip1559_fees", start.elapsed());
+        Ok(Eip1559Fees {
+            max_fee_per_gas: next_base_fee * U256::from(2) + max_priority_fee_per_gas,
+            max_priority_fee_per_gas,
+        })
+    }
+
+    /// Asks the node for the accounts and storage slots accessed by the call, to be used
+    /// as the access list of the transaction (EIP-2930).
+    pub async fn create_access_list(
+        &self,
+        from: Address,
+        to: Address,
+        data: &[u8],
+        value: U256,
+    ) -> Result<Vec<AccessListItem>, anyhow::Error> {
+        // let start = Instant::now();
+        let call = serde_json::json!({
+            "from": from,
+            "to": to,
+            "data": Bytes(data.to_vec()),
+            "value": value,
+        });
+        let response = self
+            .web3
+            .transport()
+            .execute("eth_createAccessList", vec![call, serde_json::json!("latest")])
+            .await?;
+        let result: AccessListWithGasUsed = serde_json::from_value(response)?;
+        // metrics::histogram!("eth_client.direct.create_access_list", start.elapsed());
+        match result.error {
+            Some(error) => anyhow::bail!("eth_createAccessList call failed: {}", error),
+            None => Ok(result.access_list),
+        }
+    }
+
     pub async fn tx_receipt(&self, tx_hash: H256) -> Result<Option<TransactionReceipt>, anyhow::Error> {
         // let start = Instant::now();
         let receipt = self.web3.eth().transaction_receipt(tx_hash).await?;
@@ -197,7 +687,7 @@ impl<S: EthereumSigner> ETHDirectClient<S> {
         let gas_limit = transaction.gas;
         let gas_used = receipt.gas_used;
 
-        let call_request = web3::types::CallRequest {
+        let call_request = CallRequest {
             from: Some(transaction.from),
             to: transaction.to,
             gas: Some(transaction.gas),
@@ -206,28 +696,19 @@ impl<S: EthereumSigner> ETHDirectClient<S> {
             data: Some(transaction.input),
         };
 
-        let encoded_revert_reason = self.web3.eth().call(call_request, receipt.block_number.map(Into::into)).await?;
-        let revert_code = hex::encode(&encoded_revert_reason.0);
-        let revert_reason = if encoded_revert_reason.0.len() >= 4 {
-            let encoded_string_without_function_hash = &encoded_revert_reason.0[4..];
-
-            ethabi::decode(&[ethabi::ParamType::String], encoded_string_without_function_hash)?
-                .into_iter()
-                .next()
-                .unwrap()
-                .to_string()
-                .unwrap()
-        } else {
-            "unknown".to_string()
+        let revert_data = match self.web3.eth().call(call_request, receipt.block_number.map(Into::into)).await {
+            // Some nodes return the revert data as the result of the replayed call.
+            Ok(result) => result.0,
+            Err(error) => revert_data(&error).ok_or(error)?,
         };
 
         // metrics::histogram!("eth_client.direct.failure_reason", start.elapsed());
-        Ok(Some(FailureInfo {
-            revert_code,
-            revert_reason,
+        Ok(Some(FailureInfo::from_revert_data(
+            &revert_data,
+            &self.contract_errors,
             gas_used,
             gas_limit,
-        }))
+        )))
     }
 
     pub async fn eth_balance(&self, address: Address) -> Result<U256, anyhow::Error> {
@@ -237,25 +718,33 @@ impl<S: EthereumSigner> ETHDirectClient<S> {
         Ok(balance)
     }
 
-    pub async fn sender_eth_balance(&self) -> Result<U256, anyhow::Error> {
-        self.eth_balance(self.sender_account).await
-    }
+    /// Executes the calls (target and data) with `eth_call` on the latest block, in a single batch if
+    /// the batch requests are enabled. The failures of the individual calls are returned in their results.
+    pub async fn eth_calls(&self, calls: Vec<(Address, Vec<u8>)>) -> anyhow::Result<Vec<anyhow::Result<Vec<u8>>>> {
+        let request = |(to, data): (Address, Vec<u8>)| CallRequest {
+            from: None,
+            to: Some(to),
+            gas: None,
+            gas_price: None,
+            value: None,
+            data: Some(Bytes(data)),
+        };
+        if !self.batch_requests {
+            let mut results = Vec::with_capacity(calls.len());
+            for call in calls {
+                let result = self.web3.eth().call(request(call), None).await;
+                results.push(result.map(|bytes| bytes.0).map_err(Into::into));
+            }
+            return Ok(results);
+        }
 
-    pub async fn allowance(&self, token_address: Address, erc20_abi: ethabi::Contract) -> Result<U256, anyhow::Error> {
-        // let start = Instant::now();
-        let res = self
-            .call_contract_function(
-                "allowance",
-                (self.sender_account, self.contract_addr),
-                None,
-                Options::default(),
-                None,
-                token_address,
-                erc20_abi,
-            )
+        let results = self
+            .send_batch_results(|web3| calls.into_iter().map(|call| web3.eth().call(request(call), None)).collect())
             .await?;
-        // metrics::histogram!("eth_client.direct.allowance", start.elapsed());
-        Ok(res)
+        Ok(results
+            .into_iter()
+            .map(|result| result.map(|bytes| bytes.0).map_err(Into::into))
+            .collect())
     }
 
     pub async fn call_main_contract_function<R, A, P, B>(
@@ -302,30 +791,32 @@ impl<S: EthereumSigner> ETHDirectClient<S> {
 
     pub async fn get_tx_status(&self, hash: H256) -> anyhow::Result<Option<ExecutedTxStatus>> {
         // let start = Instant::now();
+        let status = self.get_tx_statuses(&[hash]).await?.pop().flatten();
+        // metrics::histogram!("eth_client.direct.get_tx_status", start.elapsed());
+        Ok(status)
+    }
 
-        let receipt = self.tx_receipt(hash).await?;
-        let res: Result<Option<ExecutedTxStatus>, anyhow::Error> = match receipt {
-            Some(TransactionReceipt {
-                block_number: Some(tx_block_number),
-                status: Some(status),
-                ..
-            }) => {
-                let confirmations = self.block_number().await?.saturating_sub(tx_block_number).as_u64();
-                let success = status.as_u64() == 1;
-
-                // Set the receipt only for failures.
-                let receipt = if success { None } else { Some(receipt.unwrap()) };
-
-                Ok(Some(ExecutedTxStatus {
-                    confirmations,
-                    success,
-                    receipt,
-                }))
-            }
-            _ => Ok(None),
+    /// Statuses of the transactions. If the batch requests are enabled, the receipts are requested
+    /// together with the block number in a single batch.
+    pub async fn get_tx_statuses(&self, hashes: &[H256]) -> anyhow::Result<Vec<Option<ExecutedTxStatus>>> {
+        let (receipts, block_number) = if self.batch_requests {
+            let batch = Batch::new(self.web3.transport().clone());
+            let web3 = Web3::new(batch.clone());
+            let receipts: Vec<_> = hashes.iter().map(|hash| web3.eth().transaction_receipt(*hash)).collect();
+            let block_number = web3.eth().block_number();
+            batch.submit_batch().await?;
+            (try_join_all(receipts).await?, block_number.await?)
+        } else {
+            let receipts = self.tx_receipts(hashes).await?;
+            // The block number is only needed if any of the transactions is mined.
+            let block_number = if receipts.iter().any(Option::is_some) {
+                self.block_number().await?
+            } else {
+                U64::zero()
+            };
+            (receipts, block_number)
         };
-        // metrics::histogram!("eth_client.direct.get_tx_status", start.elapsed());
-        Ok(res?)
+        Ok(receipts.into_iter().map(|receipt| tx_status(receipt?, block_number)).collect())
     }
 
     pub async fn logs(&self, filter: Filter) -> anyhow::Result<Vec<Log>> {
@@ -335,6 +826,34 @@ impl<S: EthereumSigner> ETHDirectClient<S> {
         Ok(logs)
     }
 
+    /// Logs matching each of the filters, in a single batch if the batch requests are enabled.
+    pub async fn logs_batch(&self, filters: Vec<Filter>) -> anyhow::Result<Vec<Vec<Log>>> {
+        if self.batch_requests {
+            return self
+                .send_batch(|web3| filters.into_iter().map(|filter| web3.eth().logs(filter)).collect())
+                .await;
+        }
+        let mut logs = Vec::with_capacity(filters.len());
+        for filter in filters {
+            logs.push(self.logs(filter).await?);
+        }
+        Ok(logs)
+    }
+
+    /// Receipts of the transactions, in a single batch if the batch requests are enabled.
+    pub async fn tx_receipts(&self, tx_hashes: &[H256]) -> anyhow::Result<Vec<Option<TransactionReceipt>>> {
+        if self.batch_requests {
+            return self
+                .send_batch(|web3| tx_hashes.iter().map(|hash| web3.eth().transaction_receipt(*hash)).collect())
+                .await;
+        }
+        let mut receipts = Vec::with_capacity(tx_hashes.len());
+        for hash in tx_hashes {
+            receipts.push(self.tx_receipt(*hash).await?);
+        }
+        Ok(receipts)
+    }
+
     pub fn contract(&self) -> &ethabi::Contract {
         &self.contract
     }
@@ -345,3 +864,231 @@ impl<S: EthereumSigner> ETHDirectClient<S> {
         f.encode_input(&params.into_tokens()).expect("failed to encode parameters")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contracts::fluidex_contract;
+    use crate::eth_client::nonce_manager::is_rpc_error;
+    use actix_web::{post, web, App, HttpResponse, HttpServer, Responder};
+    use futures::future::{AbortHandle, Abortable};
+    use serde_json::{json, Value};
+    use std::sync::{Arc, Mutex};
+    use web3::types::FilterBuilder;
+
+    /// Latest block of the stand-in node.
+    const BLOCK_NUMBER: u64 = 20;
+    /// Logs starting from this block are too many for the stand-in node to return.
+    const TOO_MANY_LOGS_BLOCK: u64 = 15;
+
+    /// Sizes of the HTTP requests received by the stand-in node, `None` for the single calls.
+    #[derive(Clone, Default)]
+    struct State {
+        requests: Arc<Mutex<Vec<Option<usize>>>>,
+    }
+
+    #[post("/")]
+    async fn node(req: web::Json<Value>, state: web::Data<State>) -> impl Responder {
+        match req.into_inner() {
+            Value::Array(calls) => {
+                state.requests.lock().unwrap().push(Some(calls.len()));
+                HttpResponse::Ok().json(calls.iter().map(respond).collect::<Vec<_>>())
+            }
+            call => {
+                state.requests.lock().unwrap().push(None);
+                HttpResponse::Ok().json(respond(&call))
+            }
+        }
+    }
+
+    /// Answers the call like a node would: the transactions are mined in the block of the number
+    /// set in their hashes, and every block has a single log.
+    fn respond(call: &Value) -> Value {
+        let params = &call["params"];
+        let result = match call["method"].as_str().unwrap() {
+            "eth_blockNumber" => json!(U64::from(BLOCK_NUMBER)),
+            "eth_getTransactionReceipt" => {
+                let hash: H256 = serde_json::from_value(params[0].clone()).unwrap();
+                let block = hash.to_low_u64_be();
+                if block == 0 {
+                    Value::Null
+                } else {
+                    json!(TransactionReceipt {
+                        transaction_hash: hash,
+                        block_number: Some(block.into()),
+                        status: Some(U64::from(block % 2)),
+                        ..Default::default()
+                    })
+                }
+            }
+            "eth_getLogs" => {
+                let from: U64 = serde_json::from_value(params[0]["fromBlock"].clone()).unwrap();
+                let to: U64 = serde_json::from_value(params[0]["toBlock"].clone()).unwrap();
+                if to.as_u64() >= TOO_MANY_LOGS_BLOCK {
+                    return json!({
+                        "jsonrpc": "2.0",
+                        "id": call["id"],
+                        "error": { "code": -32005, "message": "query returned more than 10000 results" },
+                    });
+                }
+                let logs: Vec<_> = (from.as_u64()..=to.as_u64()).map(log).collect();
+                json!(logs)
+            }
+            // The call returns its target, and the calls to the zero address revert.
+            "eth_call" => {
+                let to: Address = serde_json::from_value(params[0]["to"].clone()).unwrap();
+                if to.is_zero() {
+                    return json!({
+                        "jsonrpc": "2.0",
+                        "id": call["id"],
+                        "error": { "code": 3, "message": "execution reverted" },
+                    });
+                }
+                json!(Bytes(to.as_bytes().to_vec()))
+            }
+            method => panic!("Unexpected method {}", method),
+        };
+        json!({ "jsonrpc": "2.0", "id": call["id"], "result": result })
+    }
+
+    fn log(block: u64) -> Log {
+        Log {
+            address: Address::zero(),
+            topics: Vec::new(),
+            data: Bytes(Vec::new()),
+            block_hash: None,
+            block_number: Some(block.into()),
+            transaction_hash: None,
+            transaction_index: None,
+            log_index: None,
+            transaction_log_index: None,
+            log_type: None,
+            removed: None,
+        }
+    }
+
+    fn run_server(state: State) -> (String, AbortHandle) {
+        let mut url = None;
+        let mut server = None;
+        for i in 9000..9999 {
+            let new_url = format!("127.0.0.1:{}", i);
+            // Try to bind to some port, hope that 999 variants will be enough
+            let tmp_state = state.clone();
+            if let Ok(ser) = HttpServer::new(move || App::new().data(tmp_state.clone()).service(node)).bind(new_url.clone()) {
+                server = Some(ser);
+                url = Some(new_url);
+                break;
+            }
+        }
+
+        let server = server.expect("Could not bind to port from 9000 to 9999");
+        let (abort_handle, abort_registration) = AbortHandle::new_pair();
+        let future = Abortable::new(server.run(), abort_registration);
+        tokio::spawn(future);
+        let address = format!("http://{}/", &url.unwrap());
+        (address, abort_handle)
+    }
+
+    fn client(url: &str) -> ETHDirectClient<ReadOnly> {
+        ETHDirectClient::new_read_only(Http::new(url).unwrap(), fluidex_contract(), Address::zero(), 9, 1.0).with_batch_requests(true)
+    }
+
+    fn filter(from: u64, to: u64) -> Filter {
+        FilterBuilder::default()
+            .from_block(BlockNumber::Number(from.into()))
+            .to_block(BlockNumber::Number(to.into()))
+            .build()
+    }
+
+    fn log_blocks(logs: &[Log]) -> Vec<u64> {
+        logs.iter().map(|log| log.block_number.unwrap().as_u64()).collect()
+    }
+
+    #[actix_rt::test]
+    async fn test_batch_requests() {
+        let state = State::default();
+        let (url, abort_handle) = run_server(state.clone());
+        let client = client(&url);
+
+        // All the calls are sent in a single HTTP request, and their results are returned in order.
+        let logs = client.logs_batch(vec![filter(3, 4), filter(1, 1), filter(7, 9)]).await.unwrap();
+        assert_eq!(
+            logs.iter().map(|logs| log_blocks(logs)).collect::<Vec<_>>(),
+            vec![vec![3, 4], vec![1], vec![7, 8, 9]]
+        );
+        assert_eq!(*state.requests.lock().unwrap(), vec![Some(3)]);
+
+        // The receipts are requested together with the block number.
+        let hashes = [H256::from_low_u64_be(5), H256::zero(), H256::from_low_u64_be(18)];
+        let statuses = client.get_tx_statuses(&hashes).await.unwrap();
+        let statuses: Vec<_> = statuses
+            .into_iter()
+            .map(|status| status.map(|status| (status.confirmations, status.success)))
+            .collect();
+        assert_eq!(statuses, vec![Some((15, true)), None, Some((2, false))]);
+        assert_eq!(state.requests.lock().unwrap()[1..], [Some(4)]);
+
+        // The failure of any call fails the whole batch.
+        let error = client
+            .logs_batch(vec![filter(1, 2), filter(10, TOO_MANY_LOGS_BLOCK)])
+            .await
+            .unwrap_err();
+        assert!(is_rpc_error(&error), "{:#}", error);
+        assert!(error.to_string().contains("more than 10000 results"), "{:#}", error);
+        assert_eq!(state.requests.lock().unwrap()[2..], [Some(2)]);
+
+        // Without the batch mode, every call is sent separately.
+        let client = client.with_batch_requests(false);
+        let receipts = client.tx_receipts(&hashes[..2]).await.unwrap();
+        assert_eq!(receipts[0].as_ref().unwrap().transaction_hash, hashes[0]);
+        assert!(receipts[1].is_none());
+        assert_eq!(state.requests.lock().unwrap()[3..], [None, None]);
+
+        abort_handle.abort();
+    }
+
+    #[actix_rt::test]
+    async fn test_batch_calls() {
+        let state = State::default();
+        let (url, abort_handle) = run_server(state.clone());
+        let calls = vec![
+            (Address::from_low_u64_be(1), vec![1]),
+            (Address::zero(), vec![2]),
+            (Address::from_low_u64_be(3), vec![3]),
+        ];
+
+        // The reverted call doesn't fail the others, which are sent in the same batch.
+        for (batch_requests, requests) in [(true, vec![Some(3)]), (false, vec![None, None, None])].iter() {
+            state.requests.lock().unwrap().clear();
+            let client = client(&url).with_batch_requests(*batch_requests);
+            let results = client.eth_calls(calls.clone()).await.unwrap();
+            assert_eq!(results[0].as_ref().unwrap(), Address::from_low_u64_be(1).as_bytes());
+            assert!(results[1].as_ref().unwrap_err().to_string().contains("execution reverted"));
+            assert_eq!(results[2].as_ref().unwrap(), Address::from_low_u64_be(3).as_bytes());
+            assert_eq!(*state.requests.lock().unwrap(), *requests);
+        }
+
+        abort_handle.abort();
+    }
+
+    #[test]
+    fn test_constrained_eip1559_fees() {
+        let fees = |max_fee_per_gas: u64, max_priority_fee_per_gas: u64| Eip1559Fees {
+            max_fee_per_gas: max_fee_per_gas.into(),
+            max_priority_fee_per_gas: max_priority_fee_per_gas.into(),
+        };
+
+        // Without the constraints, the suggested fees are used.
+        assert_eq!(fees(100, 10).constrained(None, None), fees(100, 10));
+        // The set max fee replaces the suggested one, and caps the priority fee.
+        assert_eq!(fees(100, 10).constrained(Some(150.into()), None), fees(150, 10));
+        assert_eq!(fees(100, 10).constrained(Some(5.into()), None), fees(5, 5));
+        // The priority fee of the replacement is bumped over the suggested one.
+        assert_eq!(fees(100, 10).constrained(None, Some(22.into())), fees(100, 22));
+        assert_eq!(fees(100, 10).constrained(Some(110.into()), Some(22.into())), fees(110, 22));
+        // The max fee is raised to fit the bumped priority fee, which is never capped below it.
+        assert_eq!(fees(100, 10).constrained(Some(20.into()), Some(22.into())), fees(22, 22));
+        assert_eq!(fees(100, 30).constrained(Some(20.into()), Some(22.into())), fees(22, 22));
+        assert_eq!(fees(100, 10).constrained(None, Some(120.into())), fees(120, 120));
+    }
+}
@@ -5,13 +5,39 @@ use crate::eth_client::{
 use crate::types::{TransactionReceipt, H160, H256, U256};
 use anyhow::Error;
 use ethabi::{Address, Contract};
-use std::collections::{HashMap, HashSet};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::sync::Arc;
 use tokio::sync::RwLock;
 use web3::contract::tokens::{Detokenize, Tokenize};
 use web3::contract::Options;
 use web3::types::{BlockId, Filter, Log, U64};
 
+/// Whether the log matches the filter serialized for `eth_getLogs`.
+fn log_matches(log: &Log, filter: &Value) -> bool {
+    // The single values of the address and topics may be serialized without the array.
+    fn any_of<T: DeserializeOwned + PartialEq>(value: &Value, item: &T) -> bool {
+        match value {
+            Value::Null => true,
+            Value::Array(values) => values.iter().any(|value| any_of(value, item)),
+            value => serde_json::from_value::<T>(value.clone()).map_or(false, |value| value == *item),
+        }
+    }
+    // Only the numbered blocks are matched, the tags like "latest" don't bound the range.
+    let block_bound = |key: &str| serde_json::from_value::<U64>(filter[key].clone()).ok();
+
+    let block = log.block_number.unwrap_or_default();
+    let topics = filter["topics"].as_array().cloned().unwrap_or_default();
+    block_bound("fromBlock").map_or(true, |from| block >= from)
+        && block_bound("toBlock").map_or(true, |to| block <= to)
+        && any_of(&filter["address"], &log.address)
+        && topics
+            .iter()
+            .enumerate()
+            .all(|(i, topic)| topic.is_null() || log.topics.get(i).map_or(false, |log_topic| any_of(topic, log_topic)))
+}
+
 /// Mock Ethereum client is capable of recording all the incoming requests for the further analysis.
 #[derive(Debug, Clone)]
 pub struct MockEthereum {
@@ -19,6 +45,17 @@ pub struct MockEthereum {
     pub gas_price: U256,
     pub tx_statuses: Arc<RwLock<HashMap<H256, ExecutedTxStatus>>>,
     pub sent_txs: Arc<RwLock<HashSet<Vec<u8>>>>,
+    /// Nonce of the next transaction signed without the explicit nonce.
+    pub next_nonce: Arc<RwLock<U256>>,
+    /// Errors returned instead of sending the next transactions, in order.
+    pub send_errors: Arc<RwLock<VecDeque<web3::Error>>>,
+    /// Errors returned instead of signing the next transactions, in order.
+    pub sign_errors: Arc<RwLock<VecDeque<Error>>>,
+    /// Logs returned by the queries whose filters they match.
+    pub logs: Arc<RwLock<Vec<Log>>>,
+    /// Results of `eth_call` by the target and data. The other calls return nothing,
+    /// like the calls to the addresses without a contract.
+    pub call_results: Arc<RwLock<HashMap<(Address, Vec<u8>), Result<Vec<u8>, web3::Error>>>>,
 }
 
 impl Default for MockEthereum {
@@ -28,6 +65,11 @@ impl Default for MockEthereum {
             gas_price: 100.into(),
             tx_statuses: Default::default(),
             sent_txs: Default::default(),
+            next_nonce: Default::default(),
+            send_errors: Default::default(),
+            sign_errors: Default::default(),
+            logs: Default::default(),
+            call_results: Default::default(),
         }
     }
 }
@@ -85,6 +127,11 @@ impl MockEthereum {
         Ok(self.tx_statuses.read().await.get(&hash).cloned())
     }
 
+    pub async fn get_tx_statuses(&self, hashes: &[H256]) -> anyhow::Result<Vec<Option<ExecutedTxStatus>>> {
+        let tx_statuses = self.tx_statuses.read().await;
+        Ok(hashes.iter().map(|hash| tx_statuses.get(hash).cloned()).collect())
+    }
+
     pub async fn block_number(&self) -> anyhow::Result<U64> {
         Ok(self.block_number.into())
     }
@@ -94,6 +141,9 @@ impl MockEthereum {
     }
 
     pub async fn send_raw_tx(&self, tx: Vec<u8>) -> Result<H256, anyhow::Error> {
+        if let Some(error) = self.send_errors.write().await.pop_front() {
+            return Err(error.into());
+        }
         // Cut hash of transaction
         let mut hash: [u8; 32] = Default::default();
         hash.copy_from_slice(&tx[..32]);
@@ -101,9 +151,24 @@ impl MockEthereum {
         Ok(H256::from(hash))
     }
 
+    pub async fn send_signed_tx(&self, tx: &SignedCallResult) -> Result<H256, anyhow::Error> {
+        self.send_raw_tx(tx.raw_tx.clone()).await
+    }
+
     pub async fn sign_prepared_tx(&self, raw_tx: Vec<u8>, options: Options) -> anyhow::Result<SignedCallResult> {
+        if let Some(error) = self.sign_errors.write().await.pop_front() {
+            return Err(error);
+        }
         let gas_price = options.gas_price.unwrap_or(self.gas_price);
-        let nonce = options.nonce.expect("Nonce must be set for every tx");
+        // Nonces are allocated sequentially, like the nonce manager of the real clients does.
+        let nonce = match options.nonce {
+            Some(nonce) => nonce,
+            None => {
+                let mut next_nonce = self.next_nonce.write().await;
+                *next_nonce += U256::one();
+                *next_nonce - 1
+            }
+        };
 
         // Nonce and gas_price are appended to distinguish the same transactions
         // with different gas by their hash in tests.
@@ -117,11 +182,22 @@ impl MockEthereum {
         Ok(SignedCallResult {
             raw_tx: new_raw_tx,
             gas_price,
+            max_priority_fee_per_gas: None,
             nonce,
             hash,
         })
     }
 
+    pub async fn sign_replacement_tx(
+        &self,
+        raw_tx: Vec<u8>,
+        mut options: Options,
+        replaced: &SignedCallResult,
+    ) -> anyhow::Result<SignedCallResult> {
+        options.nonce = Some(replaced.nonce);
+        self.sign_prepared_tx(raw_tx, options).await
+    }
+
     pub async fn failure_reason(&self, _tx_hash: H256) -> Result<Option<FailureInfo>, anyhow::Error> {
         Ok(None)
     }
@@ -151,6 +227,10 @@ impl MockEthereum {
         unreachable!()
     }
 
+    pub async fn tx_receipts(&self, _tx_hashes: &[H256]) -> Result<Vec<Option<TransactionReceipt>>, Error> {
+        unreachable!()
+    }
+
     pub async fn eth_balance(&self, _address: Address) -> Result<U256, Error> {
         unreachable!()
     }
@@ -187,8 +267,31 @@ impl MockEthereum {
         todo!()
     }
 
-    pub async fn logs(&self, _filter: Filter) -> anyhow::Result<Vec<Log>> {
-        todo!()
+    pub async fn logs(&self, filter: Filter) -> anyhow::Result<Vec<Log>> {
+        Ok(self.logs_batch(vec![filter]).await?.remove(0))
+    }
+
+    pub async fn logs_batch(&self, filters: Vec<Filter>) -> anyhow::Result<Vec<Vec<Log>>> {
+        let logs = self.logs.read().await;
+        filters
+            .into_iter()
+            .map(|filter| {
+                // The fields of the filter are private, so it's matched in the form it's sent to the node.
+                let filter = serde_json::to_value(filter)?;
+                Ok(logs.iter().filter(|log| log_matches(log, &filter)).cloned().collect())
+            })
+            .collect()
+    }
+
+    pub async fn eth_calls(&self, calls: Vec<(Address, Vec<u8>)>) -> anyhow::Result<Vec<anyhow::Result<Vec<u8>>>> {
+        let call_results = self.call_results.read().await;
+        Ok(calls
+            .into_iter()
+            .map(|call| match call_results.get(&call) {
+                Some(result) => result.clone().map_err(Into::into),
+                None => Ok(Vec::new()),
+            })
+            .collect())
     }
 
     #[allow(clippy::too_many_arguments)]
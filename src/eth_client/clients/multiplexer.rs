@@ -1,6 +1,7 @@
 use crate::eth_client::ethereum_gateway::{ExecutedTxStatus, FailureInfo, SignedCallResult};
+use crate::eth_client::nonce_manager::{NonceError, NonceManager, SendOutcome};
 use crate::eth_client::ETHDirectClient;
-use crate::eth_signer::PrivateKeySigner;
+use crate::eth_signer::{EthereumSigner, PrivateKeySigner};
 use crate::types::{TransactionReceipt, H160, H256, U256};
 use ethabi::Contract;
 use web3::{
@@ -10,56 +11,52 @@ use web3::{
 };
 
 #[derive(Debug, Clone)]
-pub struct MultiplexerEthereumClient {
-    clients: Vec<(String, ETHDirectClient<PrivateKeySigner>)>,
+pub struct MultiplexerEthereumClient<S = PrivateKeySigner> {
+    clients: Vec<(String, ETHDirectClient<S>)>,
 }
 
-impl Default for MultiplexerEthereumClient {
+impl<S> Default for MultiplexerEthereumClient<S> {
     fn default() -> Self {
         Self::new()
     }
 }
 
+/// Calls the method of the clients in turn, until one of them succeeds.
+///
+/// The error which any other node would return as well (e.g. the revert of the dry run, or the nonce
+/// manager shared by the clients running out of nonces) is returned right away, otherwise the error of the last client is returned once all of them fail.
 macro_rules! multiple_call {
-    ($self:expr, $func:ident($($attr:expr),+)) => {
+    ($self:expr, $func:ident($($attr:expr),*)) => {
+        let mut last_error = None;
         for (name, client) in $self.clients.iter() {
-            match client.$func($($attr.clone()),+).await {
+            match client.$func($($attr.clone()),*).await {
                 Ok(res) => return Ok(res),
-                Err(err) => log::error!("Error in interface: {}, {} ", name, err),
+                Err(err) if is_final_error(&err) => return Err(err),
+                Err(err) => {
+                    log::error!("Error in interface: {}, {} ", name, err);
+                    last_error = Some(err);
+                }
             }
         }
-        anyhow::bail!("All interfaces was wrong please try again")
+        return Err(last_error.unwrap_or_else(|| anyhow::anyhow!("No interfaces are configured")));
     };
+}
 
-    ($self:expr, $func:ident()) => {
-        for (name, client) in $self.clients.iter() {
-            match client.$func().await {
-                Ok(res) => return Ok(res),
-                Err(err) => log::error!("Error in interface: {}, {} ", name, err),
-            }
-        }
-        anyhow::bail!("All interfaces was wrong please try again")
-    };
+/// Whether the error doesn't depend on the node, so there is no point in trying the other ones.
+fn is_final_error(error: &anyhow::Error) -> bool {
+    error.downcast_ref::<FailureInfo>().is_some() || error.downcast_ref::<NonceError>().is_some()
 }
 
-impl MultiplexerEthereumClient {
+impl<S> MultiplexerEthereumClient<S> {
     pub fn new() -> Self {
         Self { clients: vec![] }
     }
 
-    pub fn add_client(mut self, name: String, client: ETHDirectClient<PrivateKeySigner>) -> Self {
+    pub fn add_client(mut self, name: String, client: ETHDirectClient<S>) -> Self {
         self.clients.push((name, client));
         self
     }
 
-    pub async fn pending_nonce(&self) -> Result<U256, anyhow::Error> {
-        multiple_call!(self, pending_nonce());
-    }
-
-    pub async fn current_nonce(&self) -> Result<U256, anyhow::Error> {
-        multiple_call!(self, current_nonce());
-    }
-
     pub async fn block_number(&self) -> Result<U64, anyhow::Error> {
         multiple_call!(self, block_number());
     }
@@ -68,27 +65,6 @@ impl MultiplexerEthereumClient {
         multiple_call!(self, get_gas_price());
     }
 
-    pub async fn sender_eth_balance(&self) -> Result<U256, anyhow::Error> {
-        multiple_call!(self, sender_eth_balance());
-    }
-
-    pub async fn sign_prepared_tx(&self, data: Vec<u8>, options: Options) -> Result<SignedCallResult, anyhow::Error> {
-        multiple_call!(self, sign_prepared_tx(data, options));
-    }
-
-    pub async fn sign_prepared_tx_for_addr(
-        &self,
-        data: Vec<u8>,
-        contract_addr: H160,
-        options: Options,
-    ) -> Result<SignedCallResult, anyhow::Error> {
-        multiple_call!(self, sign_prepared_tx_for_addr(data, contract_addr, options));
-    }
-
-    pub async fn send_raw_tx(&self, tx: Vec<u8>) -> Result<H256, anyhow::Error> {
-        multiple_call!(self, send_raw_tx(tx));
-    }
-
     pub async fn tx_receipt(&self, tx_hash: H256) -> Result<Option<TransactionReceipt>, anyhow::Error> {
         multiple_call!(self, tx_receipt(tx_hash));
     }
@@ -101,10 +77,6 @@ impl MultiplexerEthereumClient {
         multiple_call!(self, eth_balance(address));
     }
 
-    pub async fn allowance(&self, token_address: Address, erc20_abi: Contract) -> Result<U256, anyhow::Error> {
-        multiple_call!(self, allowance(token_address, erc20_abi));
-    }
-
     #[allow(clippy::too_many_arguments)]
     pub async fn call_contract_function<R, A, B, P>(
         &self,
@@ -149,12 +121,103 @@ impl MultiplexerEthereumClient {
         multiple_call!(self, get_tx_status(hash));
     }
 
+    pub async fn get_tx_statuses(&self, hashes: &[H256]) -> Result<Vec<Option<ExecutedTxStatus>>, anyhow::Error> {
+        multiple_call!(self, get_tx_statuses(hashes));
+    }
+
+    pub async fn tx_receipts(&self, tx_hashes: &[H256]) -> Result<Vec<Option<TransactionReceipt>>, anyhow::Error> {
+        multiple_call!(self, tx_receipts(tx_hashes));
+    }
+
     pub async fn logs(&self, filter: Filter) -> anyhow::Result<Vec<Log>> {
         multiple_call!(self, logs(filter));
     }
 
+    pub async fn logs_batch(&self, filters: Vec<Filter>) -> anyhow::Result<Vec<Vec<Log>>> {
+        multiple_call!(self, logs_batch(filters));
+    }
+
+    pub async fn eth_calls(&self, calls: Vec<(Address, Vec<u8>)>) -> anyhow::Result<Vec<anyhow::Result<Vec<u8>>>> {
+        multiple_call!(self, eth_calls(calls));
+    }
+
     pub fn encode_tx_data<P: Tokenize + Clone>(&self, func: &str, params: P) -> Vec<u8> {
         let (_, client) = self.clients.first().expect("Should be exactly one client");
         client.encode_tx_data(func, params)
     }
 }
+
+impl<S: EthereumSigner> MultiplexerEthereumClient<S> {
+    pub async fn pending_nonce(&self) -> Result<U256, anyhow::Error> {
+        multiple_call!(self, pending_nonce());
+    }
+
+    pub async fn current_nonce(&self) -> Result<U256, anyhow::Error> {
+        multiple_call!(self, current_nonce());
+    }
+
+    pub async fn sender_eth_balance(&self) -> Result<U256, anyhow::Error> {
+        multiple_call!(self, sender_eth_balance());
+    }
+
+    pub async fn allowance(&self, token_address: Address, erc20_abi: Contract) -> Result<U256, anyhow::Error> {
+        multiple_call!(self, allowance(token_address, erc20_abi));
+    }
+
+    pub async fn sign_prepared_tx(&self, data: Vec<u8>, options: Options) -> Result<SignedCallResult, anyhow::Error> {
+        multiple_call!(self, sign_prepared_tx(data, options));
+    }
+
+    pub async fn sign_prepared_tx_for_addr(
+        &self,
+        data: Vec<u8>,
+        contract_addr: H160,
+        options: Options,
+    ) -> Result<SignedCallResult, anyhow::Error> {
+        multiple_call!(self, sign_prepared_tx_for_addr(data, contract_addr, options));
+    }
+
+    pub async fn sign_replacement_tx(
+        &self,
+        data: Vec<u8>,
+        options: Options,
+        replaced: &SignedCallResult,
+    ) -> Result<SignedCallResult, anyhow::Error> {
+        multiple_call!(self, sign_replacement_tx(data, options, replaced));
+    }
+
+    pub async fn send_raw_tx(&self, tx: Vec<u8>) -> Result<H256, anyhow::Error> {
+        multiple_call!(self, send_raw_tx(tx));
+    }
+
+    /// Sends the transaction through the clients in turn, until the node of one of them answers.
+    ///
+    /// The client failed without the answer (e.g. on a timeout) may have delivered the transaction
+    /// anyway, so after that only the acceptance by another node is conclusive, and the nonce
+    /// stays reserved otherwise. The error of the last client failed without the answer is returned then.
+    pub async fn send_signed_tx(&self, tx: &SignedCallResult) -> Result<H256, anyhow::Error> {
+        let mut undelivered_error = None;
+        for (name, client) in self.clients.iter() {
+            let result = client.send_raw_tx(tx.raw_tx.clone()).await;
+            let outcome = SendOutcome::of(tx.nonce, &result);
+            if outcome == SendOutcome::Accepted || (undelivered_error.is_none() && outcome != SendOutcome::Unknown) {
+                return client.complete_send(tx, result, outcome);
+            }
+            if let Err(err) = result {
+                log::error!("Error in interface: {}, {} ", name, err);
+                if outcome == SendOutcome::Unknown {
+                    undelivered_error = Some(err);
+                }
+            }
+        }
+        if let Some(manager) = self.nonce_manager() {
+            manager.record_send(tx.nonce, &SendOutcome::Unknown);
+        }
+        Err(undelivered_error.unwrap_or_else(|| anyhow::anyhow!("No interfaces are configured")))
+    }
+
+    /// All the clients share the nonce manager of the gateway.
+    pub fn nonce_manager(&self) -> Option<&NonceManager> {
+        self.clients.first().and_then(|(_, client)| client.nonce_manager())
+    }
+}
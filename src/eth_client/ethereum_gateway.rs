@@ -1,19 +1,28 @@
 use crate::config;
-use crate::contracts::fluidex_contract;
+use crate::contracts::{fluidex_contract, fluidex_errors, ContractError};
 use crate::eth_client::clients::mock::MockEthereum;
 use crate::eth_client::clients::multiplexer::MultiplexerEthereumClient;
+use crate::eth_client::nonce_manager::NonceManager;
+use crate::eth_client::revert_error::RevertError;
 use crate::eth_client::ETHDirectClient;
-use crate::eth_signer::PrivateKeySigner;
+use crate::eth_signer::{EthereumSigner, PrivateKeySigner, ReadOnly};
 use crate::types::{TransactionReceipt, H160, H256, U256};
-use std::fmt::Debug;
+use crate::utils::ZeroPrefixHexSerde;
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Debug};
 use web3::contract::tokens::{Detokenize, Tokenize};
 use web3::contract::Options;
+use web3::transports::Http;
 use web3::types::{Address, BlockId, Filter, Log, U64};
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct SignedCallResult {
+    #[serde(with = "ZeroPrefixHexSerde")]
     pub raw_tx: Vec<u8>,
+    /// Gas price of the legacy transaction, or the max fee per gas of the EIP-1559 one.
     pub gas_price: U256,
+    /// Set only for the EIP-1559 transactions.
+    pub max_priority_fee_per_gas: Option<U256>,
     pub nonce: U256,
     pub hash: H256,
 }
@@ -30,53 +39,122 @@ pub struct ExecutedTxStatus {
     pub receipt: Option<TransactionReceipt>,
 }
 /// Information about transaction failure.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FailureInfo {
+    /// Hex-encoded revert data.
     pub revert_code: String,
     pub revert_reason: String,
+    /// Not set if the failure is found by a dry run.
     pub gas_used: Option<U256>,
     pub gas_limit: U256,
+    /// Decoded revert data, `None` if the transaction reverted without the data or with an unknown error.
+    #[serde(default)]
+    pub revert_error: Option<RevertError>,
+}
+
+impl FailureInfo {
+    /// Decodes the data the transaction reverted with, matching the custom errors against `contract_errors`.
+    pub fn from_revert_data(revert_data: &[u8], contract_errors: &[ContractError], gas_used: Option<U256>, gas_limit: U256) -> Self {
+        let revert_error = RevertError::decode(revert_data, contract_errors);
+        Self {
+            revert_code: hex::encode(revert_data),
+            revert_reason: revert_error.as_ref().map_or_else(|| "unknown".to_string(), ToString::to_string),
+            gas_used,
+            gas_limit,
+            revert_error,
+        }
+    }
 }
 
+impl fmt::Display for FailureInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "transaction reverts: {} (0x{})", self.revert_reason, self.revert_code)
+    }
+}
+
+impl std::error::Error for FailureInfo {}
+
+/// Gateway to the Ethereum nodes.
+///
+/// A gateway is either able to sign and send transactions with the operator account (the `S` signer),
+/// or is read-only (`EthereumGateway<ReadOnly>`). The read-only gateways don't need the `eth_sender`
+/// settings, and the methods signing or sending transactions are not available for them.
 #[derive(Debug, Clone)]
-pub enum EthereumGateway {
-    Direct(ETHDirectClient<PrivateKeySigner>),
-    Multiplexed(MultiplexerEthereumClient),
+pub enum EthereumGateway<S = PrivateKeySigner> {
+    Direct(ETHDirectClient<S>),
+    Multiplexed(MultiplexerEthereumClient<S>),
     Mock(MockEthereum),
 }
 
-impl EthereumGateway {
-    pub fn from_config(config: &config::Settings) -> Self {
-        if config.eth_client.web3_url.len() == 1 {
-            let transport = web3::transports::Http::new(&config.eth_client.web3_url()).unwrap();
+/// Gateway which is only able to read from the Ethereum nodes.
+pub type ReadOnlyEthereumGateway = EthereumGateway<ReadOnly>;
+
+impl<S: EthereumSigner> EthereumGateway<S> {
+    /// Creates a gateway signing the transactions with the operator signer,
+    /// usually created by `eth_signer::signer_from_config`.
+    ///
+    /// The nonces are allocated locally, with at most `max_txs_in_flight` transactions not mined.
+    ///
+    /// Panics if the `eth_sender` settings are not provided.
+    pub fn from_config(config: &config::Settings, signer: S) -> Self {
+        let sender = &config
+            .eth_sender
+            .as_ref()
+            .expect("eth_sender settings are required to sign transactions")
+            .sender;
+        let nonce_manager = NonceManager::new(sender.max_txs_in_flight);
+
+        Self::from_clients(config, |transport, contract| {
+            ETHDirectClient::new(
+                transport,
+                contract,
+                sender.operator_commit_eth_addr,
+                signer.clone(),
+                config.contracts.contract_addr,
+                config.eth_client.chain_id,
+                config.eth_client.gas_price_factor,
+            )
+            .with_transaction_type(config.eth_client.transaction_type)
+            .with_nonce_manager(nonce_manager.clone())
+            .with_gas_limits(config.eth_client.gas_limit_multiplier, &config.eth_client.default_gas_limits)
+        })
+    }
+}
 
-            EthereumGateway::Direct(ETHDirectClient::new(
+impl EthereumGateway<ReadOnly> {
+    /// Creates a gateway which is only able to read from the Ethereum nodes.
+    pub fn read_only_from_config(config: &config::Settings) -> Self {
+        Self::from_clients(config, |transport, contract| {
+            ETHDirectClient::new_read_only(
                 transport,
-                fluidex_contract(),
-                config.eth_sender.sender.operator_commit_eth_addr,
-                PrivateKeySigner::new(config.eth_sender.sender.operator_private_key),
+                contract,
                 config.contracts.contract_addr,
                 config.eth_client.chain_id,
                 config.eth_client.gas_price_factor,
-            ))
+            )
+        })
+    }
+}
+
+impl<S> EthereumGateway<S> {
+    fn from_clients(config: &config::Settings, new_client: impl Fn(Http, ethabi::Contract) -> ETHDirectClient<S>) -> Self {
+        let contract_errors = fluidex_errors();
+        let configured_client = |transport, contract| {
+            new_client(transport, contract)
+                .with_contract_errors(contract_errors.clone())
+                .with_batch_requests(config.eth_client.batch_requests)
+        };
+        if config.eth_client.web3_url.len() == 1 {
+            let transport = Http::new(&config.eth_client.web3_url()).unwrap();
+
+            EthereumGateway::Direct(configured_client(transport, fluidex_contract()))
         } else {
             let mut client = MultiplexerEthereumClient::new();
 
             let contract = fluidex_contract();
             for web3_url in config.eth_client.web3_url.iter() {
-                let transport = web3::transports::Http::new(web3_url).unwrap();
-                client = client.add_client(
-                    web3_url.clone(),
-                    ETHDirectClient::new(
-                        transport,
-                        contract.clone(),
-                        config.eth_sender.sender.operator_commit_eth_addr,
-                        PrivateKeySigner::new(config.eth_sender.sender.operator_private_key),
-                        config.contracts.contract_addr,
-                        config.eth_client.chain_id,
-                        config.eth_client.gas_price_factor,
-                    ),
-                );
+                let transport = Http::new(web3_url).unwrap();
+                client = client.add_client(web3_url.clone(), configured_client(transport, contract.clone()));
             }
             EthereumGateway::Multiplexed(client)
         }
@@ -101,7 +179,7 @@ macro_rules! delegate_call {
 
 }
 
-impl EthereumGateway {
+impl<S: EthereumSigner> EthereumGateway<S> {
     /// Returns the next *expected* nonce with respect to the transactions
     /// in the mempool.
     ///
@@ -120,18 +198,15 @@ impl EthereumGateway {
         delegate_call!(self.current_nonce())
     }
 
-    pub async fn block_number(&self) -> Result<U64, anyhow::Error> {
-        delegate_call!(self.block_number())
-    }
-
-    pub async fn get_gas_price(&self) -> Result<U256, anyhow::Error> {
-        delegate_call!(self.get_gas_price())
-    }
     /// Returns the account balance.
     pub async fn sender_eth_balance(&self) -> Result<U256, anyhow::Error> {
         delegate_call!(self.sender_eth_balance())
     }
 
+    pub async fn allowance(&self, token_address: Address, erc20_abi: ethabi::Contract) -> Result<U256, anyhow::Error> {
+        delegate_call!(self.allowance(token_address, erc20_abi))
+    }
+
     /// Signs the transaction given the previously encoded data.
     /// Fills in gas/nonce if not supplied inside options.
     pub async fn sign_prepared_tx(&self, data: Vec<u8>, options: Options) -> Result<SignedCallResult, anyhow::Error> {
@@ -140,6 +215,9 @@ impl EthereumGateway {
 
     /// Signs the transaction given the previously encoded data.
     /// Fills in gas/nonce if not supplied inside options.
+    ///
+    /// Unless set, the nonce is allocated by the nonce manager, and the signing fails with
+    /// `NonceError::TooManyInFlight` once `max_txs_in_flight` transactions are not mined.
     pub async fn sign_prepared_tx_for_addr(
         &self,
         data: Vec<u8>,
@@ -149,12 +227,71 @@ impl EthereumGateway {
         delegate_call!(self.sign_prepared_tx_for_addr(data, contract_addr, options))
     }
 
+    /// Signs the transaction replacing the stuck `replaced` transaction, with the same nonce
+    /// and the gas price set in `options`, which must be at least `min_replacement_fee` of the replaced one.
+    pub async fn sign_replacement_tx(
+        &self,
+        data: Vec<u8>,
+        options: Options,
+        replaced: &SignedCallResult,
+    ) -> Result<SignedCallResult, anyhow::Error> {
+        delegate_call!(self.sign_replacement_tx(data, options, replaced))
+    }
+
     /// Sends the transaction to the Ethereum blockchain.
     /// Transaction is expected to be encoded as the byte sequence.
     pub async fn send_raw_tx(&self, tx: Vec<u8>) -> Result<H256, anyhow::Error> {
         delegate_call!(self.send_raw_tx(tx))
     }
 
+    /// Sends the signed transaction, keeping track of its nonce.
+    ///
+    /// If the node rejects the nonce, the error has the `NonceError` context, and the transaction
+    /// is to be signed again with a new nonce (or a higher fee, if it's a replacement).
+    pub async fn send_signed_tx(&self, tx: &SignedCallResult) -> Result<H256, anyhow::Error> {
+        delegate_call!(self.send_signed_tx(tx))
+    }
+
+    /// Reserves the nonce of the transaction sent before (e.g. restored after a restart),
+    /// so it's not allocated to another transaction until this one is mined.
+    pub fn reserve_nonce(&self, nonce: U256) {
+        if let Some(manager) = self.nonce_manager() {
+            manager.reserve(nonce);
+        }
+    }
+
+    /// Frees the in-flight slot of the transaction once it is mined.
+    pub fn confirm_nonce(&self, nonce: U256) {
+        if let Some(manager) = self.nonce_manager() {
+            manager.confirm(nonce);
+        }
+    }
+
+    /// Returns the nonce of the signed transaction which is never going to be sent.
+    pub fn release_nonce(&self, nonce: U256) {
+        if let Some(manager) = self.nonce_manager() {
+            manager.release(nonce);
+        }
+    }
+
+    fn nonce_manager(&self) -> Option<&NonceManager> {
+        match self {
+            EthereumGateway::Direct(c) => c.nonce_manager(),
+            EthereumGateway::Multiplexed(c) => c.nonce_manager(),
+            EthereumGateway::Mock(_) => None,
+        }
+    }
+}
+
+impl<S> EthereumGateway<S> {
+    pub async fn block_number(&self) -> Result<U64, anyhow::Error> {
+        delegate_call!(self.block_number())
+    }
+
+    pub async fn get_gas_price(&self) -> Result<U256, anyhow::Error> {
+        delegate_call!(self.get_gas_price())
+    }
+
     /// Gets the Ethereum transaction receipt.
     pub async fn tx_receipt(&self, tx_hash: H256) -> Result<Option<TransactionReceipt>, anyhow::Error> {
         delegate_call!(self.tx_receipt(tx_hash))
@@ -169,12 +306,19 @@ impl EthereumGateway {
         delegate_call!(self.eth_balance(address))
     }
 
-    pub async fn allowance(&self, token_address: Address, erc20_abi: ethabi::Contract) -> Result<U256, anyhow::Error> {
-        delegate_call!(self.allowance(token_address, erc20_abi))
-    }
     pub async fn get_tx_status(&self, hash: H256) -> anyhow::Result<Option<ExecutedTxStatus>> {
         delegate_call!(self.get_tx_status(hash))
     }
+
+    /// Statuses of several transactions, requested in a single batch if the batch requests are enabled.
+    pub async fn get_tx_statuses(&self, hashes: &[H256]) -> anyhow::Result<Vec<Option<ExecutedTxStatus>>> {
+        delegate_call!(self.get_tx_statuses(hashes))
+    }
+
+    /// Gets the receipts of several transactions, in a single batch if the batch requests are enabled.
+    pub async fn tx_receipts(&self, tx_hashes: &[H256]) -> anyhow::Result<Vec<Option<TransactionReceipt>>> {
+        delegate_call!(self.tx_receipts(tx_hashes))
+    }
     /// Encodes the transaction data (smart contract method and its input) to the bytes
     /// without creating an actual transaction.
     pub async fn call_main_contract_function<R, A, B, P>(
@@ -218,6 +362,18 @@ impl EthereumGateway {
         delegate_call!(self.logs(filter))
     }
 
+    /// Logs matching each of the filters, in a single batch if the batch requests are enabled.
+    pub async fn logs_batch(&self, filters: Vec<Filter>) -> anyhow::Result<Vec<Vec<Log>>> {
+        delegate_call!(self.logs_batch(filters))
+    }
+
+    /// Executes the calls (target and data) with `eth_call`, in a single batch if the batch requests are enabled.
+    /// The outer error means none of the calls could be made, the failures of the individual calls
+    /// are returned in their results.
+    pub async fn eth_calls(&self, calls: Vec<(Address, Vec<u8>)>) -> anyhow::Result<Vec<anyhow::Result<Vec<u8>>>> {
+        delegate_call!(self.eth_calls(calls))
+    }
+
     pub fn encode_tx_data<P: Tokenize + Clone>(&self, func: &str, params: P) -> Vec<u8> {
         match self {
             EthereumGateway::Multiplexed(c) => c.encode_tx_data(func, params),
@@ -1,7 +1,12 @@
 pub mod clients;
 pub mod ethereum_gateway;
+pub mod nonce_manager;
+pub mod revert_error;
 pub mod token_inquirer;
-pub use clients::http_client::ETHDirectClient;
+pub use crate::config::configs::eth_client::DEFAULT_GAS_LIMIT_MULTIPLIER;
+pub use clients::http_client::{min_replacement_fee, ETHDirectClient};
 pub use clients::multiplexer::MultiplexerEthereumClient;
-pub use ethereum_gateway::{EthereumGateway, SignedCallResult};
-pub use token_inquirer::TokenInquirer;
+pub use ethereum_gateway::{EthereumGateway, ReadOnlyEthereumGateway, SignedCallResult};
+pub use nonce_manager::{NonceError, NonceManager, SendOutcome};
+pub use revert_error::RevertError;
+pub use token_inquirer::{TokenInfo, TokenInquirer};
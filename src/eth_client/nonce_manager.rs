@@ -0,0 +1,332 @@
+//! Local allocation of the operator account nonces.
+//!
+//! Asking the node for the pending nonce before every transaction is unreliable behind a cluster
+//! of nodes (e.g. Infura): the request may be routed to a node which hasn't seen the previously
+//! sent transaction yet, and the same nonce is given out twice. Instead, the nonces are allocated
+//! locally, and the node is only asked for the nonce of the latest *mined* block, which is used
+//! to forget the mined transactions.
+
+use crate::types::{H256, U256};
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Arc, Mutex};
+
+/// Error of the nonce allocation, or the nonce related error of the sent transaction.
+///
+/// The errors returned by `ETHDirectClient::send_signed_tx` carry it as the context, so it can be
+/// found with `error.downcast_ref::<NonceError>()`.
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum NonceError {
+    #[error("the maximum of {0} transactions are already in flight")]
+    TooManyInFlight(u64),
+    /// A transaction with this nonce is already mined, the transaction must be signed with a new nonce.
+    #[error("nonce {0} is too low")]
+    NonceTooLow(U256),
+    /// A transaction with this nonce is already pending. Unless a replacement was intended,
+    /// the transaction must be signed with a new nonce, otherwise the fee must be increased.
+    #[error("a transaction with nonce {0} is already pending")]
+    ReplacementUnderpriced(U256),
+}
+
+/// Outcome of `eth_sendRawTransaction`, as far as the nonce of the transaction is concerned.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SendOutcome {
+    /// The node accepted the transaction, or already has the very same one.
+    Accepted,
+    /// The node refused the transaction because of its nonce.
+    Nonce(NonceError),
+    /// The node refused the transaction for another reason, so it never reaches the network.
+    Rejected,
+    /// The request failed without the answer of the node (e.g. on a timeout), so the transaction
+    /// may still reach the network.
+    Unknown,
+}
+
+impl SendOutcome {
+    /// Classifies the result of sending the transaction with the provided nonce.
+    ///
+    /// Only the error responses of the node are conclusive, the nonce related ones are
+    /// recognized by their messages.
+    pub fn of(nonce: U256, result: &Result<H256, anyhow::Error>) -> Self {
+        let error = match result {
+            Ok(_) => return Self::Accepted,
+            Err(error) => error,
+        };
+        if !is_rpc_error(error) {
+            return Self::Unknown;
+        }
+        let message = format!("{:#}", error).to_lowercase();
+        if message.contains("already known") || message.contains("known transaction") {
+            Self::Accepted
+        } else if message.contains("nonce too low") {
+            Self::Nonce(NonceError::NonceTooLow(nonce))
+        } else if message.contains("replacement transaction underpriced") {
+            Self::Nonce(NonceError::ReplacementUnderpriced(nonce))
+        } else {
+            Self::Rejected
+        }
+    }
+}
+
+/// Whether the error is the JSON-RPC error response of the node.
+pub fn is_rpc_error(error: &anyhow::Error) -> bool {
+    error
+        .chain()
+        .any(|cause| matches!(cause.downcast_ref::<web3::Error>(), Some(web3::Error::Rpc(_))))
+}
+
+#[derive(Debug, Default)]
+struct NonceState {
+    /// Nonce of the next transaction. `None` until the first allocation.
+    next_nonce: Option<U256>,
+    /// Allocated nonces, mapped to whether the node accepted a transaction with this nonce.
+    in_flight: BTreeMap<U256, bool>,
+    /// Nonces below `next_nonce` whose transactions were never sent, to be allocated first.
+    gaps: BTreeSet<U256>,
+}
+
+impl NonceState {
+    /// Forgets the transactions mined according to the `current_nonce` of the node.
+    fn resync(&mut self, current_nonce: U256) {
+        self.in_flight = self.in_flight.split_off(&current_nonce);
+        self.gaps = self.gaps.split_off(&current_nonce);
+        let next_nonce = self.next_nonce.map_or(current_nonce, |next_nonce| next_nonce.max(current_nonce));
+        self.next_nonce = Some(next_nonce);
+    }
+
+    /// Returns the unsent nonce to the pool, shrinking `next_nonce` if it was the last one allocated.
+    fn release(&mut self, nonce: U256) {
+        if self.in_flight.get(&nonce) != Some(&false) {
+            return;
+        }
+        self.in_flight.remove(&nonce);
+        self.gaps.insert(nonce);
+        while let Some(next_nonce) = self.next_nonce {
+            let last_nonce = match next_nonce.checked_sub(U256::one()) {
+                Some(last_nonce) if self.gaps.remove(&last_nonce) => last_nonce,
+                _ => break,
+            };
+            self.next_nonce = Some(last_nonce);
+        }
+    }
+
+    /// Records the nonce as used by a transaction known to the network.
+    fn mark_used(&mut self, nonce: U256) {
+        self.gaps.remove(&nonce);
+        let next_nonce = nonce + 1;
+        self.next_nonce = Some(self.next_nonce.map_or(next_nonce, |current| current.max(next_nonce)));
+    }
+}
+
+/// Allocates the nonces of the operator transactions sequentially, and tracks the transactions
+/// in flight. Shared by all the clients of the gateway.
+#[derive(Debug, Clone)]
+pub struct NonceManager {
+    state: Arc<Mutex<NonceState>>,
+    max_txs_in_flight: u64,
+}
+
+impl NonceManager {
+    pub fn new(max_txs_in_flight: u64) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(NonceState::default())),
+            max_txs_in_flight,
+        }
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, NonceState> {
+        self.state.lock().expect("nonce manager lock is poisoned")
+    }
+
+    /// Allocates the nonce of the next transaction, given the nonce of the latest mined block.
+    ///
+    /// The nonces of the transactions which failed to be sent are reused first, so the
+    /// transactions sent later aren't stuck behind the gap.
+    pub fn allocate(&self, current_nonce: U256) -> Result<U256, NonceError> {
+        let mut state = self.state();
+        state.resync(current_nonce);
+        if state.in_flight.len() as u64 >= self.max_txs_in_flight {
+            return Err(NonceError::TooManyInFlight(self.max_txs_in_flight));
+        }
+
+        let nonce = match state.gaps.iter().next().copied() {
+            Some(nonce) => {
+                state.gaps.remove(&nonce);
+                nonce
+            }
+            None => {
+                let nonce = state.next_nonce.unwrap_or(current_nonce);
+                state.next_nonce = Some(nonce + 1);
+                nonce
+            }
+        };
+        state.in_flight.insert(nonce, false);
+        Ok(nonce)
+    }
+
+    /// Returns the nonce of the transaction which wasn't signed or sent.
+    pub fn release(&self, nonce: U256) {
+        self.state().release(nonce);
+    }
+
+    /// Records that the node accepted the transaction.
+    pub fn mark_sent(&self, nonce: U256) {
+        self.record_send(nonce, &SendOutcome::Accepted);
+    }
+
+    /// Reserves the nonce of the transaction which may be known to the network (e.g. restored after
+    /// a restart), so it's never allocated again until the transaction is mined.
+    pub fn reserve(&self, nonce: U256) {
+        self.record_send(nonce, &SendOutcome::Unknown);
+    }
+
+    /// Frees the in-flight slot of the mined transaction.
+    pub fn confirm(&self, nonce: U256) {
+        let mut state = self.state();
+        state.in_flight.remove(&nonce);
+        state.mark_used(nonce);
+    }
+
+    /// Updates the state according to the outcome of sending the transaction with the nonce.
+    ///
+    /// - accepted: the nonce stays reserved until the transaction is mined.
+    /// - "nonce too low": the nonce is used by a mined transaction and is never allocated again.
+    /// - "replacement transaction underpriced": the nonce is used by another pending transaction,
+    ///   and stays reserved until it is mined.
+    /// - rejected: the nonce of the transaction which never reaches the network is reused.
+    /// - unknown: the transaction may still be mined, so the nonce stays reserved until it's
+    ///   settled by sending the same transaction again or replacing it.
+    pub fn record_send(&self, nonce: U256, outcome: &SendOutcome) {
+        let mut state = self.state();
+        match outcome {
+            SendOutcome::Nonce(NonceError::NonceTooLow(_)) => {
+                state.in_flight.remove(&nonce);
+                state.mark_used(nonce);
+            }
+            SendOutcome::Rejected => state.release(nonce),
+            SendOutcome::Accepted | SendOutcome::Nonce(_) | SendOutcome::Unknown => {
+                state.in_flight.insert(nonce, true);
+                state.mark_used(nonce);
+            }
+        }
+    }
+
+    /// Amount of the allocated nonces whose transactions are not mined yet.
+    pub fn in_flight(&self) -> usize {
+        self.state().in_flight.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node_error(message: &str) -> Result<H256, anyhow::Error> {
+        Err(web3::Error::Rpc(jsonrpc_core::Error {
+            code: jsonrpc_core::ErrorCode::ServerError(-32000),
+            message: message.into(),
+            data: None,
+        })
+        .into())
+    }
+
+    fn send_outcome(nonce: u64, result: Result<H256, anyhow::Error>) -> SendOutcome {
+        SendOutcome::of(nonce.into(), &result)
+    }
+
+    #[test]
+    fn test_sequential_allocation() {
+        let manager = NonceManager::new(3);
+        assert_eq!(manager.allocate(5.into()), Ok(5.into()));
+        // The node behind a load balancer may still report the old nonce.
+        assert_eq!(manager.allocate(4.into()), Ok(6.into()));
+        assert_eq!(manager.allocate(5.into()), Ok(7.into()));
+        assert_eq!(manager.allocate(5.into()), Err(NonceError::TooManyInFlight(3)));
+
+        manager.mark_sent(5.into());
+        manager.confirm(5.into());
+        assert_eq!(manager.allocate(5.into()), Ok(8.into()));
+
+        // Mined transactions are forgotten.
+        assert_eq!(manager.in_flight(), 3);
+        assert_eq!(manager.allocate(8.into()), Ok(9.into()));
+        assert_eq!(manager.in_flight(), 2);
+    }
+
+    #[test]
+    fn test_reserved_nonces() {
+        let manager = NonceManager::new(3);
+        manager.reserve(5.into());
+        assert_eq!(manager.allocate(5.into()), Ok(6.into()));
+
+        // The node rejecting the reserved transaction sent again doesn't free its nonce.
+        let outcome = send_outcome(5, node_error("insufficient funds for gas * price + value"));
+        manager.record_send(5.into(), &outcome);
+        assert_eq!(manager.allocate(5.into()), Ok(7.into()));
+        assert_eq!(manager.in_flight(), 3);
+    }
+
+    #[test]
+    fn test_gaps_are_refilled() {
+        let manager = NonceManager::new(10);
+        for nonce in 0..4 {
+            assert_eq!(manager.allocate(0.into()), Ok(nonce.into()));
+        }
+        manager.mark_sent(0.into());
+        manager.mark_sent(2.into());
+
+        let outcome = send_outcome(1, node_error("insufficient funds for gas * price + value"));
+        assert_eq!(outcome, SendOutcome::Rejected);
+        manager.record_send(1.into(), &outcome);
+        assert_eq!(manager.allocate(0.into()), Ok(1.into()));
+
+        // Releasing the last allocated nonce doesn't leave a gap.
+        manager.release(3.into());
+        assert_eq!(manager.allocate(0.into()), Ok(3.into()));
+        assert_eq!(manager.allocate(0.into()), Ok(4.into()));
+
+        // Transactions which were accepted are never released.
+        manager.release(2.into());
+        assert_eq!(manager.allocate(0.into()), Ok(5.into()));
+    }
+
+    #[test]
+    fn test_nonce_errors() {
+        let manager = NonceManager::new(10);
+        assert_eq!(manager.allocate(0.into()), Ok(0.into()));
+        let outcome = send_outcome(0, node_error("nonce too low"));
+        assert_eq!(outcome, SendOutcome::Nonce(NonceError::NonceTooLow(0.into())));
+        manager.record_send(0.into(), &outcome);
+        assert_eq!(manager.in_flight(), 0);
+        assert_eq!(manager.allocate(0.into()), Ok(1.into()));
+
+        let outcome = send_outcome(1, node_error("replacement transaction underpriced"));
+        assert_eq!(outcome, SendOutcome::Nonce(NonceError::ReplacementUnderpriced(1.into())));
+        manager.record_send(1.into(), &outcome);
+        assert_eq!(manager.in_flight(), 1);
+        assert_eq!(manager.allocate(0.into()), Ok(2.into()));
+
+        // The pending transaction is forgotten once mined.
+        assert_eq!(manager.allocate(2.into()), Ok(3.into()));
+        assert_eq!(manager.in_flight(), 2);
+    }
+
+    #[test]
+    fn test_unknown_outcome_keeps_nonce() {
+        let manager = NonceManager::new(10);
+        assert_eq!(manager.allocate(0.into()), Ok(0.into()));
+
+        // The transaction may have reached the node before the request timed out.
+        let timeout = Err(web3::Error::Transport("request timed out".into()).into());
+        let outcome = send_outcome(0, timeout);
+        assert_eq!(outcome, SendOutcome::Unknown);
+        manager.record_send(0.into(), &outcome);
+        manager.release(0.into());
+        assert_eq!(manager.allocate(0.into()), Ok(1.into()));
+
+        // Another node already has the transaction sent through the timed out one.
+        assert_eq!(send_outcome(0, node_error("already known")), SendOutcome::Accepted);
+        // Plain errors with the same message are not the responses of the node.
+        assert_eq!(send_outcome(0, Err(anyhow::anyhow!("nonce too low"))), SendOutcome::Unknown);
+        assert_eq!(manager.in_flight(), 2);
+    }
+}
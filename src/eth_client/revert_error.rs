@@ -0,0 +1,184 @@
+//! Decoding of the data the reverted transactions return.
+//!
+//! Besides the `require`/`revert` messages (`Error(string)`), Solidity 0.8 reverts with
+//! `Panic(uint256)` on the failed assertions and arithmetic errors, and with the custom errors
+//! declared by the contract (e.g. `error InvalidBlock(uint256 blockId)`).
+
+use crate::contracts::ContractError;
+use crate::types::U256;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Selector of `Error(string)`, which the `require` and `revert` statements with a message revert with.
+const ERROR_STRING_SELECTOR: [u8; 4] = [0x08, 0xc3, 0x79, 0xa0];
+/// Selector of `Panic(uint256)`.
+const PANIC_SELECTOR: [u8; 4] = [0x4e, 0x48, 0x7b, 0x71];
+
+/// Argument of the custom error.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ErrorArg {
+    pub name: String,
+    pub value: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum RevertError {
+    /// `Error(string)`.
+    Error { message: String },
+    /// `Panic(uint256)` with the meaning of the code.
+    Panic { code: U256, meaning: String },
+    /// Custom error of the contract.
+    Custom { name: String, args: Vec<ErrorArg> },
+}
+
+impl RevertError {
+    /// Decodes the revert data, matching the custom errors against `contract_errors`.
+    /// Returns `None` if the data is empty or isn't recognized.
+    pub fn decode(revert_data: &[u8], contract_errors: &[ContractError]) -> Option<Self> {
+        if revert_data.len() < 4 {
+            return None;
+        }
+        let (selector, encoded) = revert_data.split_at(4);
+        if selector == ERROR_STRING_SELECTOR {
+            let message = decode_single(ethabi::ParamType::String, encoded)?.to_string()?;
+            return Some(Self::Error { message });
+        }
+        if selector == PANIC_SELECTOR {
+            let code = decode_single(ethabi::ParamType::Uint(256), encoded)?.to_uint()?;
+            return Some(Self::Panic {
+                code,
+                meaning: panic_meaning(code).to_string(),
+            });
+        }
+
+        let error = contract_errors.iter().find(|error| error.selector() == selector)?;
+        let tokens = ethabi::decode(&error.param_types(), encoded).ok()?;
+        let args = error
+            .inputs
+            .iter()
+            .zip(tokens)
+            .map(|(param, token)| ErrorArg {
+                name: param.name.clone(),
+                value: format_token(&token),
+            })
+            .collect();
+        Some(Self::Custom {
+            name: error.name.clone(),
+            args,
+        })
+    }
+}
+
+impl fmt::Display for RevertError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Error { message } => write!(f, "{}", message),
+            Self::Panic { code, meaning } => write!(f, "panic {:#x}: {}", code, meaning),
+            Self::Custom { name, args } => {
+                let args: Vec<String> = args.iter().map(|arg| format!("{}: {}", arg.name, arg.value)).collect();
+                write!(f, "{}({})", name, args.join(", "))
+            }
+        }
+    }
+}
+
+fn decode_single(kind: ethabi::ParamType, encoded: &[u8]) -> Option<ethabi::Token> {
+    ethabi::decode(&[kind], encoded).ok()?.into_iter().next()
+}
+
+/// Formats the token the way it's written in Solidity, unlike its `Display` implementation
+/// which omits the `0x` prefixes and prints the numbers in hex.
+fn format_token(token: &ethabi::Token) -> String {
+    use ethabi::Token;
+    match token {
+        Token::Address(address) => format!("{:#x}", address),
+        Token::Bytes(bytes) | Token::FixedBytes(bytes) => format!("0x{}", hex::encode(bytes)),
+        Token::Uint(value) => value.to_string(),
+        // Negative numbers are in the two's complement.
+        Token::Int(value) if value.bit(255) => format!("-{}", (!*value).overflowing_add(U256::one()).0),
+        Token::Int(value) => value.to_string(),
+        Token::Bool(value) => value.to_string(),
+        Token::String(value) => format!("{:?}", value),
+        Token::Array(tokens) | Token::FixedArray(tokens) => {
+            format!("[{}]", tokens.iter().map(format_token).collect::<Vec<_>>().join(", "))
+        }
+        Token::Tuple(tokens) => format!("({})", tokens.iter().map(format_token).collect::<Vec<_>>().join(", ")),
+    }
+}
+
+/// Meaning of the `Panic(uint256)` codes, according to the Solidity documentation.
+fn panic_meaning(code: U256) -> &'static str {
+    if code > U256::from(u8::MAX) {
+        return "unknown panic code";
+    }
+    match code.low_u64() {
+        0x00 => "generic compiler inserted panic",
+        0x01 => "assertion failed",
+        0x11 => "arithmetic overflow or underflow",
+        0x12 => "division or modulo by zero",
+        0x21 => "conversion to an invalid enum value",
+        0x22 => "incorrectly encoded storage byte array",
+        0x31 => "pop on an empty array",
+        0x32 => "array index out of bounds",
+        0x41 => "too much memory allocated",
+        0x51 => "call to a zero-initialized internal function",
+        _ => "unknown panic code",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Address;
+    use ethabi::{Param, ParamType, Token};
+
+    #[test]
+    fn test_decode_builtin_errors() {
+        let mut data = ERROR_STRING_SELECTOR.to_vec();
+        data.extend(ethabi::encode(&[Token::String("invalid block".into())]));
+        assert_eq!(
+            RevertError::decode(&data, &[]),
+            Some(RevertError::Error {
+                message: "invalid block".into()
+            })
+        );
+
+        let mut data = PANIC_SELECTOR.to_vec();
+        data.extend(ethabi::encode(&[Token::Uint(0x11.into())]));
+        let panic = RevertError::decode(&data, &[]).unwrap();
+        assert_eq!(panic.to_string(), "panic 0x11: arithmetic overflow or underflow");
+
+        assert_eq!(RevertError::decode(&[], &[]), None);
+        assert_eq!(RevertError::decode(&[1, 2, 3, 4], &[]), None);
+    }
+
+    #[test]
+    fn test_decode_custom_error() {
+        let error = ContractError {
+            name: "InsufficientBalance".into(),
+            inputs: vec![
+                Param {
+                    name: "account".into(),
+                    kind: ParamType::Address,
+                },
+                Param {
+                    name: "delta".into(),
+                    kind: ParamType::Int(256),
+                },
+            ],
+        };
+        // keccak256("InsufficientBalance(address,int256)")
+        assert_eq!(hex::encode(error.selector()), "829f9811");
+
+        let mut data = error.selector().to_vec();
+        // -5 in the two's complement.
+        let delta = !U256::from(4);
+        data.extend(ethabi::encode(&[Token::Address(Address::from_low_u64_be(1)), Token::Int(delta)]));
+        let decoded = RevertError::decode(&data, &[error]).unwrap();
+        assert_eq!(
+            decoded.to_string(),
+            "InsufficientBalance(account: 0x0000000000000000000000000000000000000001, delta: -5)"
+        );
+    }
+}
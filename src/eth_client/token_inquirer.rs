@@ -1,5 +1,12 @@
 use crate::config;
-use crate::types::U256;
+use crate::eth_client::clients::http_client::revert_data;
+use crate::eth_client::ethereum_gateway::EthereumGateway;
+use crate::eth_signer::ReadOnly;
+use crate::types::{Address, U256};
+use anyhow::{anyhow, ensure, Context};
+use ethabi::{ParamType, Token};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
 const MIN_ABI: &str = r#"[
   {
@@ -32,51 +39,500 @@ const MIN_ABI: &str = r#"[
   }
 ]"#;
 
+const MULTICALL3_ABI: &str = r#"[
+  {
+    "name":"aggregate3",
+    "inputs":[{"name":"calls","type":"tuple[]","components":[
+      {"name":"target","type":"address"},
+      {"name":"allowFailure","type":"bool"},
+      {"name":"callData","type":"bytes"}
+    ]}],
+    "outputs":[{"name":"returnData","type":"tuple[]","components":[
+      {"name":"success","type":"bool"},
+      {"name":"returnData","type":"bytes"}
+    ]}],
+    "type":"function",
+    "stateMutability":"payable"
+  }
+]"#;
+
+/// Address of Multicall3, which is deployed at the same address on most of the networks.
+pub const MULTICALL3_ADDRESS: &str = "cA11bde05977b3631167028862bE2a173976CA11";
+
+/// Token functions called for each token, in the order of the calls.
+const TOKEN_INFO_FUNCTIONS: [&str; 4] = ["symbol", "name", "decimals", "totalSupply"];
+
+/// Decimals of the tokens which don't implement `decimals`.
+const DEFAULT_DECIMALS: u8 = 18;
+
+/// Queries the info of the ERC-20 tokens. It only reads from the Ethereum nodes,
+/// so it works with any gateway, read-only by default.
 #[derive(Debug, Clone)]
-pub struct TokenInquirer {
-    pub web3: web3::Web3<web3::transports::Http>,
+pub struct TokenInquirer<S = ReadOnly> {
+    gateway: EthereumGateway<S>,
+    erc20: ethabi::Contract,
+    multicall: ethabi::Contract,
+    /// Address of the Multicall3 contract. If it's `None` or the contract is not deployed,
+    /// the tokens are queried with the separate calls.
+    multicall_addr: Option<Address>,
 }
 
-#[derive(Debug, Clone)]
+/// Optional fields of the ERC-20 tokens.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenField {
+    Symbol,
+    Name,
+    Decimals,
+}
+
+/// Reason why the field of `TokenInfo` is set to the default value.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, thiserror::Error)]
+#[serde(rename_all = "snake_case")]
+pub enum DefaultReason {
+    /// The call reverted or returned nothing, i.e. the token doesn't implement the function.
+    #[error("not implemented")]
+    NotImplemented,
+    /// The returned value couldn't be decoded.
+    #[error("malformed value: {0}")]
+    Malformed(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct TokenInfo {
+    /// Empty if defaulted.
     pub symbol: String,
+    /// Empty if defaulted.
     pub name: String,
+    /// 18 if defaulted.
     pub decimals: u8,
     pub total_supply: U256,
+    /// Fields which are set to the default values, since the token failed to provide them.
+    pub defaulted_fields: BTreeMap<TokenField, DefaultReason>,
 }
 
 impl TokenInquirer {
     pub fn from_config(config: &config::Settings) -> Self {
-        let transport = web3::transports::Http::new(&config.eth_client.web3_url()).unwrap();
-        let web3 = web3::Web3::new(transport);
-        Self { web3 }
-    }
-
-    pub async fn get_token_info(&self, contract_addr_no_prefix: String) -> Result<TokenInfo, anyhow::Error> {
-        let contract_addr: web3::types::Address = contract_addr_no_prefix.parse()?;
-        let contract = web3::contract::Contract::from_json(self.web3.eth(), contract_addr, MIN_ABI.as_bytes())?;
-
-        let symbol: String = contract
-            .query("symbol", (), None, web3::contract::Options::default(), None)
-            .await
-            .unwrap_or_else(|_| "".to_string());
-        let name: String = contract
-            .query("name", (), None, web3::contract::Options::default(), None)
-            .await
-            .unwrap_or_else(|_| "".to_string());
-        let decimals: u8 = contract
-            .query("decimals", (), None, web3::contract::Options::default(), None)
-            .await
-            .unwrap_or(18);
-        let total_supply: U256 = contract
-            .query("totalSupply", (), None, web3::contract::Options::default(), None)
-            .await?;
-
-        Ok(TokenInfo {
-            symbol,
-            name,
-            decimals,
-            total_supply,
+        Self::new(EthereumGateway::read_only_from_config(config))
+    }
+}
+
+impl<S> TokenInquirer<S> {
+    pub fn new(gateway: EthereumGateway<S>) -> Self {
+        Self {
+            gateway,
+            erc20: ethabi::Contract::load(MIN_ABI.as_bytes()).expect("erc20 abi"),
+            multicall: ethabi::Contract::load(MULTICALL3_ABI.as_bytes()).expect("multicall3 abi"),
+            multicall_addr: Some(MULTICALL3_ADDRESS.parse().expect("multicall3 address")),
+        }
+    }
+
+    /// Sets the address of the Multicall3 contract, `None` disables the aggregation of the calls.
+    pub fn with_multicall_addr(mut self, multicall_addr: Option<Address>) -> Self {
+        self.multicall_addr = multicall_addr;
+        self
+    }
+
+    pub async fn get_token_info(&self, token: Address) -> Result<TokenInfo, anyhow::Error> {
+        let mut infos = self.get_tokens_info(&[token]).await;
+        infos.pop().expect("info is returned for each token")
+    }
+
+    /// Queries the info of the tokens, in the same order.
+    ///
+    /// The calls are aggregated into a single `eth_call` to Multicall3. If it's not deployed,
+    /// the calls are sent separately (in a single JSON-RPC batch if the gateway is configured to).
+    ///
+    /// The info of the token fails to be queried if any of its calls fails to be made, or if it doesn't
+    /// provide the total supply. The other fields are defaulted if the token doesn't implement them,
+    /// which is reported in the info.
+    pub async fn get_tokens_info(&self, tokens: &[Address]) -> Vec<Result<TokenInfo, anyhow::Error>> {
+        let calls: Vec<(Address, Vec<u8>)> = tokens
+            .iter()
+            .flat_map(|token| TOKEN_INFO_FUNCTIONS.iter().map(move |function| (*token, function)))
+            .map(|(token, function)| (token, self.erc20_call_data(function)))
+            .collect();
+        let mut results = match self.call(calls).await {
+            Ok(results) => results.into_iter(),
+            Err(error) => {
+                return tokens
+                    .iter()
+                    .map(|token| Err(anyhow!("Unable to call the token {:#x}: {:#}", token, error)))
+                    .collect()
+            }
+        };
+
+        tokens
+            .iter()
+            .map(|token| {
+                let token_results: Vec<_> = results.by_ref().take(TOKEN_INFO_FUNCTIONS.len()).collect();
+                let token_results = token_results.into_iter().collect::<Result<Vec<_>, _>>()?;
+                decode_token_info(*token, &token_results)
+            })
+            .collect()
+    }
+
+    fn erc20_call_data(&self, function: &str) -> Vec<u8> {
+        let function = self.erc20.function(function).expect("erc20 function");
+        function.encode_input(&[]).expect("erc20 function without inputs")
+    }
+
+    /// Outputs of the calls, `None` for the reverted ones. Fails if the calls couldn't be sent at all.
+    async fn call(&self, calls: Vec<(Address, Vec<u8>)>) -> Result<Vec<Result<Option<Vec<u8>>, anyhow::Error>>, anyhow::Error> {
+        if let Some(multicall_addr) = self.multicall_addr {
+            match self.aggregate(multicall_addr, calls.clone()).await {
+                Ok(results) => return Ok(results.into_iter().map(Ok).collect()),
+                Err(error) => log::debug!("Unable to aggregate the calls with Multicall3, calling separately: {:#}", error),
+            }
+        }
+        let targets: Vec<Address> = calls.iter().map(|(target, _)| *target).collect();
+        let results = self.gateway.eth_calls(calls).await?;
+        Ok(targets
+            .into_iter()
+            .zip(results)
+            .map(|(target, result)| match result {
+                Ok(output) => Ok(Some(output)),
+                // The node executed the call, and reported that it reverted. The other errors of the node
+                // (e.g. the rate limit or the missing state) don't tell anything about the token.
+                Err(error) if error.downcast_ref::<web3::Error>().and_then(revert_data).is_some() => Ok(None),
+                Err(error) => Err(error.context(format!("Unable to call the token {:#x}", target))),
+            })
+            .collect())
+    }
+
+    async fn aggregate(&self, multicall_addr: Address, calls: Vec<(Address, Vec<u8>)>) -> Result<Vec<Option<Vec<u8>>>, anyhow::Error> {
+        let calls_count = calls.len();
+        let data = self.aggregate_call_data(calls);
+        let output = self
+            .gateway
+            .eth_calls(vec![(multicall_addr, data)])
+            .await?
+            .pop()
+            .expect("result is returned for each call")?;
+        self.decode_aggregate_output(&output, calls_count)
+    }
+
+    fn aggregate_call_data(&self, calls: Vec<(Address, Vec<u8>)>) -> Vec<u8> {
+        let calls = calls
+            .into_iter()
+            .map(|(target, data)| Token::Tuple(vec![Token::Address(target), Token::Bool(true), Token::Bytes(data)]))
+            .collect();
+        let function = self.multicall.function("aggregate3").expect("aggregate3 function");
+        function.encode_input(&[Token::Array(calls)]).expect("aggregate3 input")
+    }
+
+    fn decode_aggregate_output(&self, output: &[u8], calls_count: usize) -> Result<Vec<Option<Vec<u8>>>, anyhow::Error> {
+        // The call to an address without a contract succeeds with the empty output, and fails to be decoded.
+        let function = self.multicall.function("aggregate3").expect("aggregate3 function");
+        let tokens = function.decode_output(output).context("Unexpected output of Multicall3")?;
+        let results = match tokens.into_iter().next() {
+            Some(Token::Array(results)) => results,
+            _ => return Err(anyhow!("Unexpected output of Multicall3")),
+        };
+        ensure!(
+            results.len() == calls_count,
+            "Multicall3 returned {} results for {} calls",
+            results.len(),
+            calls_count
+        );
+
+        Ok(results
+            .into_iter()
+            .map(|result| match result {
+                Token::Tuple(fields) => match fields.as_slice() {
+                    [Token::Bool(true), Token::Bytes(data)] => Some(data.clone()),
+                    _ => None,
+                },
+                _ => None,
+            })
+            .collect())
+    }
+}
+
+/// Decodes the outputs of the token calls, `None` for the reverted calls.
+fn decode_token_info(token: Address, results: &[Option<Vec<u8>>]) -> Result<TokenInfo, anyhow::Error> {
+    ensure!(
+        results.len() == TOKEN_INFO_FUNCTIONS.len(),
+        "Missing results of the calls to the token {:#x}",
+        token
+    );
+    // The reverted calls are handled the same as the calls which returned nothing.
+    let output = |index: usize| results[index].as_deref().unwrap_or_default();
+
+    let mut defaulted_fields = BTreeMap::new();
+    let symbol = or_default(&mut defaulted_fields, TokenField::Symbol, decode_string(output(0)), String::new());
+    let name = or_default(&mut defaulted_fields, TokenField::Name, decode_string(output(1)), String::new());
+    let decimals = decode_uint(output(2)).and_then(|decimals| {
+        if decimals > U256::from(u8::MAX) {
+            return Err(DefaultReason::Malformed(format!("decimals {} don't fit into uint8", decimals)));
+        }
+        Ok(decimals.low_u32() as u8)
+    });
+    let decimals = or_default(&mut defaulted_fields, TokenField::Decimals, decimals, DEFAULT_DECIMALS);
+    let total_supply =
+        decode_uint(output(3)).map_err(|reason| anyhow!("Unable to get the total supply of the token {:#x}: {}", token, reason))?;
+
+    Ok(TokenInfo {
+        symbol,
+        name,
+        decimals,
+        total_supply,
+        defaulted_fields,
+    })
+}
+
+fn or_default<T>(
+    defaulted_fields: &mut BTreeMap<TokenField, DefaultReason>,
+    field: TokenField,
+    value: Result<T, DefaultReason>,
+    default: T,
+) -> T {
+    value.unwrap_or_else(|reason| {
+        defaulted_fields.insert(field, reason);
+        default
+    })
+}
+
+/// Decodes the `string` output, or the `bytes32` one padded with zeros (e.g. the symbol of MKR).
+fn decode_string(output: &[u8]) -> Result<String, DefaultReason> {
+    let bytes = match output.len() {
+        0 => return Err(DefaultReason::NotImplemented),
+        // The `string` output takes at least 64 bytes, for the offset and the length.
+        32 => {
+            let len = output.iter().rposition(|byte| *byte != 0).map_or(0, |last| last + 1);
+            output[..len].to_vec()
+        }
+        _ => match ethabi::decode(&[ParamType::Bytes], output) {
+            Ok(tokens) => tokens.into_iter().next().and_then(Token::to_bytes).unwrap_or_default(),
+            Err(error) => return Err(DefaultReason::Malformed(error.to_string())),
+        },
+    };
+    String::from_utf8(bytes).map_err(|error| DefaultReason::Malformed(error.to_string()))
+}
+
+fn decode_uint(output: &[u8]) -> Result<U256, DefaultReason> {
+    if output.is_empty() {
+        return Err(DefaultReason::NotImplemented);
+    }
+    match ethabi::decode(&[ParamType::Uint(256)], output) {
+        Ok(tokens) => Ok(tokens.into_iter().next().and_then(Token::to_uint).unwrap_or_default()),
+        Err(error) => Err(DefaultReason::Malformed(error.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eth_client::clients::mock::MockEthereum;
+
+    fn inquirer() -> TokenInquirer {
+        TokenInquirer::new(EthereumGateway::Mock(MockEthereum::default()))
+    }
+
+    fn string_output(value: &str) -> Option<Vec<u8>> {
+        Some(ethabi::encode(&[Token::String(value.into())]))
+    }
+
+    fn uint_output(value: u64) -> Option<Vec<u8>> {
+        Some(ethabi::encode(&[Token::Uint(value.into())]))
+    }
+
+    fn node_error(code: i64, message: &str) -> web3::Error {
+        web3::Error::Rpc(jsonrpc_core::Error {
+            code: jsonrpc_core::ErrorCode::ServerError(code),
+            message: message.into(),
+            data: None,
         })
     }
+
+    fn reverted() -> web3::Error {
+        node_error(3, "execution reverted")
+    }
+
+    /// Calls of the token functions, in the order of `TOKEN_INFO_FUNCTIONS`.
+    fn token_calls(inquirer: &TokenInquirer, token: Address) -> Vec<(Address, Vec<u8>)> {
+        TOKEN_INFO_FUNCTIONS
+            .iter()
+            .map(|function| (token, inquirer.erc20_call_data(function)))
+            .collect()
+    }
+
+    #[test]
+    fn test_aggregate_encoding() {
+        let inquirer = inquirer();
+        let token = Address::repeat_byte(0x11);
+        let data = inquirer.aggregate_call_data(vec![(token, inquirer.erc20_call_data("symbol"))]);
+        let expected = "82ad56cb\
+            0000000000000000000000000000000000000000000000000000000000000020\
+            0000000000000000000000000000000000000000000000000000000000000001\
+            0000000000000000000000000000000000000000000000000000000000000020\
+            0000000000000000000000001111111111111111111111111111111111111111\
+            0000000000000000000000000000000000000000000000000000000000000001\
+            0000000000000000000000000000000000000000000000000000000000000060\
+            0000000000000000000000000000000000000000000000000000000000000004\
+            95d89b4100000000000000000000000000000000000000000000000000000000";
+        assert_eq!(hex::encode(data), expected);
+
+        let results = vec![
+            Token::Tuple(vec![Token::Bool(true), Token::Bytes(vec![1])]),
+            Token::Tuple(vec![Token::Bool(false), Token::Bytes(vec![])]),
+        ];
+        let output = ethabi::encode(&[Token::Array(results)]);
+        assert_eq!(inquirer.decode_aggregate_output(&output, 2).unwrap(), vec![Some(vec![1]), None]);
+        assert!(inquirer.decode_aggregate_output(&output, 3).is_err());
+        // No contract is deployed at the address.
+        assert!(inquirer.decode_aggregate_output(&[], 2).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_tokens_info_with_multicall() {
+        let mock = MockEthereum::default();
+        let inquirer: TokenInquirer = TokenInquirer::new(EthereumGateway::Mock(mock.clone()));
+        let (usdt, broken) = (Address::repeat_byte(0x11), Address::repeat_byte(0x22));
+        let outputs = vec![
+            string_output("USDT"),
+            string_output("Tether USD"),
+            uint_output(6),
+            uint_output(1000),
+            // The token which doesn't provide the total supply fails alone.
+            string_output("BRK"),
+            None,
+            None,
+            None,
+        ];
+        let results = outputs
+            .into_iter()
+            .map(|output| Token::Tuple(vec![Token::Bool(output.is_some()), Token::Bytes(output.unwrap_or_default())]))
+            .collect();
+        let calls = [token_calls(&inquirer, usdt), token_calls(&inquirer, broken)].concat();
+        let multicall_addr = MULTICALL3_ADDRESS.parse().unwrap();
+        // The separate calls are not answered, so the info is only queried through Multicall3.
+        mock.call_results.write().await.insert(
+            (multicall_addr, inquirer.aggregate_call_data(calls)),
+            Ok(ethabi::encode(&[Token::Array(results)])),
+        );
+
+        let infos = inquirer.get_tokens_info(&[usdt, broken]).await;
+        assert_eq!(infos.len(), 2);
+        let info = infos[0].as_ref().unwrap();
+        assert_eq!((info.symbol.as_str(), info.name.as_str(), info.decimals), ("USDT", "Tether USD", 6));
+        assert_eq!(info.total_supply, 1000.into());
+        assert!(info.defaulted_fields.is_empty());
+        assert!(infos[1].is_err());
+    }
+
+    #[tokio::test]
+    async fn test_tokens_info_with_separate_calls() {
+        let mock = MockEthereum::default();
+        // Multicall3 is not deployed, so its call returns nothing.
+        let inquirer: TokenInquirer = TokenInquirer::new(EthereumGateway::Mock(mock.clone()));
+        let (usdt, unnamed, unreachable, unsynced) = (
+            Address::repeat_byte(0x11),
+            Address::repeat_byte(0x22),
+            Address::repeat_byte(0x33),
+            Address::repeat_byte(0x44),
+        );
+        let results = vec![
+            Ok(string_output("USDT")),
+            Ok(string_output("Tether USD")),
+            Ok(uint_output(6)),
+            Ok(uint_output(1000)),
+            Ok(string_output("UNN")),
+            Err(reverted()),
+            Ok(uint_output(8)),
+            Ok(uint_output(1)),
+            Ok(string_output("UNR")),
+            Ok(string_output("Unreachable")),
+            Ok(uint_output(18)),
+            Err(web3::Error::Transport("request timed out".into())),
+            Ok(string_output("UNS")),
+            Err(node_error(-32000, "header not found")),
+            Ok(uint_output(18)),
+            Ok(uint_output(1)),
+        ];
+        let calls = [
+            token_calls(&inquirer, usdt),
+            token_calls(&inquirer, unnamed),
+            token_calls(&inquirer, unreachable),
+            token_calls(&inquirer, unsynced),
+        ]
+        .concat();
+        let mut call_results = mock.call_results.write().await;
+        for (call, result) in calls.into_iter().zip(results) {
+            call_results.insert(call, result.map(Option::unwrap));
+        }
+        drop(call_results);
+
+        let infos = inquirer.get_tokens_info(&[usdt, unnamed, unreachable, unsynced]).await;
+        assert_eq!(infos.len(), 4);
+        assert_eq!(infos[0].as_ref().unwrap().symbol, "USDT");
+        // The reverted call means that the token doesn't implement the function.
+        let info = infos[1].as_ref().unwrap();
+        assert_eq!((info.symbol.as_str(), info.name.as_str(), info.decimals), ("UNN", "", 8));
+        assert_eq!(info.defaulted_fields[&TokenField::Name], DefaultReason::NotImplemented);
+        // The call which failed to be made fails the token.
+        let error = infos[2].as_ref().unwrap_err();
+        assert!(format!("{:#}", error).contains("request timed out"), "{:#}", error);
+        // Only the reverts of the calls default the fields, the other errors of the node fail the token.
+        let error = infos[3].as_ref().unwrap_err();
+        assert!(format!("{:#}", error).contains("header not found"), "{:#}", error);
+
+        // No contract is deployed at the address.
+        assert!(inquirer.get_token_info(Address::repeat_byte(0x55)).await.is_err());
+    }
+
+    #[test]
+    fn test_token_info_decoding() {
+        let token = Address::repeat_byte(0x11);
+        let results = vec![
+            Some(ethabi::encode(&[Token::String("TKN".into())])),
+            None,
+            Some(Vec::new()),
+            Some(ethabi::encode(&[Token::Uint(1000.into())])),
+        ];
+        let info = decode_token_info(token, &results).unwrap();
+        assert_eq!(info.symbol, "TKN");
+        assert_eq!(info.name, "");
+        assert_eq!(info.decimals, 18);
+        assert_eq!(info.total_supply, 1000.into());
+        let defaulted: Vec<_> = info.defaulted_fields.into_iter().collect();
+        assert_eq!(
+            defaulted,
+            vec![
+                (TokenField::Name, DefaultReason::NotImplemented),
+                (TokenField::Decimals, DefaultReason::NotImplemented)
+            ]
+        );
+
+        assert!(decode_token_info(token, &results[..3]).is_err());
+        let mut results = results;
+        results[3] = None;
+        assert!(decode_token_info(token, &results).is_err());
+    }
+
+    #[test]
+    fn test_non_standard_outputs() {
+        // MKR returns the symbol as `bytes32`.
+        let mut mkr = [0u8; 32];
+        mkr[..3].copy_from_slice(b"MKR");
+        assert_eq!(decode_string(&mkr), Ok("MKR".to_string()));
+
+        let invalid_utf8 = ethabi::encode(&[Token::Bytes(vec![0xff, 0xfe])]);
+        assert!(matches!(decode_string(&invalid_utf8), Err(DefaultReason::Malformed(_))));
+        assert!(matches!(decode_string(&[1, 2, 3]), Err(DefaultReason::Malformed(_))));
+
+        let token = Address::repeat_byte(0x11);
+        let results = vec![
+            Some(mkr.to_vec()),
+            Some(invalid_utf8),
+            Some(ethabi::encode(&[Token::Uint(256.into())])),
+            Some(ethabi::encode(&[Token::Uint(1.into())])),
+        ];
+        let info = decode_token_info(token, &results).unwrap();
+        assert_eq!(info.symbol, "MKR");
+        assert_eq!(info.decimals, 18);
+        assert!(!info.defaulted_fields.contains_key(&TokenField::Symbol));
+        assert!(matches!(info.defaulted_fields[&TokenField::Name], DefaultReason::Malformed(_)));
+        assert!(matches!(info.defaulted_fields[&TokenField::Decimals], DefaultReason::Malformed(_)));
+    }
 }
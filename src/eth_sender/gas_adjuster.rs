@@ -0,0 +1,152 @@
+//! Gas adjuster limits the gas price of the operator transactions, so the operator doesn't
+//! overpay during the short spikes of the network gas price.
+//!
+//! The network gas price is sampled every `sample_interval`, and every `update_interval` the limit
+//! is set to the average of the samples gathered during the last `update_interval`, multiplied by
+//! `scale_factor`. Until the first update, the `default` limit is used.
+
+use crate::config::configs::eth_sender::GasLimit;
+use crate::eth_client::EthereumGateway;
+use crate::types::U256;
+use std::collections::VecDeque;
+use std::time::Instant;
+
+/// Rolling window of the sampled gas prices.
+#[derive(Debug)]
+struct GasStatistics {
+    samples: VecDeque<U256>,
+    max_samples: usize,
+    sum: U256,
+}
+
+impl GasStatistics {
+    fn new(max_samples: usize) -> Self {
+        Self {
+            samples: VecDeque::with_capacity(max_samples + 1),
+            max_samples,
+            sum: U256::zero(),
+        }
+    }
+
+    fn add_sample(&mut self, gas_price: U256) {
+        self.samples.push_back(gas_price);
+        self.sum = self.sum.saturating_add(gas_price);
+        if self.samples.len() > self.max_samples {
+            let oldest = self.samples.pop_front().expect("samples are not empty");
+            self.sum -= oldest;
+        }
+    }
+
+    fn average(&self) -> Option<U256> {
+        if self.samples.is_empty() {
+            return None;
+        }
+        Some(self.sum / self.samples.len())
+    }
+}
+
+#[derive(Debug)]
+pub struct GasAdjuster {
+    config: GasLimit,
+    statistics: GasStatistics,
+    price_limit: U256,
+    last_update: Instant,
+}
+
+impl GasAdjuster {
+    pub fn new(config: GasLimit) -> Self {
+        // The window covers the samples gathered during a single update interval.
+        let max_samples = (config.update_interval / config.sample_interval.max(1)).max(1) as usize;
+        Self {
+            statistics: GasStatistics::new(max_samples),
+            price_limit: config.default.into(),
+            last_update: Instant::now(),
+            config,
+        }
+    }
+
+    /// The current limit of the gas price.
+    pub fn price_limit(&self) -> U256 {
+        self.price_limit
+    }
+
+    /// Samples the network gas price, and updates the limit once `update_interval` has passed
+    /// since the last update. Expected to be called every `sample_interval`.
+    pub async fn keep_updated<S>(&mut self, gateway: &EthereumGateway<S>) {
+        match gateway.get_gas_price().await {
+            Ok(gas_price) => self.statistics.add_sample(gas_price),
+            Err(error) => log::warn!("Unable to sample the network gas price: {:#}", error),
+        }
+        if self.last_update.elapsed() >= self.config.update_interval() {
+            self.update_price_limit();
+        }
+    }
+
+    fn update_price_limit(&mut self) {
+        // Without the samples, the previous limit is kept.
+        if let Some(average) = self.statistics.average() {
+            let percent_scale_factor = U256::from((self.config.scale_factor * 100.0).round() as u64);
+            self.price_limit = average.saturating_mul(percent_scale_factor) / U256::from(100);
+            log::info!("Gas price limit is updated to {} wei", self.price_limit);
+        }
+        self.last_update = Instant::now();
+    }
+
+    /// Gas price for a new transaction: the network gas price, capped by the limit.
+    pub async fn get_gas_price<S>(&self, gateway: &EthereumGateway<S>) -> anyhow::Result<U256> {
+        let network_price = gateway.get_gas_price().await?;
+        if network_price > self.price_limit {
+            log::warn!(
+                "Network gas price {} wei exceeds the limit, using the limit {} wei",
+                network_price,
+                self.price_limit
+            );
+            return Ok(self.price_limit);
+        }
+        Ok(network_price)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eth_client::clients::mock::MockEthereum;
+    use crate::eth_signer::PrivateKeySigner;
+
+    fn gas_limit(scale_factor: f64) -> GasLimit {
+        GasLimit {
+            default: 1000,
+            update_interval: 45,
+            sample_interval: 15,
+            scale_factor,
+        }
+    }
+
+    #[test]
+    fn test_rolling_average() {
+        let mut adjuster = GasAdjuster::new(gas_limit(1.5));
+        adjuster.update_price_limit();
+        assert_eq!(adjuster.price_limit(), 1000.into());
+
+        // Only the samples of the last update interval are taken into account.
+        for gas_price in &[1000, 100, 200, 300] {
+            adjuster.statistics.add_sample((*gas_price).into());
+        }
+        adjuster.update_price_limit();
+        assert_eq!(adjuster.price_limit(), 300.into());
+    }
+
+    #[tokio::test]
+    async fn test_gas_price_is_capped() {
+        let mut mock = MockEthereum::default();
+        mock.gas_price = 500.into();
+        let gateway: EthereumGateway<PrivateKeySigner> = EthereumGateway::Mock(mock);
+
+        let mut adjuster = GasAdjuster::new(gas_limit(1.0));
+        assert_eq!(adjuster.get_gas_price(&gateway).await.unwrap(), 500.into());
+
+        adjuster.statistics.add_sample(200.into());
+        adjuster.update_price_limit();
+        assert_eq!(adjuster.get_gas_price(&gateway).await.unwrap(), 200.into());
+    }
+}
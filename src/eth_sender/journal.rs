@@ -0,0 +1,202 @@
+//! Journal of the sender operations, persisted to a file.
+//!
+//! Every change of an operation is appended to the file as a JSON line and flushed to the disk,
+//! and the signed transactions are recorded *before* they are sent. On start, the journal is replayed,
+//! so the sender resumes monitoring the transactions sent before a crash instead of sending the
+//! operations again with new nonces.
+
+use super::{OperationId, OperationStatus};
+use crate::eth_client::SignedCallResult;
+use crate::types::{H256, U256};
+use crate::utils::ZeroPrefixHexSerde;
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum JournalEntry {
+    Queued {
+        id: OperationId,
+        #[serde(with = "ZeroPrefixHexSerde")]
+        data: Vec<u8>,
+        gas_limit: U256,
+    },
+    /// Transaction is signed for the operation, and is about to be sent.
+    Signed {
+        id: OperationId,
+        tx: SignedCallResult,
+        sent_at_block: u64,
+    },
+    /// Node refused to accept the signed transaction, so it is never mined.
+    Rejected { id: OperationId, hash: H256 },
+    /// Operation got enough confirmations, its status is either `Committed` or `Failed`.
+    Completed { id: OperationId, status: OperationStatus },
+}
+
+#[derive(Debug)]
+pub struct Journal {
+    path: PathBuf,
+    file: File,
+    /// Amount of the entries in the file.
+    entry_count: usize,
+}
+
+impl Journal {
+    /// Opens the journal for appending, creating the file if needed.
+    /// Returns the journal together with the entries recorded so far.
+    pub fn open(path: impl Into<PathBuf>) -> anyhow::Result<(Self, Vec<JournalEntry>)> {
+        let path = path.into();
+        let (entries, incomplete) = Self::read_entries(&path)?;
+        if incomplete {
+            // Otherwise the next entry would be appended to the incomplete one.
+            Self::rewrite(&path, &entries)?;
+        }
+        let journal = Self {
+            file: Self::open_file(&path)?,
+            path,
+            entry_count: entries.len(),
+        };
+        Ok((journal, entries))
+    }
+
+    fn open_file(path: &Path) -> anyhow::Result<File> {
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Unable to open the sender journal {}", path.display()))
+    }
+
+    /// Reads the entries, and whether the last entry is incomplete.
+    fn read_entries(path: &Path) -> anyhow::Result<(Vec<JournalEntry>, bool)> {
+        if !path.exists() {
+            return Ok((Vec::new(), false));
+        }
+        let contents = fs::read_to_string(path).with_context(|| format!("Unable to read the sender journal {}", path.display()))?;
+
+        let lines: Vec<&str> = contents.lines().filter(|line| !line.trim().is_empty()).collect();
+        let mut entries = Vec::with_capacity(lines.len());
+        let mut incomplete = false;
+        for (index, line) in lines.iter().enumerate() {
+            match serde_json::from_str(line) {
+                Ok(entry) => entries.push(entry),
+                // The last entry may be cut short by a crash, and the change it describes never took effect.
+                Err(error) if index + 1 == lines.len() && !contents.ends_with('\n') => {
+                    log::warn!("Ignoring the incomplete last entry of the sender journal: {}", error);
+                    incomplete = true;
+                }
+                Err(error) => {
+                    return Err(error).with_context(|| format!("Malformed entry {} of the sender journal {}", index + 1, path.display()))
+                }
+            }
+        }
+        Ok((entries, incomplete))
+    }
+
+    /// Replaces the journal contents with the entries. The entries are written to a temporary file
+    /// first and then renamed, so that an interrupted write never leaves a truncated journal behind.
+    fn rewrite(path: &Path, entries: &[JournalEntry]) -> anyhow::Result<()> {
+        let mut contents = Vec::new();
+        for entry in entries {
+            serde_json::to_writer(&mut contents, entry)?;
+            contents.push(b'\n');
+        }
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, contents).with_context(|| format!("Unable to write the sender journal to {}", tmp_path.display()))?;
+        fs::rename(&tmp_path, path).with_context(|| format!("Unable to move the sender journal to {}", path.display()))?;
+        Ok(())
+    }
+
+    /// Appends the entry, returning once it is written to the disk.
+    pub fn append(&mut self, entry: &JournalEntry) -> anyhow::Result<()> {
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+        self.file
+            .write_all(&line)
+            .and_then(|()| self.file.sync_data())
+            .with_context(|| format!("Unable to write to the sender journal {}", self.path.display()))?;
+        self.entry_count += 1;
+        Ok(())
+    }
+
+    /// Replaces the journal contents with the entries, which describe the same operations as
+    /// the recorded ones, except for the forgotten ones.
+    pub fn compact(&mut self, entries: &[JournalEntry]) -> anyhow::Result<()> {
+        Self::rewrite(&self.path, entries)?;
+        // The renamed file replaced the one opened before.
+        self.file = Self::open_file(&self.path)?;
+        self.entry_count = entries.len();
+        Ok(())
+    }
+
+    pub fn entry_count(&self) -> usize {
+        self.entry_count
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn test_entries_are_replayed() {
+        let path = env::temp_dir().join(format!("eth_sender_journal_test_{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let tx = SignedCallResult {
+            raw_tx: vec![1, 2, 3],
+            gas_price: 100.into(),
+            max_priority_fee_per_gas: None,
+            nonce: 5.into(),
+            hash: H256::from_low_u64_be(1),
+        };
+        let (mut journal, entries) = Journal::open(&path).unwrap();
+        assert!(entries.is_empty());
+        journal
+            .append(&JournalEntry::Queued {
+                id: 0,
+                data: vec![4, 5],
+                gas_limit: 100_000.into(),
+            })
+            .unwrap();
+        journal
+            .append(&JournalEntry::Signed {
+                id: 0,
+                tx: tx.clone(),
+                sent_at_block: 10,
+            })
+            .unwrap();
+        drop(journal);
+
+        // An entry interrupted by a crash is ignored.
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"{\"event\":\"completed\",\"id\":0,").unwrap();
+        drop(file);
+
+        let (mut journal, entries) = Journal::open(&path).unwrap();
+        assert_eq!(entries.len(), 2);
+        journal.append(&JournalEntry::Rejected { id: 0, hash: tx.hash }).unwrap();
+        let (_, entries) = Journal::open(&path).unwrap();
+        assert_eq!(entries.len(), 3);
+        assert!(matches!(&entries[0], JournalEntry::Queued { id: 0, data, .. } if data == &vec![4, 5]));
+        assert!(matches!(&entries[1], JournalEntry::Signed { tx: signed, sent_at_block: 10, .. } if signed == &tx));
+
+        // The entries appended after the compaction follow the compacted ones.
+        let (mut journal, entries) = Journal::open(&path).unwrap();
+        journal.compact(&entries[..1]).unwrap();
+        journal.append(&JournalEntry::Rejected { id: 1, hash: tx.hash }).unwrap();
+        assert_eq!(journal.entry_count(), 2);
+        let (_, entries) = Journal::open(&path).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert!(matches!(&entries[1], JournalEntry::Rejected { id: 1, .. }));
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,1015 @@
+//! Ethereum sender sends the operator transactions to the Fluidex contract, and watches them
+//! until they get `wait_confirmations` confirmations.
+//!
+//! Operations are queued with `ETHSenderRequest::SendTx`, and at most `max_txs_in_flight` of them
+//! are sent at once. The nonces of the transactions are allocated by the nonce manager of the gateway,
+//! and their gas prices are capped by the `gas_adjuster`.
+//!
+//! A transaction not mined within `expected_wait_time_block` blocks is considered stuck, and is replaced
+//! by a transaction with the same nonce and a higher gas price. Any of the transactions sent for
+//! the operation may end up mined, so all of them are tracked.
+//!
+//! If the `journal_file` is configured, the operations are recorded to the `journal`, and restored
+//! from it on start.
+
+use self::journal::{Journal, JournalEntry};
+use crate::config::ETHSenderConfig;
+use crate::eth_client::ethereum_gateway::FailureInfo;
+use crate::eth_client::nonce_manager::is_rpc_error;
+use crate::eth_client::{min_replacement_fee, EthereumGateway, NonceError, SignedCallResult};
+use crate::eth_signer::EthereumSigner;
+use crate::types::{H256, U256};
+use futures::{
+    channel::{mpsc, oneshot},
+    StreamExt,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use tokio::time;
+use web3::contract::Options;
+
+pub use gas_adjuster::GasAdjuster;
+
+mod gas_adjuster;
+pub mod journal;
+
+/// Amount of the reverted dry runs of the queued operation, after which it fails without being sent.
+const MAX_DRY_RUN_REVERTS: usize = 3;
+
+/// Amount of the latest completed operations kept for the queries, the older ones are forgotten.
+const MAX_COMPLETED_OPERATIONS: usize = 1000;
+
+/// Amount of the journal entries, after which the journal is rewritten with only the entries
+/// of the operations which are kept.
+const JOURNAL_COMPACTION_THRESHOLD: usize = 10_000;
+
+/// Identifier of the operation, assigned by the sender in the order of submission.
+pub type OperationId = u64;
+
+/// State of the operation.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum OperationStatus {
+    /// Waiting for a free in-flight slot.
+    Queued,
+    /// Transaction is sent, and waits to be mined and confirmed.
+    Pending,
+    /// Transaction was executed successfully and has enough confirmations.
+    Committed,
+    /// Transaction was executed with enough confirmations, but failed.
+    /// The reason is set if the node was able to report it.
+    Failed(Option<FailureInfo>),
+}
+
+impl OperationStatus {
+    pub fn is_completed(&self) -> bool {
+        matches!(self, Self::Committed | Self::Failed(_))
+    }
+}
+
+/// Call of the Fluidex contract requested by the operator.
+#[derive(Debug, Clone)]
+pub struct Operation {
+    pub id: OperationId,
+    /// Encoded contract call, see `EthereumGateway::encode_tx_data`.
+    pub data: Vec<u8>,
+    pub gas_limit: U256,
+    pub status: OperationStatus,
+    /// Transactions sent for the operation, each one replacing the previous stuck one.
+    pub txs: Vec<SentTx>,
+    /// Failures of the dry runs of the queued operation.
+    pub dry_run_reverts: Vec<FailureInfo>,
+}
+
+impl Operation {
+    /// The latest transaction sent for the operation.
+    pub fn last_tx(&self) -> Option<&SignedCallResult> {
+        self.txs.last().map(|sent| &sent.tx)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SentTx {
+    pub tx: SignedCallResult,
+    /// The latest block at the moment the transaction was sent.
+    pub sent_at_block: u64,
+}
+
+#[derive(Debug)]
+pub enum ETHSenderRequest {
+    /// Queues the contract call, responding with the id of the operation.
+    SendTx {
+        data: Vec<u8>,
+        gas_limit: U256,
+        resp: oneshot::Sender<OperationId>,
+    },
+    GetOperation {
+        id: OperationId,
+        resp: oneshot::Sender<Option<Operation>>,
+    },
+    /// Finds the operation by the hash of any of its transactions.
+    GetOperationByTxHash {
+        hash: H256,
+        resp: oneshot::Sender<Option<Operation>>,
+    },
+}
+
+pub struct ETHSender<S> {
+    gateway: EthereumGateway<S>,
+    config: ETHSenderConfig,
+    gas_adjuster: GasAdjuster,
+    /// All the operations, including the latest `MAX_COMPLETED_OPERATIONS` completed ones, which are kept for the queries.
+    operations: BTreeMap<OperationId, Operation>,
+    /// Operations waiting to be sent, in the order of submission.
+    queue: VecDeque<OperationId>,
+    /// Operations whose transactions are sent, but don't have enough confirmations yet.
+    in_flight: Vec<OperationId>,
+    next_id: OperationId,
+    journal: Option<Journal>,
+}
+
+impl<S: EthereumSigner> ETHSender<S> {
+    pub fn new(gateway: EthereumGateway<S>, config: ETHSenderConfig) -> Self {
+        Self {
+            gateway,
+            gas_adjuster: GasAdjuster::new(config.gas_price_limit.clone()),
+            config,
+            operations: BTreeMap::new(),
+            queue: VecDeque::new(),
+            in_flight: Vec::new(),
+            next_id: 0,
+            journal: None,
+        }
+    }
+
+    /// Creates the sender, restoring the operations from the `journal_file`, if it's configured.
+    pub fn from_config(gateway: EthereumGateway<S>, config: ETHSenderConfig) -> anyhow::Result<Self> {
+        let journal_file = config.sender.journal_file.clone();
+        let mut sender = Self::new(gateway, config);
+        if let Some(journal_file) = journal_file {
+            let (mut journal, entries) = Journal::open(journal_file)?;
+            sender.restore(entries);
+            sender.forget_completed_operations();
+            journal.compact(&sender.journal_snapshot())?;
+            log::info!(
+                "Restored {} operations from {}, {} of them are not completed",
+                sender.operations.len(),
+                journal.path().display(),
+                sender.queue.len() + sender.in_flight.len()
+            );
+            sender.journal = Some(journal);
+        }
+        Ok(sender)
+    }
+
+    /// Replays the journal entries, reserving the nonces of the pending transactions.
+    fn restore(&mut self, entries: Vec<JournalEntry>) {
+        for entry in entries {
+            match entry {
+                JournalEntry::Queued { id, data, gas_limit } => {
+                    self.operations.insert(
+                        id,
+                        Operation {
+                            id,
+                            data,
+                            gas_limit,
+                            status: OperationStatus::Queued,
+                            txs: Vec::new(),
+                            dry_run_reverts: Vec::new(),
+                        },
+                    );
+                    self.next_id = self.next_id.max(id + 1);
+                }
+                JournalEntry::Signed { id, tx, sent_at_block } => {
+                    if let Some(operation) = self.operations.get_mut(&id) {
+                        operation.txs.push(SentTx { tx, sent_at_block });
+                        operation.status = OperationStatus::Pending;
+                    }
+                }
+                JournalEntry::Rejected { id, hash } => {
+                    if let Some(operation) = self.operations.get_mut(&id) {
+                        operation.txs.retain(|sent| sent.tx.hash != hash);
+                        if operation.txs.is_empty() {
+                            operation.status = OperationStatus::Queued;
+                        }
+                    }
+                }
+                JournalEntry::Completed { id, status } => {
+                    if let Some(operation) = self.operations.get_mut(&id) {
+                        operation.status = status;
+                    }
+                }
+            }
+        }
+
+        for operation in self.operations.values() {
+            match operation.status {
+                OperationStatus::Queued => self.queue.push_back(operation.id),
+                OperationStatus::Pending => {
+                    // The transactions of the operation may be known to the network, even if the node
+                    // rejects them when they are sent again.
+                    let tx = operation.last_tx().expect("sent operation has a transaction");
+                    self.gateway.reserve_nonce(tx.nonce);
+                    self.in_flight.push(operation.id);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Entries describing the operations which are kept, in the order of their submission.
+    fn journal_snapshot(&self) -> Vec<JournalEntry> {
+        let mut entries = Vec::new();
+        for operation in self.operations.values() {
+            let id = operation.id;
+            entries.push(JournalEntry::Queued {
+                id,
+                data: operation.data.clone(),
+                gas_limit: operation.gas_limit,
+            });
+            for sent in &operation.txs {
+                entries.push(JournalEntry::Signed {
+                    id,
+                    tx: sent.tx.clone(),
+                    sent_at_block: sent.sent_at_block,
+                });
+            }
+            if operation.status.is_completed() {
+                entries.push(JournalEntry::Completed {
+                    id,
+                    status: operation.status.clone(),
+                });
+            }
+        }
+        entries
+    }
+
+    /// Forgets the oldest completed operations, keeping the latest `MAX_COMPLETED_OPERATIONS` ones.
+    ///
+    /// The completed operation with the highest id is never forgotten, so the ids restored from
+    /// the compacted journal are not reused.
+    fn forget_completed_operations(&mut self) {
+        let completed: Vec<OperationId> = self
+            .operations
+            .values()
+            .filter(|operation| operation.status.is_completed())
+            .map(|operation| operation.id)
+            .collect();
+        let forgotten = completed.len().saturating_sub(MAX_COMPLETED_OPERATIONS);
+        for id in &completed[..forgotten] {
+            self.operations.remove(id);
+        }
+    }
+
+    /// Rewrites the journal once it grows too long.
+    fn compact_journal(&mut self) {
+        let snapshot = match &self.journal {
+            Some(journal) if journal.entry_count() > JOURNAL_COMPACTION_THRESHOLD => self.journal_snapshot(),
+            _ => return,
+        };
+        let journal = self.journal.as_mut().expect("journal is configured");
+        match journal.compact(&snapshot) {
+            Ok(()) => log::info!(
+                "Sender journal {} is compacted to {} entries",
+                journal.path().display(),
+                snapshot.len()
+            ),
+            Err(error) => log::error!("Unable to compact the sender journal: {:#}", error),
+        }
+    }
+
+    /// Appends the entry to the journal, if it's configured.
+    fn record(&mut self, entry: JournalEntry) -> anyhow::Result<()> {
+        match &mut self.journal {
+            Some(journal) => journal.append(&entry),
+            None => Ok(()),
+        }
+    }
+
+    /// Queues the contract call. It's sent on the next poll, once there is a free in-flight slot.
+    pub fn add_operation(&mut self, data: Vec<u8>, gas_limit: U256) -> OperationId {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.operations.insert(
+            id,
+            Operation {
+                id,
+                data,
+                gas_limit,
+                status: OperationStatus::Queued,
+                txs: Vec::new(),
+                dry_run_reverts: Vec::new(),
+            },
+        );
+        self.queue.push_back(id);
+
+        let operation = &self.operations[&id];
+        let entry = JournalEntry::Queued {
+            id,
+            data: operation.data.clone(),
+            gas_limit,
+        };
+        if let Err(error) = self.record(entry) {
+            log::error!("Unable to record the operation {}: {:#}", id, error);
+        }
+        id
+    }
+
+    pub fn operation(&self, id: OperationId) -> Option<&Operation> {
+        self.operations.get(&id)
+    }
+
+    pub fn operation_by_tx_hash(&self, hash: H256) -> Option<&Operation> {
+        self.operations
+            .values()
+            .find(|operation| operation.txs.iter().any(|sent| sent.tx.hash == hash))
+    }
+
+    pub async fn run(mut self, mut requests: mpsc::Receiver<ETHSenderRequest>) {
+        if self.config.sender.is_enabled {
+            self.resend_pending_txs().await;
+        }
+        let mut poll_timer = time::interval(self.config.sender.tx_poll_period());
+        let mut gas_sample_timer = time::interval(self.config.gas_price_limit.sample_interval());
+        loop {
+            tokio::select! {
+                request = requests.next() => match request {
+                    Some(request) => self.handle_request(request),
+                    None => break,
+                },
+                _ = poll_timer.tick() => self.proceed_next_operations().await,
+                _ = gas_sample_timer.tick() => self.gas_adjuster.keep_updated(&self.gateway).await,
+            }
+        }
+
+        // All the request senders are dropped, which means that the sender is being shut down.
+        log::info!(
+            "ETH sender is shutting down, {} operations are not completed",
+            self.queue.len() + self.in_flight.len()
+        );
+    }
+
+    fn handle_request(&mut self, request: ETHSenderRequest) {
+        match request {
+            ETHSenderRequest::SendTx { data, gas_limit, resp } => {
+                resp.send(self.add_operation(data, gas_limit)).unwrap_or_default();
+            }
+            ETHSenderRequest::GetOperation { id, resp } => {
+                resp.send(self.operation(id).cloned()).unwrap_or_default();
+            }
+            ETHSenderRequest::GetOperationByTxHash { hash, resp } => {
+                resp.send(self.operation_by_tx_hash(hash).cloned()).unwrap_or_default();
+            }
+        }
+    }
+
+    /// Sends the latest transactions of the restored pending operations again, in case they were
+    /// signed but not sent before the restart, or were dropped by the nodes since then.
+    async fn resend_pending_txs(&self) {
+        for id in &self.in_flight {
+            let tx = self.operations[id].last_tx().expect("sent operation has a transaction");
+            if let Err(error) = self.gateway.send_signed_tx(tx).await {
+                // The transaction is most likely already mined, which is found out by the next poll.
+                log::info!("Transaction {:#x} of the operation {} is not sent again: {:#}", tx.hash, id, error);
+            }
+        }
+    }
+
+    /// Completes the confirmed operations, and sends the queued ones in their place.
+    async fn proceed_next_operations(&mut self) {
+        if !self.config.sender.is_enabled {
+            return;
+        }
+        let current_block = match self.gateway.block_number().await {
+            Ok(block) => block.as_u64(),
+            Err(error) => {
+                log::warn!("Unable to fetch the last block number: {:#}", error);
+                return;
+            }
+        };
+        self.check_pending_operations(current_block).await;
+        self.send_queued_operations(current_block).await;
+    }
+
+    async fn check_pending_operations(&mut self, current_block: u64) {
+        let mut still_pending = Vec::with_capacity(self.in_flight.len());
+        for id in std::mem::take(&mut self.in_flight) {
+            match self.check_operation(id, current_block).await {
+                Ok(true) => {}
+                Ok(false) => still_pending.push(id),
+                Err(error) => {
+                    log::warn!("Unable to check the state of the operation {}: {:#}", id, error);
+                    still_pending.push(id);
+                }
+            }
+        }
+        self.in_flight = still_pending;
+    }
+
+    /// Returns `true` if the operation is completed. Replaces the stuck transaction of the operation.
+    async fn check_operation(&mut self, id: OperationId, current_block: u64) -> anyhow::Result<bool> {
+        let operation = &self.operations[&id];
+        let txs: Vec<SignedCallResult> = operation.txs.iter().rev().map(|sent| sent.tx.clone()).collect();
+        let sent_at_block = operation.txs.last().expect("sent operation has a transaction").sent_at_block;
+
+        // The transactions share the nonce, so at most one of them is mined.
+        let hashes: Vec<H256> = txs.iter().map(|tx| tx.hash).collect();
+        let statuses = self.gateway.get_tx_statuses(&hashes).await?;
+        let mined = txs
+            .into_iter()
+            .zip(statuses)
+            .find_map(|(tx, status)| status.map(|status| (tx, status)));
+        let (tx, status) = match mined {
+            Some((tx, status)) if status.confirmations >= self.config.sender.wait_confirmations => (tx, status),
+            // Mined transaction is waiting for the confirmations.
+            Some(_) => return Ok(false),
+            None => {
+                let waited_blocks = current_block.saturating_sub(sent_at_block);
+                if waited_blocks >= self.config.sender.expected_wait_time_block {
+                    self.replace_stuck_tx(id, current_block, waited_blocks).await?;
+                }
+                return Ok(false);
+            }
+        };
+        self.gateway.confirm_nonce(tx.nonce);
+
+        let new_status = if status.success {
+            log::info!("Operation {} is committed by the transaction {:#x}", id, tx.hash);
+            OperationStatus::Committed
+        } else {
+            let failure = self.gateway.failure_reason(tx.hash).await.unwrap_or_else(|error| {
+                log::warn!("Unable to get the failure reason of the transaction {:#x}: {:#}", tx.hash, error);
+                None
+            });
+            log::error!("Operation {} failed in the transaction {:#x}: {:?}", id, tx.hash, failure);
+            OperationStatus::Failed(failure)
+        };
+        self.complete_operation(id, new_status);
+        Ok(true)
+    }
+
+    fn complete_operation(&mut self, id: OperationId, status: OperationStatus) {
+        self.operations.get_mut(&id).expect("operation exists").status = status.clone();
+        if let Err(error) = self.record(JournalEntry::Completed { id, status }) {
+            log::error!("Unable to record the completion of the operation {}: {:#}", id, error);
+        }
+        self.forget_completed_operations();
+        self.compact_journal();
+    }
+
+    /// Sends the transaction with the same nonce and the gas price increased enough for the nodes
+    /// to replace the stuck one. The transaction stays stuck if the increased price exceeds the limit.
+    async fn replace_stuck_tx(&mut self, id: OperationId, current_block: u64, waited_blocks: u64) -> anyhow::Result<()> {
+        let operation = &self.operations[&id];
+        let stuck_tx = operation.last_tx().expect("sent operation has a transaction");
+        let min_gas_price = min_replacement_fee(stuck_tx.gas_price);
+        let price_limit = self.gas_adjuster.price_limit();
+        if min_gas_price > price_limit {
+            log::warn!(
+                "Transaction {:#x} of the operation {} is not mined for {} blocks, but can't be replaced \
+                 since the gas price {} wei would exceed the limit {} wei",
+                stuck_tx.hash,
+                id,
+                waited_blocks,
+                min_gas_price,
+                price_limit
+            );
+            return Ok(());
+        }
+
+        let gas_price = self.gas_adjuster.get_gas_price(&self.gateway).await?.max(min_gas_price);
+        let options = Options::with(|options| {
+            options.gas = Some(operation.gas_limit);
+            options.gas_price = Some(gas_price);
+        });
+        let tx = self.gateway.sign_replacement_tx(operation.data.clone(), options, stuck_tx).await?;
+        log::warn!(
+            "Transaction {:#x} of the operation {} is not mined for {} blocks, replacing it by {:#x} with gas price {} wei",
+            stuck_tx.hash,
+            id,
+            waited_blocks,
+            tx.hash,
+            gas_price
+        );
+        self.send_tx(id, tx, current_block).await
+    }
+
+    async fn send_queued_operations(&mut self, current_block: u64) {
+        while (self.in_flight.len() as u64) < self.config.sender.max_txs_in_flight {
+            let id = match self.queue.front() {
+                Some(id) => *id,
+                None => break,
+            };
+            match self.send_operation(id, current_block).await {
+                Ok(()) => self.in_flight.push(id),
+                Err(error) => match error.downcast::<FailureInfo>() {
+                    // The transaction would revert, so it's not sent at all. The state it depends on may be
+                    // changed by the pending transactions, so it's tried again on the next polls first.
+                    Ok(failure) => {
+                        let reverts = &mut self.operations.get_mut(&id).expect("operation exists").dry_run_reverts;
+                        reverts.push(failure.clone());
+                        if reverts.len() < MAX_DRY_RUN_REVERTS {
+                            log::warn!(
+                                "Operation {} is not sent yet, since the {} ({} of {} attempts)",
+                                id,
+                                failure,
+                                reverts.len(),
+                                MAX_DRY_RUN_REVERTS
+                            );
+                            break;
+                        }
+                        log::error!("Operation {} is not sent, since the {}", id, failure);
+                        self.complete_operation(id, OperationStatus::Failed(Some(failure)));
+                    }
+                    // The operation stays queued and is sent again on the next poll. The nonce related
+                    // errors are handled by the nonce manager, so the next attempt gets a new nonce.
+                    Err(error) => {
+                        log::warn!("Unable to send the operation {}: {:#}", id, error);
+                        break;
+                    }
+                },
+            }
+            self.queue.pop_front();
+        }
+    }
+
+    async fn send_operation(&mut self, id: OperationId, current_block: u64) -> anyhow::Result<()> {
+        let operation = &self.operations[&id];
+        // For the EIP-1559 transactions, the capped price is used as the max fee per gas.
+        let gas_price = self.gas_adjuster.get_gas_price(&self.gateway).await?;
+        let options = Options::with(|options| {
+            options.gas = Some(operation.gas_limit);
+            options.gas_price = Some(gas_price);
+        });
+        let tx = self.gateway.sign_prepared_tx(operation.data.clone(), options).await?;
+        log::info!(
+            "Sending the operation {} in the transaction {:#x} with nonce {}",
+            id,
+            tx.hash,
+            tx.nonce
+        );
+        self.send_tx(id, tx, current_block).await
+    }
+
+    /// Records the signed transaction of the operation, and sends it.
+    async fn send_tx(&mut self, id: OperationId, tx: SignedCallResult, current_block: u64) -> anyhow::Result<()> {
+        let entry = JournalEntry::Signed {
+            id,
+            tx: tx.clone(),
+            sent_at_block: current_block,
+        };
+        // Unrecorded transaction would be sent again with a new nonce after a restart.
+        if let Err(error) = self.record(entry) {
+            self.gateway.release_nonce(tx.nonce);
+            return Err(error);
+        }
+
+        if let Err(error) = self.gateway.send_signed_tx(&tx).await {
+            if is_rpc_error(&error) || error.downcast_ref::<NonceError>().is_some() {
+                if let Err(error) = self.record(JournalEntry::Rejected { id, hash: tx.hash }) {
+                    log::error!("Unable to record the rejection of the transaction {:#x}: {:#}", tx.hash, error);
+                }
+                return Err(error);
+            }
+            // Without the answer of the node the transaction may still be mined, so it's tracked
+            // like the sent one, and is replaced if it's not mined in time.
+            log::warn!(
+                "Transaction {:#x} of the operation {} may not have been sent: {:#}",
+                tx.hash,
+                id,
+                error
+            );
+        }
+
+        let operation = self.operations.get_mut(&id).expect("operation exists");
+        operation.txs.push(SentTx {
+            tx,
+            sent_at_block: current_block,
+        });
+        operation.status = OperationStatus::Pending;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::configs::eth_sender::{GasLimit, Sender, SignerConfig};
+    use crate::contracts::fluidex_contract;
+    use crate::eth_client::clients::mock::MockEthereum;
+    use crate::eth_client::{ETHDirectClient, MultiplexerEthereumClient, NonceManager};
+    use crate::eth_signer::PrivateKeySigner;
+    use crate::types::Address;
+    use actix_web::{post, web, App, HttpResponse, HttpServer, Responder};
+    use futures::future::{AbortHandle, Abortable};
+    use futures::SinkExt;
+    use parity_crypto::Keccak256;
+    use serde_json::{json, Value};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use web3::{transports::Http, types::U64};
+
+    fn config(max_txs_in_flight: u64, is_enabled: bool) -> ETHSenderConfig {
+        ETHSenderConfig {
+            sender: Sender {
+                signer: SignerConfig::PrivateKey,
+                operator_private_key: None,
+                operator_commit_eth_addr: Address::from_low_u64_be(2),
+                wait_confirmations: 1,
+                expected_wait_time_block: 30,
+                tx_poll_period: 3,
+                max_txs_in_flight,
+                is_enabled,
+                journal_file: None,
+            },
+            gas_price_limit: GasLimit {
+                default: 400_000_000_000,
+                update_interval: 150,
+                sample_interval: 15,
+                scale_factor: 1.0,
+            },
+        }
+    }
+
+    fn sender(max_txs_in_flight: u64, is_enabled: bool) -> ETHSender<PrivateKeySigner> {
+        ETHSender::new(
+            EthereumGateway::Mock(MockEthereum::default()),
+            config(max_txs_in_flight, is_enabled),
+        )
+    }
+
+    fn sent_tx(sender: &ETHSender<PrivateKeySigner>, id: OperationId) -> SignedCallResult {
+        sender.operation(id).unwrap().last_tx().cloned().unwrap()
+    }
+
+    /// Methods called on the stand-in node, whether the dry runs revert on it, and the amount
+    /// of the next transactions it rejects.
+    #[derive(Clone, Default)]
+    struct NodeState {
+        methods: Arc<Mutex<Vec<String>>>,
+        reverts: bool,
+        rejected_sends: Arc<Mutex<usize>>,
+    }
+
+    impl NodeState {
+        fn calls(&self, method: &str) -> usize {
+            self.methods.lock().unwrap().iter().filter(|called| *called == method).count()
+        }
+    }
+
+    #[post("/")]
+    async fn node(req: web::Json<Value>, state: web::Data<NodeState>) -> impl Responder {
+        let method = req["method"].as_str().unwrap().to_string();
+        state.methods.lock().unwrap().push(method.clone());
+        let error = |code: i64, message: &str| {
+            HttpResponse::Ok().json(json!({
+                "jsonrpc": "2.0",
+                "id": req["id"],
+                "error": { "code": code, "message": message },
+            }))
+        };
+        let result = match method.as_str() {
+            "eth_blockNumber" => json!(U64::from(10)),
+            "eth_gasPrice" => json!(U256::from(1_000_000_000)),
+            "eth_call" if state.reverts => return error(3, "execution reverted"),
+            "eth_call" => json!("0x"),
+            "eth_sendRawTransaction" if *state.rejected_sends.lock().unwrap() > 0 => {
+                *state.rejected_sends.lock().unwrap() -= 1;
+                return error(-32000, "insufficient funds for gas * price + value");
+            }
+            "eth_getTransactionCount" => json!(U256::from(5)),
+            "web3_sha3" | "eth_sendRawTransaction" => {
+                let tx = hex::decode(req["params"][0].as_str().unwrap().trim_start_matches("0x")).unwrap();
+                json!(H256::from(tx.keccak256()))
+            }
+            "eth_getTransactionReceipt" => Value::Null,
+            method => panic!("Unexpected method {}", method),
+        };
+        HttpResponse::Ok().json(json!({ "jsonrpc": "2.0", "id": req["id"], "result": result }))
+    }
+
+    fn run_server(state: NodeState) -> (String, AbortHandle) {
+        let mut url = None;
+        let mut server = None;
+        for i in 9000..9999 {
+            let new_url = format!("127.0.0.1:{}", i);
+            // Try to bind to some port, hope that 999 variants will be enough
+            let tmp_state = state.clone();
+            if let Ok(ser) = HttpServer::new(move || App::new().data(tmp_state.clone()).service(node)).bind(new_url.clone()) {
+                server = Some(ser);
+                url = Some(new_url);
+                break;
+            }
+        }
+
+        let server = server.expect("Could not bind to port from 9000 to 9999");
+        let (abort_handle, abort_registration) = AbortHandle::new_pair();
+        let future = Abortable::new(server.run(), abort_registration);
+        tokio::spawn(future);
+        let address = format!("http://{}/", &url.unwrap());
+        (address, abort_handle)
+    }
+
+    /// Gateway multiplexing the nodes, which share the nonce manager.
+    fn multiplexed_gateway(urls: &[String], max_txs_in_flight: u64) -> EthereumGateway<PrivateKeySigner> {
+        let signer = PrivateKeySigner::new(H256::from_low_u64_be(1));
+        let nonce_manager = NonceManager::new(max_txs_in_flight);
+        let gateway = urls.iter().fold(MultiplexerEthereumClient::new(), |gateway, url| {
+            let client = ETHDirectClient::new(
+                Http::new(url).unwrap(),
+                fluidex_contract(),
+                Address::from_low_u64_be(2),
+                signer.clone(),
+                Address::from_low_u64_be(1),
+                9,
+                1.0,
+            )
+            .with_nonce_manager(nonce_manager.clone());
+            gateway.add_client(url.clone(), client)
+        });
+        EthereumGateway::Multiplexed(gateway)
+    }
+
+    #[tokio::test]
+    async fn test_operations_lifecycle() {
+        let mut sender = sender(2, true);
+        let ids: Vec<_> = (0..3u8).map(|i| sender.add_operation(vec![i; 4], 100_000.into())).collect();
+
+        sender.proceed_next_operations().await;
+        let mock = sender.gateway.get_mock().unwrap();
+        for id in &ids[..2] {
+            mock.assert_sent(&sent_tx(&sender, *id).raw_tx).await;
+            assert!(matches!(sender.operation(*id).unwrap().status, OperationStatus::Pending));
+        }
+        // Only `max_txs_in_flight` transactions are sent at once.
+        assert!(matches!(sender.operation(ids[2]).unwrap().status, OperationStatus::Queued));
+
+        let (first_hash, second_hash) = (sent_tx(&sender, ids[0]).hash, sent_tx(&sender, ids[1]).hash);
+        let mock = sender.gateway.get_mut_mock().unwrap();
+        mock.add_successfull_execution(first_hash, 1).await;
+        mock.add_failed_execution(&second_hash, 0).await;
+        sender.proceed_next_operations().await;
+
+        assert!(matches!(sender.operation(ids[0]).unwrap().status, OperationStatus::Committed));
+        // The failure is not reported until the transaction has enough confirmations.
+        assert!(matches!(sender.operation(ids[1]).unwrap().status, OperationStatus::Pending));
+        assert!(matches!(sender.operation(ids[2]).unwrap().status, OperationStatus::Pending));
+
+        sender.gateway.get_mut_mock().unwrap().add_failed_execution(&second_hash, 1).await;
+        sender.proceed_next_operations().await;
+        assert!(matches!(sender.operation(ids[1]).unwrap().status, OperationStatus::Failed(None)));
+        assert_ne!(sent_tx(&sender, ids[0]).nonce, sent_tx(&sender, ids[2]).nonce);
+    }
+
+    #[tokio::test]
+    async fn test_stuck_tx_is_replaced() {
+        let mut sender = sender(2, true);
+        let id = sender.add_operation(vec![1, 2, 3], 100_000.into());
+        sender.proceed_next_operations().await;
+        let stuck_tx = sent_tx(&sender, id);
+
+        sender.gateway.get_mut_mock().unwrap().block_number += 29;
+        sender.proceed_next_operations().await;
+        assert_eq!(sender.operation(id).unwrap().txs.len(), 1);
+
+        sender.gateway.get_mut_mock().unwrap().block_number += 1;
+        sender.proceed_next_operations().await;
+        let replacement = sent_tx(&sender, id);
+        sender.gateway.get_mock().unwrap().assert_sent(&replacement.raw_tx).await;
+        assert_eq!(replacement.nonce, stuck_tx.nonce);
+        assert_eq!(replacement.gas_price, min_replacement_fee(stuck_tx.gas_price));
+        assert_eq!(sender.operation_by_tx_hash(stuck_tx.hash).unwrap().id, id);
+
+        // The replaced transaction may still be mined instead of the replacement.
+        sender
+            .gateway
+            .get_mut_mock()
+            .unwrap()
+            .add_successfull_execution(stuck_tx.hash, 1)
+            .await;
+        sender.proceed_next_operations().await;
+        assert!(matches!(sender.operation(id).unwrap().status, OperationStatus::Committed));
+        assert_eq!(sender.operation(id).unwrap().txs.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_send_errors() {
+        let mut sender = sender(2, true);
+        let id = sender.add_operation(vec![1], 100_000.into());
+        let mock = sender.gateway.get_mock().unwrap();
+        mock.send_errors.write().await.push_back(web3::Error::Rpc(jsonrpc_core::Error {
+            code: jsonrpc_core::ErrorCode::ServerError(-32000),
+            message: "insufficient funds for gas * price + value".into(),
+            data: None,
+        }));
+        sender.proceed_next_operations().await;
+        // The operation rejected by the node is sent again on the next poll.
+        assert!(matches!(sender.operation(id).unwrap().status, OperationStatus::Queued));
+        assert!(sender.operation(id).unwrap().txs.is_empty());
+
+        let mock = sender.gateway.get_mock().unwrap();
+        mock.send_errors
+            .write()
+            .await
+            .push_back(web3::Error::Transport("request timed out".into()));
+        sender.proceed_next_operations().await;
+        // The transaction may have reached the node before the timeout, so it's waited for.
+        let tx = sent_tx(&sender, id);
+        assert!(matches!(sender.operation(id).unwrap().status, OperationStatus::Pending));
+        assert_eq!(sender.in_flight, vec![id]);
+
+        sender.gateway.get_mut_mock().unwrap().add_successfull_execution(tx.hash, 1).await;
+        sender.proceed_next_operations().await;
+        assert!(matches!(sender.operation(id).unwrap().status, OperationStatus::Committed));
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_reverts() {
+        let mut sender = sender(2, true);
+        let id = sender.add_operation(vec![1], 100_000.into());
+        let failure = FailureInfo {
+            revert_code: String::new(),
+            revert_reason: "unknown".into(),
+            gas_used: None,
+            gas_limit: 100_000.into(),
+            revert_error: None,
+        };
+        let mock = sender.gateway.get_mock().unwrap();
+        for _ in 0..MAX_DRY_RUN_REVERTS {
+            mock.sign_errors.write().await.push_back(failure.clone().into());
+        }
+
+        // The reverted operation stays queued until the attempts are exhausted.
+        for attempt in 1..MAX_DRY_RUN_REVERTS {
+            sender.proceed_next_operations().await;
+            let operation = sender.operation(id).unwrap();
+            assert!(matches!(operation.status, OperationStatus::Queued));
+            assert_eq!(operation.dry_run_reverts.len(), attempt);
+        }
+        sender.proceed_next_operations().await;
+        assert!(matches!(sender.operation(id).unwrap().status, OperationStatus::Failed(Some(_))));
+        assert!(sender.queue.is_empty());
+        assert!(sender.gateway.get_mock().unwrap().sent_txs.read().await.is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn test_dry_run_reverts_with_multiplexed_gateway() {
+        let nodes: Vec<_> = (0..2)
+            .map(|_| NodeState {
+                reverts: true,
+                ..Default::default()
+            })
+            .collect();
+        let (urls, abort_handles): (Vec<_>, Vec<_>) = nodes.iter().cloned().map(run_server).unzip();
+        let mut sender = ETHSender::new(multiplexed_gateway(&urls, 2), config(2, true));
+        let id = sender.add_operation(vec![1], 100_000.into());
+
+        for _ in 0..MAX_DRY_RUN_REVERTS {
+            sender.proceed_next_operations().await;
+        }
+        assert!(matches!(sender.operation(id).unwrap().status, OperationStatus::Failed(Some(_))));
+        // The revert is the same on any node, so the dry run is not repeated on the other one.
+        assert_eq!(nodes[0].calls("eth_call"), MAX_DRY_RUN_REVERTS);
+        assert_eq!(nodes[1].calls("eth_call"), 0);
+        assert_eq!(nodes[0].calls("eth_sendRawTransaction"), 0);
+
+        abort_handles.iter().for_each(AbortHandle::abort);
+    }
+
+    #[actix_rt::test]
+    async fn test_nonces_with_multiplexed_gateway() {
+        let nodes = vec![NodeState::default(), NodeState::default()];
+        let (mut urls, abort_handles): (Vec<_>, Vec<_>) = nodes.iter().cloned().map(run_server).unzip();
+        // Nothing listens on the port, so the first client fails without the answer of the node.
+        urls.insert(0, "http://127.0.0.1:1/".to_string());
+        let mut sender = ETHSender::new(multiplexed_gateway(&urls, 2), config(2, true));
+        let ids: Vec<_> = (0..3u8).map(|i| sender.add_operation(vec![i; 4], 100_000.into())).collect();
+
+        // The nonces are allocated by the manager shared by the clients, starting from the mined one.
+        sender.proceed_next_operations().await;
+        assert_eq!(sent_tx(&sender, ids[0]).nonce, 5.into());
+        assert_eq!(sent_tx(&sender, ids[1]).nonce, 6.into());
+        assert!(matches!(sender.operation(ids[2]).unwrap().status, OperationStatus::Queued));
+        assert_eq!(nodes[0].calls("eth_sendRawTransaction"), 2);
+
+        // Once the nonces run out, the other clients are not tried, since they share the manager.
+        let options = Options::with(|options| options.gas = Some(100_000.into()));
+        let error = sender.gateway.sign_prepared_tx(vec![3], options).await.unwrap_err();
+        assert_eq!(error.downcast_ref::<NonceError>(), Some(&NonceError::TooManyInFlight(2)));
+        assert!(nodes[1].methods.lock().unwrap().is_empty());
+
+        abort_handles.iter().for_each(AbortHandle::abort);
+    }
+
+    #[tokio::test]
+    async fn test_operations_are_restored_from_journal() {
+        let journal_file = std::env::temp_dir().join(format!("eth_sender_restore_test_{}", std::process::id()));
+        let _ = std::fs::remove_file(&journal_file);
+        let mut config = config(1, true);
+        config.sender.journal_file = Some(journal_file.to_string_lossy().into_owned());
+        let mock = MockEthereum::default();
+
+        let mut sender = ETHSender::<PrivateKeySigner>::from_config(EthereumGateway::Mock(mock.clone()), config.clone()).unwrap();
+        let sent_id = sender.add_operation(vec![1], 100_000.into());
+        let queued_id = sender.add_operation(vec![2], 100_000.into());
+        sender.proceed_next_operations().await;
+        let tx = sent_tx(&sender, sent_id);
+        drop(sender);
+
+        // The sent transaction is monitored again instead of being sent with a new nonce.
+        let mut sender = ETHSender::<PrivateKeySigner>::from_config(EthereumGateway::Mock(mock.clone()), config.clone()).unwrap();
+        assert_eq!(sender.in_flight, vec![sent_id]);
+        assert_eq!(sender.queue, vec![queued_id]);
+        assert_eq!(sent_tx(&sender, sent_id), tx);
+        assert_eq!(sender.add_operation(vec![3], 100_000.into()), 2);
+
+        sender.gateway.get_mut_mock().unwrap().add_successfull_execution(tx.hash, 1).await;
+        sender.proceed_next_operations().await;
+        assert!(matches!(sender.operation(sent_id).unwrap().status, OperationStatus::Committed));
+        drop(sender);
+
+        let sender = ETHSender::<PrivateKeySigner>::from_config(EthereumGateway::Mock(mock), config).unwrap();
+        assert!(matches!(sender.operation(sent_id).unwrap().status, OperationStatus::Committed));
+        assert_eq!(sender.in_flight, vec![queued_id]);
+        assert_eq!(sender.queue, vec![2]);
+        drop(sender);
+
+        // The journal is compacted on start, with the entries of every operation following each other.
+        let (_, entries) = Journal::open(&journal_file).unwrap();
+        assert_eq!(entries.len(), 6);
+        assert!(matches!(entries[1], JournalEntry::Signed { id, .. } if id == sent_id));
+        assert!(matches!(entries[2], JournalEntry::Completed { id, .. } if id == sent_id));
+        std::fs::remove_file(&journal_file).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_completed_operations_are_forgotten() {
+        let mut sender = sender(2, true);
+        let ids: Vec<_> = (0..=MAX_COMPLETED_OPERATIONS)
+            .map(|_| sender.add_operation(vec![1], 100_000.into()))
+            .collect();
+        for id in ids.iter().rev() {
+            sender.queue.pop_back();
+            sender.complete_operation(*id, OperationStatus::Committed);
+        }
+
+        // The operations are forgotten in the order of their ids, not of their completion.
+        assert_eq!(sender.operations.len(), MAX_COMPLETED_OPERATIONS);
+        assert!(sender.operation(ids[0]).is_none());
+        assert!(sender.operation(ids[1]).is_some());
+        assert_eq!(sender.add_operation(vec![2], 100_000.into()), ids.len() as OperationId);
+    }
+
+    #[actix_rt::test]
+    async fn test_restored_nonces_are_reserved() {
+        let journal_file = std::env::temp_dir().join(format!("eth_sender_nonces_test_{}", std::process::id()));
+        let _ = std::fs::remove_file(&journal_file);
+        let mut config = config(2, true);
+        config.sender.journal_file = Some(journal_file.to_string_lossy().into_owned());
+        let node = NodeState::default();
+        let (url, abort_handle) = run_server(node.clone());
+
+        let mut sender = ETHSender::from_config(multiplexed_gateway(&[url.clone()], 2), config.clone()).unwrap();
+        let sent_id = sender.add_operation(vec![1], 100_000.into());
+        sender.proceed_next_operations().await;
+        let queued_id = sender.add_operation(vec![2], 100_000.into());
+        assert_eq!(sent_tx(&sender, sent_id).nonce, 5.into());
+        drop(sender);
+
+        // After the restart, the node rejects the transaction sent again, which may still be mined.
+        *node.rejected_sends.lock().unwrap() = 1;
+        let sender = ETHSender::from_config(multiplexed_gateway(&[url], 2), config).unwrap();
+        let (mut requests, receiver) = mpsc::channel(1);
+        let handle = tokio::spawn(sender.run(receiver));
+        let mut queued = None;
+        for _ in 0..100 {
+            let (resp, operation) = oneshot::channel();
+            requests.send(ETHSenderRequest::GetOperation { id: queued_id, resp }).await.unwrap();
+            let operation = operation.await.unwrap().unwrap();
+            if !operation.txs.is_empty() {
+                queued = Some(operation);
+                break;
+            }
+            time::delay_for(Duration::from_millis(50)).await;
+        }
+
+        // The nonce of the restored transaction is not allocated to the queued operation.
+        let queued = queued.expect("the queued operation is sent");
+        assert_eq!(queued.last_tx().unwrap().nonce, 6.into());
+        assert_eq!(node.calls("eth_sendRawTransaction"), 3);
+
+        drop(requests);
+        handle.await.unwrap();
+        abort_handle.abort();
+        std::fs::remove_file(&journal_file).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_disabled_sender() {
+        let mut sender = sender(2, false);
+        let id = sender.add_operation(vec![1, 2, 3], 100_000.into());
+        sender.proceed_next_operations().await;
+
+        assert!(matches!(sender.operation(id).unwrap().status, OperationStatus::Queued));
+        assert!(sender.gateway.get_mock().unwrap().sent_txs.read().await.is_empty());
+    }
+}
@@ -27,6 +27,10 @@ pub enum SignerError {
     NoSigningKey,
     #[error("Address determination error")]
     DefineAddress,
+    #[error("Invalid keystore: {0}")]
+    InvalidKeystore(String),
+    #[error("Invalid keystore passphrase")]
+    InvalidPassphrase,
     #[error("Recover address from signature failed: {0}")]
     RecoverAddress(String),
     #[error("{0}")]
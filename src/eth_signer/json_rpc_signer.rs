@@ -2,7 +2,7 @@ use crate::eth_signer::error::{RpcSignerError, SignerError};
 use crate::eth_signer::json_rpc_signer::messages::JsonRpcRequest;
 use crate::eth_signer::EthereumSigner;
 use crate::eth_signer::RawTransaction;
-use crate::types::tx::{PackedEthSignature, TxEthSignature};
+use crate::types::tx::{PackedEthSignature, TxEthSignature, TypedData};
 use crate::types::Address;
 use jsonrpc_core::types::response::Output;
 
@@ -94,6 +94,26 @@ impl EthereumSigner for JsonRpcSigner {
         }
     }
 
+    /// Signs the EIP-712 typed data with `eth_signTypedData_v4`,
+    /// and checks that the signature is made by the current account.
+    async fn sign_typed_data(&self, typed_data: &TypedData) -> Result<TxEthSignature, SignerError> {
+        let message = JsonRpcRequest::sign_typed_data(self.address()?, typed_data);
+        let ret = self
+            .post(&message)
+            .await
+            .map_err(|err| SignerError::SigningFailed(err.to_string()))?;
+        let signature: PackedEthSignature = serde_json::from_value(ret).map_err(|err| SignerError::SigningFailed(err.to_string()))?;
+
+        let signer = signature
+            .typed_data_recover_signer(typed_data)
+            .map_err(|err| SignerError::RecoverAddress(err.to_string()))?;
+        if signer == self.address()? {
+            Ok(TxEthSignature::EthereumSignature(signature))
+        } else {
+            Err(SignerError::SigningFailed("Invalid signature from JsonRpcSigner".to_string()))
+        }
+    }
+
     async fn get_address(&self) -> Result<Address, SignerError> {
         self.address()
     }
@@ -254,9 +274,9 @@ impl JsonRpcSigner {
     }
 }
 
-mod messages {
-    use crate::eth_signer::RawTransaction;
-    use crate::types::Address;
+pub(crate) mod messages {
+    use crate::eth_signer::{raw_ethereum_tx::TransactionType, RawTransaction};
+    use crate::types::{tx::TypedData, Address, U256};
     use hex::encode;
 
     #[derive(Debug, Serialize, Deserialize)]
@@ -302,36 +322,80 @@ mod messages {
             Self::create("eth_sign", params)
         }
 
+        /// Signs the EIP-712 typed data. The address to sign with must be unlocked.
+        pub fn sign_typed_data(address: Address, typed_data: &TypedData) -> Self {
+            let params = vec![
+                serde_json::to_value(address).expect("serialization fail"),
+                serde_json::to_value(typed_data).expect("serialization fail"),
+            ];
+            Self::create("eth_signTypedData_v4", params)
+        }
+
         /// Signs a transaction that can be submitted to the network.
         /// The address to sign with must be unlocked.
         pub fn sign_transaction(from: Address, tx_data: RawTransaction) -> Self {
-            let mut params = Vec::new();
+            Self::create("eth_signTransaction", vec![transaction_object(from, tx_data)])
+        }
 
-            // Parameter `To` is optional, so we add it only if it is not None
-            let tx = if let Some(to) = tx_data.to {
-                serde_json::json!({
-                    "from": serde_json::to_value(from).expect("serialization fail"),
-                    "to": serde_json::to_value(to).expect("serialization fail"),
-                    "gas": serde_json::to_value(tx_data.gas).expect("serialization fail"),
-                    "gasPrice": serde_json::to_value(tx_data.gas_price).expect("serialization fail"),
-                    "value": serde_json::to_value(tx_data.value).expect("serialization fail"),
-                    "data": serde_json::to_value(format!("0x{}", encode(tx_data.data))).expect("serialization fail"),
-                    "nonce": serde_json::to_value(tx_data.nonce).expect("serialization fail"),
-                })
-            } else {
-                serde_json::json!({
-                    "from": serde_json::to_value(from).expect("serialization fail"),
-                    "gas": serde_json::to_value(tx_data.gas).expect("serialization fail"),
-                    "gasPrice": serde_json::to_value(tx_data.gas_price).expect("serialization fail"),
-                    "value": serde_json::to_value(tx_data.value).expect("serialization fail"),
-                    "data": serde_json::to_value(format!("0x{}", encode(tx_data.data))).expect("serialization fail"),
-                    "nonce": serde_json::to_value(tx_data.nonce).expect("serialization fail"),
-                })
-            };
-            params.push(tx);
-            Self::create("eth_signTransaction", params)
+        /// Signs a transaction with Clef, which asks the operator of the signer for the approval.
+        pub fn account_sign_transaction(from: Address, tx_data: RawTransaction) -> Self {
+            Self::create("account_signTransaction", vec![transaction_object(from, tx_data)])
+        }
+
+        /// Signs a message with Clef, the `\x19Ethereum Signed Message:\n` prefix is added by the signer.
+        pub fn account_sign_data(address: Address, message: &[u8]) -> Self {
+            let params = vec![
+                serde_json::to_value("text/plain").expect("serialization fail"),
+                serde_json::to_value(address).expect("serialization fail"),
+                serde_json::to_value(format!("0x{}", encode(message))).expect("serialization fail"),
+            ];
+            Self::create("account_signData", params)
+        }
+
+        /// Signs the EIP-712 typed data with Clef.
+        pub fn account_sign_typed_data(address: Address, typed_data: &TypedData) -> Self {
+            let params = vec![
+                serde_json::to_value(address).expect("serialization fail"),
+                serde_json::to_value(typed_data).expect("serialization fail"),
+            ];
+            Self::create("account_signTypedData", params)
         }
     }
+
+    /// Transaction in the format of the `eth_signTransaction` method.
+    fn transaction_object(from: Address, tx_data: RawTransaction) -> serde_json::Value {
+        let mut tx = serde_json::json!({
+            "from": serde_json::to_value(from).expect("serialization fail"),
+            "gas": serde_json::to_value(tx_data.gas).expect("serialization fail"),
+            "value": serde_json::to_value(tx_data.value).expect("serialization fail"),
+            "data": serde_json::to_value(format!("0x{}", encode(&tx_data.data))).expect("serialization fail"),
+            "nonce": serde_json::to_value(tx_data.nonce).expect("serialization fail"),
+        });
+        // Parameter `To` is optional, so we add it only if it is not None
+        if let Some(to) = tx_data.to {
+            tx["to"] = serde_json::to_value(to).expect("serialization fail");
+        }
+        match tx_data.tx_type() {
+            TransactionType::Legacy => {
+                tx["gasPrice"] = serde_json::to_value(tx_data.gas_price).expect("serialization fail");
+            }
+            TransactionType::Eip2930 => {
+                tx["type"] = serde_json::to_value("0x1").expect("serialization fail");
+                tx["gasPrice"] = serde_json::to_value(tx_data.gas_price).expect("serialization fail");
+            }
+            TransactionType::Eip1559 => {
+                tx["type"] = serde_json::to_value("0x2").expect("serialization fail");
+                tx["maxFeePerGas"] = serde_json::to_value(tx_data.max_fee_per_gas).expect("serialization fail");
+                tx["maxPriorityFeePerGas"] =
+                    serde_json::to_value(tx_data.max_priority_fee_per_gas.unwrap_or_default()).expect("serialization fail");
+            }
+        }
+        if tx_data.tx_type().has_access_list() {
+            tx["chainId"] = serde_json::to_value(U256::from(tx_data.chain_id)).expect("serialization fail");
+            tx["accessList"] = serde_json::to_value(tx_data.access_list.unwrap_or_default()).expect("serialization fail");
+        }
+        tx
+    }
 }
 
 #[cfg(test)]
@@ -343,11 +407,12 @@ mod tests {
     use serde_json::json;
 
     use crate::types::{
-        tx::{PackedEthSignature, TxEthSignature},
-        Address,
+        tx::{PackedEthSignature, TxEthSignature, TypedData},
+        Address, H256,
     };
 
     use crate::eth_signer::json_rpc_signer::{is_signature_from_address, messages::JsonRpcRequest};
+    use crate::eth_signer::raw_ethereum_tx::AccessListItem;
     use crate::eth_signer::{EthereumSigner, JsonRpcSigner, RawTransaction};
 
     #[post("/")]
@@ -369,6 +434,11 @@ mod tests {
                 let signature = PackedEthSignature::sign(state.key_pairs[0].secret(), &data_bytes).unwrap();
                 create_success(json!(signature))
             }
+            "eth_signTypedData_v4" => {
+                let typed_data: TypedData = serde_json::from_value(req.params[1].clone()).unwrap();
+                let signature = PackedEthSignature::sign_typed_data(state.key_pairs[0].secret(), &typed_data).unwrap();
+                create_success(json!(signature))
+            }
             "eth_signTransaction" => {
                 let tx_value = json!(req.params[0].clone()).to_string();
                 let tx = tx_value.as_bytes();
@@ -448,10 +518,79 @@ mod tests {
                 gas_price: Default::default(),
                 gas: Default::default(),
                 data: vec![],
+                max_fee_per_gas: None,
+                max_priority_fee_per_gas: None,
+                access_list: None,
             })
             .await
             .unwrap();
         assert_ne!(transaction_signature.len(), 0);
+
+        let typed_data: TypedData = serde_json::from_value(json!({
+            "types": {
+                "EIP712Domain": [{ "name": "name", "type": "string" }, { "name": "chainId", "type": "uint256" }],
+                "Message": [{ "name": "data", "type": "bytes" }, { "name": "nonces", "type": "uint32[]" }],
+            },
+            "primaryType": "Message",
+            "domain": { "name": "Fluidex", "chainId": 9 },
+            "message": { "data": "0x0102", "nonces": [1, 2] },
+        }))
+        .unwrap();
+        if let TxEthSignature::EthereumSignature(signature) = client.sign_typed_data(&typed_data).await.unwrap() {
+            assert_eq!(signature.typed_data_recover_signer(&typed_data).unwrap(), client.address().unwrap());
+        } else {
+            panic!("Wrong signature type")
+        }
         abort_handle.abort();
     }
+
+    #[test]
+    fn test_eip1559_sign_transaction_request() {
+        let request = JsonRpcRequest::sign_transaction(
+            Address::zero(),
+            RawTransaction {
+                chain_id: 1,
+                max_fee_per_gas: Some(100.into()),
+                max_priority_fee_per_gas: Some(2.into()),
+                ..Default::default()
+            },
+        );
+        let tx = &request.params[0];
+        assert_eq!(tx["type"], "0x2");
+        assert_eq!(tx["chainId"], "0x1");
+        assert_eq!(tx["maxFeePerGas"], "0x64");
+        assert_eq!(tx["maxPriorityFeePerGas"], "0x2");
+        assert_eq!(tx["accessList"], json!([]));
+        assert!(tx.get("gasPrice").is_none());
+        assert!(tx.get("to").is_none());
+    }
+
+    #[test]
+    fn test_eip2930_sign_transaction_request() {
+        let storage_key = H256::from_low_u64_be(1);
+        let request = JsonRpcRequest::sign_transaction(
+            Address::zero(),
+            RawTransaction {
+                chain_id: 1,
+                gas_price: 100.into(),
+                access_list: Some(vec![AccessListItem {
+                    address: Address::repeat_byte(0x11),
+                    storage_keys: vec![storage_key],
+                }]),
+                ..Default::default()
+            },
+        );
+        let tx = &request.params[0];
+        assert_eq!(tx["type"], "0x1");
+        assert_eq!(tx["chainId"], "0x1");
+        assert_eq!(tx["gasPrice"], "0x64");
+        assert_eq!(
+            tx["accessList"],
+            json!([{
+                "address": "0x1111111111111111111111111111111111111111",
+                "storageKeys": ["0x0000000000000000000000000000000000000000000000000000000000000001"],
+            }])
+        );
+        assert!(tx.get("maxFeePerGas").is_none());
+    }
 }
@@ -0,0 +1,196 @@
+//! Decryption of the Ethereum keystore files (Web3 Secret Storage, version 3),
+//! as created by `geth account new`, MetaMask, etc.
+
+use crate::eth_signer::{PrivateKeySigner, SignerError};
+use crate::types::{Address, H256};
+use parity_crypto::Keccak256;
+use serde::Deserialize;
+use std::num::NonZeroU32;
+use zeroize::Zeroizing;
+
+/// Variable containing the keystore passphrase, used if no passphrase file is configured.
+pub const KEYSTORE_PASSPHRASE_VAR: &str = "ETH_WATCH_KEYSTORE_PASSPHRASE";
+
+/// Contents of the V3 keystore file.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Keystore {
+    /// Address of the account, hex without the `0x` prefix. Optional, since it discloses the owner.
+    #[serde(default)]
+    pub address: Option<String>,
+    #[serde(alias = "Crypto")]
+    pub crypto: KeystoreCrypto,
+    pub version: u32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct KeystoreCrypto {
+    pub cipher: String,
+    pub cipherparams: CipherParams,
+    pub ciphertext: String,
+    pub kdf: String,
+    pub kdfparams: KdfParams,
+    pub mac: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CipherParams {
+    pub iv: String,
+}
+
+/// Parameters of the key derivation function, either `scrypt` or `pbkdf2`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum KdfParams {
+    Scrypt { dklen: u32, n: u32, p: u32, r: u32, salt: String },
+    Pbkdf2 { c: u32, dklen: u32, prf: String, salt: String },
+}
+
+fn invalid(message: impl Into<String>) -> SignerError {
+    SignerError::InvalidKeystore(message.into())
+}
+
+fn decode_hex(name: &str, value: &str) -> Result<Vec<u8>, SignerError> {
+    hex::decode(value.strip_prefix("0x").unwrap_or(value)).map_err(|err| invalid(format!("{} is not a hex string: {}", name, err)))
+}
+
+impl Keystore {
+    /// Reads the keystore from the JSON file.
+    pub fn from_file(path: &str) -> Result<Self, SignerError> {
+        let contents = std::fs::read_to_string(path).map_err(|err| invalid(format!("unable to read {}: {}", path, err)))?;
+        serde_json::from_str(&contents).map_err(|err| invalid(format!("unable to parse {}: {}", path, err)))
+    }
+
+    /// Decrypts the private key. The wrong passphrase is detected by the MAC check.
+    pub fn decrypt(&self, passphrase: &[u8]) -> Result<PrivateKeySigner, SignerError> {
+        let crypto = &self.crypto;
+        if self.version != 3 {
+            return Err(invalid(format!("unsupported version {}", self.version)));
+        }
+        if crypto.cipher != "aes-128-ctr" {
+            return Err(invalid(format!("unsupported cipher {}", crypto.cipher)));
+        }
+
+        // The first half of the derived key is the AES key, the second one is used for the MAC.
+        let (aes_key, mac_key) = match &crypto.kdfparams {
+            KdfParams::Scrypt { dklen, n, p, r, salt } if crypto.kdf == "scrypt" && *dklen == 32 => {
+                let salt = decode_hex("salt", salt)?;
+                parity_crypto::scrypt::derive_key(passphrase, &salt, *n, *p, *r)
+                    .map_err(|err| invalid(format!("invalid scrypt parameters: {}", err)))?
+            }
+            KdfParams::Pbkdf2 { c, dklen, prf, salt } if crypto.kdf == "pbkdf2" && *dklen == 32 && prf == "hmac-sha256" => {
+                let salt = decode_hex("salt", salt)?;
+                let iterations = NonZeroU32::new(*c).ok_or_else(|| invalid("pbkdf2 iterations count is zero"))?;
+                parity_crypto::derive_key_iterations(passphrase, &salt, iterations)
+            }
+            _ => return Err(invalid(format!("unsupported key derivation {} {:?}", crypto.kdf, crypto.kdfparams))),
+        };
+        let (aes_key, mac_key) = (Zeroizing::new(aes_key), Zeroizing::new(mac_key));
+
+        let ciphertext = decode_hex("ciphertext", &crypto.ciphertext)?;
+        let mac_input: Zeroizing<Vec<u8>> = Zeroizing::new(mac_key.iter().chain(ciphertext.iter()).copied().collect());
+        if !parity_crypto::is_equal(&mac_input.as_slice().keccak256(), &decode_hex("mac", &crypto.mac)?) {
+            return Err(SignerError::InvalidPassphrase);
+        }
+
+        if ciphertext.len() != 32 {
+            return Err(invalid("private key must be 32 bytes long"));
+        }
+        let iv = decode_hex("iv", &crypto.cipherparams.iv)?;
+        let mut private_key = Zeroizing::new([0u8; 32]);
+        parity_crypto::aes::decrypt_128_ctr(&aes_key, &iv, &ciphertext, &mut private_key[..])
+            .map_err(|err| invalid(format!("decryption failed: {}", err)))?;
+
+        Ok(PrivateKeySigner::new(H256::from(*private_key)))
+    }
+
+    /// Address stored in the keystore, if any.
+    pub fn address(&self) -> Result<Option<Address>, SignerError> {
+        match &self.address {
+            Some(address) => {
+                let bytes = decode_hex("address", address)?;
+                if bytes.len() != 20 {
+                    return Err(invalid("address must be 20 bytes long"));
+                }
+                Ok(Some(Address::from_slice(&bytes)))
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+/// Reads the keystore passphrase from the file, or from the `ETH_WATCH_KEYSTORE_PASSPHRASE` variable
+/// if no file is given. The trailing line break of the file is ignored.
+pub fn read_passphrase(passphrase_file: Option<&str>) -> Result<Zeroizing<String>, SignerError> {
+    let mut passphrase = match passphrase_file {
+        Some(path) => Zeroizing::new(
+            std::fs::read_to_string(path).map_err(|err| invalid(format!("unable to read the passphrase from {}: {}", path, err)))?,
+        ),
+        None => {
+            Zeroizing::new(std::env::var(KEYSTORE_PASSPHRASE_VAR).map_err(|_| invalid(format!("{} is not set", KEYSTORE_PASSPHRASE_VAR)))?)
+        }
+    };
+    let len = passphrase.trim_end_matches(&['\r', '\n'][..]).len();
+    passphrase.truncate(len);
+    Ok(passphrase)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::eth_signer::EthereumSigner;
+
+    const PASSPHRASE: &[u8] = b"testpassword";
+
+    fn keystore(crypto: serde_json::Value) -> Keystore {
+        serde_json::from_value(serde_json::json!({
+            "address": "008aeeda4d805471df9b2a5b0f38a0c3bcba786b",
+            "crypto": crypto,
+            "id": "3198bc9c-6672-5ab3-d995-4942343ae5b6",
+            "version": 3,
+        }))
+        .unwrap()
+    }
+
+    async fn assert_decrypts(keystore: Keystore) {
+        let signer = keystore.decrypt(PASSPHRASE).unwrap();
+        assert_eq!(Some(signer.get_address().await.unwrap()), keystore.address().unwrap());
+        assert_eq!(keystore.decrypt(b"wrongpassword").unwrap_err(), SignerError::InvalidPassphrase);
+    }
+
+    #[tokio::test]
+    async fn test_decrypt_scrypt() {
+        assert_decrypts(keystore(serde_json::json!({
+            "cipher": "aes-128-ctr",
+            "cipherparams": { "iv": "101112131415161718191a1b1c1d1e1f" },
+            "ciphertext": "b2f0b7ccdba8068a3661fe42fe435e9d672e615aaaaad745b84a08fa1e741ee7",
+            "kdf": "scrypt",
+            "kdfparams": {
+                "dklen": 32,
+                "n": 1024,
+                "p": 1,
+                "r": 8,
+                "salt": "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f",
+            },
+            "mac": "07d323eed6af56b50142373e46e62d92e2beaa599dec2b7461926353d05b594f",
+        })))
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_decrypt_pbkdf2() {
+        assert_decrypts(keystore(serde_json::json!({
+            "cipher": "aes-128-ctr",
+            "cipherparams": { "iv": "101112131415161718191a1b1c1d1e1f" },
+            "ciphertext": "1de23619f07fc02588d4095ec4935c0808582715e2c837a36087f4355d126495",
+            "kdf": "pbkdf2",
+            "kdfparams": {
+                "c": 1024,
+                "dklen": 32,
+                "prf": "hmac-sha256",
+                "salt": "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f",
+            },
+            "mac": "4ceb280898d20ea395bfa8cafea037088e1e8f9bef488312e9cd250f89fed4f0",
+        })))
+        .await;
+    }
+}
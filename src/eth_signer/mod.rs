@@ -1,20 +1,108 @@
-use crate::types::tx::TxEthSignature;
+use crate::config::configs::eth_sender::{Sender, SignerConfig};
+use crate::types::tx::{TxEthSignature, TypedData};
 use crate::types::Address;
 use async_trait::async_trait;
 use error::SignerError;
+use json_rpc_signer::{AddressOrIndex, SignerType};
 
 pub use json_rpc_signer::JsonRpcSigner;
 pub use pk_signer::PrivateKeySigner;
 pub use raw_ethereum_tx::RawTransaction;
+pub use remote_signer::RemoteSigner;
 
 pub mod error;
 pub mod json_rpc_signer;
+pub mod keystore;
 pub mod pk_signer;
 pub mod raw_ethereum_tx;
+pub mod remote_signer;
+
+/// Placeholder for the signer in the clients which only read from the Ethereum node.
+///
+/// It deliberately doesn't implement `EthereumSigner`, so the methods signing or sending
+/// transactions can't be called on such clients.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReadOnly;
 
 #[async_trait]
 pub trait EthereumSigner: Send + Sync + Clone {
     async fn sign_message(&self, message: &[u8]) -> Result<TxEthSignature, SignerError>;
     async fn sign_transaction(&self, raw_tx: RawTransaction) -> Result<Vec<u8>, SignerError>;
+    /// Signs the structured data according to EIP-712.
+    async fn sign_typed_data(&self, typed_data: &TypedData) -> Result<TxEthSignature, SignerError>;
     async fn get_address(&self) -> Result<Address, SignerError>;
 }
+
+/// Signer of the operator account, chosen by the `eth_sender.sender.signer` settings
+/// (see `signer_from_config`).
+#[derive(Debug, Clone)]
+pub enum OperatorSigner {
+    PrivateKey(PrivateKeySigner),
+    JsonRpc(JsonRpcSigner),
+    Remote(RemoteSigner),
+}
+
+/// Creates the configured operator signer, decrypting the keystore or connecting to the signing node if required.
+/// Fails if the signer doesn't control the `operator_commit_eth_addr` account.
+pub async fn signer_from_config(sender: &Sender) -> Result<OperatorSigner, SignerError> {
+    let signer = match &sender.signer {
+        SignerConfig::PrivateKey => {
+            let private_key = sender.operator_private_key.ok_or(SignerError::MissingEthPrivateKey)?;
+            OperatorSigner::PrivateKey(PrivateKeySigner::new(private_key))
+        }
+        SignerConfig::Keystore { path, passphrase_file } => {
+            let passphrase = keystore::read_passphrase(passphrase_file.as_deref())?;
+            OperatorSigner::PrivateKey(keystore::Keystore::from_file(path)?.decrypt(passphrase.as_bytes())?)
+        }
+        SignerConfig::JsonRpc { url, address } => {
+            let address = address.map(AddressOrIndex::Address);
+            // Ethereum nodes add the prefix to the signed messages themselves.
+            OperatorSigner::JsonRpc(JsonRpcSigner::new(url.as_str(), address, Some(SignerType::NotNeedPrefix), None).await?)
+        }
+        SignerConfig::Remote(remote) => OperatorSigner::Remote(RemoteSigner::from_config(remote, sender.operator_commit_eth_addr)?),
+    };
+
+    let address = signer.get_address().await?;
+    if address != sender.operator_commit_eth_addr {
+        return Err(SignerError::CustomError(format!(
+            "signer controls {:?} instead of the operator account {:?}",
+            address, sender.operator_commit_eth_addr
+        )));
+    }
+    Ok(signer)
+}
+
+#[async_trait]
+impl EthereumSigner for OperatorSigner {
+    async fn sign_message(&self, message: &[u8]) -> Result<TxEthSignature, SignerError> {
+        match self {
+            Self::PrivateKey(signer) => signer.sign_message(message).await,
+            Self::JsonRpc(signer) => signer.sign_message(message).await,
+            Self::Remote(signer) => signer.sign_message(message).await,
+        }
+    }
+
+    async fn sign_transaction(&self, raw_tx: RawTransaction) -> Result<Vec<u8>, SignerError> {
+        match self {
+            Self::PrivateKey(signer) => signer.sign_transaction(raw_tx).await,
+            Self::JsonRpc(signer) => signer.sign_transaction(raw_tx).await,
+            Self::Remote(signer) => signer.sign_transaction(raw_tx).await,
+        }
+    }
+
+    async fn sign_typed_data(&self, typed_data: &TypedData) -> Result<TxEthSignature, SignerError> {
+        match self {
+            Self::PrivateKey(signer) => signer.sign_typed_data(typed_data).await,
+            Self::JsonRpc(signer) => signer.sign_typed_data(typed_data).await,
+            Self::Remote(signer) => signer.sign_typed_data(typed_data).await,
+        }
+    }
+
+    async fn get_address(&self) -> Result<Address, SignerError> {
+        match self {
+            Self::PrivateKey(signer) => signer.get_address().await,
+            Self::JsonRpc(signer) => signer.get_address().await,
+            Self::Remote(signer) => signer.get_address().await,
+        }
+    }
+}
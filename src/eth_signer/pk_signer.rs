@@ -1,8 +1,9 @@
 use crate::eth_signer::raw_ethereum_tx::RawTransaction;
 use crate::eth_signer::{EthereumSigner, SignerError};
-use crate::types::tx::{PackedEthSignature, TxEthSignature};
+use crate::types::tx::{PackedEthSignature, TxEthSignature, TypedData};
 use crate::types::{Address, H256};
 use parity_crypto::publickey::sign;
+use zeroize::Zeroize;
 
 #[derive(Clone)]
 pub struct PrivateKeySigner {
@@ -21,6 +22,13 @@ impl PrivateKeySigner {
     }
 }
 
+// Every copy of the key is wiped from the memory once it's no longer used.
+impl Drop for PrivateKeySigner {
+    fn drop(&mut self) {
+        self.private_key.as_bytes_mut().zeroize();
+    }
+}
+
 #[async_trait::async_trait]
 impl EthereumSigner for PrivateKeySigner {
     /// Get Ethereum address that matches the private key.
@@ -40,14 +48,27 @@ impl EthereumSigner for PrivateKeySigner {
         let sig = sign(&self.private_key.into(), &raw_tx.hash().into()).map_err(|_| SignerError::NoSigningKey)?;
         Ok(raw_tx.rlp_encode_tx(sig))
     }
+
+    /// The sign method calculates an EIP-712 signature with:
+    /// sign(keccak256("\x19\x01" + domainSeparator + hashStruct(message))).
+    async fn sign_typed_data(&self, typed_data: &TypedData) -> Result<TxEthSignature, SignerError> {
+        let pack = PackedEthSignature::sign_typed_data(&self.private_key, typed_data)
+            .map_err(|err| SignerError::SigningFailed(err.to_string()))?;
+        Ok(TxEthSignature::EthereumSignature(pack))
+    }
 }
 
 #[cfg(test)]
 mod test {
+    use crate::eth_signer::raw_ethereum_tx::{decode_signature, AccessListItem};
     use crate::eth_signer::EthereumSigner;
     use crate::eth_signer::PrivateKeySigner;
     use crate::eth_signer::RawTransaction;
+    use crate::types::tx::{TxEthSignature, TypedData};
     use crate::types::{H160, H256, U256};
+    use parity_crypto::publickey::{public_to_address, recover};
+    use parity_crypto::Keccak256;
+    use rlp::Rlp;
 
     #[tokio::test]
     async fn test_generating_signature() {
@@ -61,6 +82,9 @@ mod test {
             gas_price: U256::from(1),
             gas: U256::from(2),
             data: vec![1, 2, 3],
+            max_fee_per_gas: None,
+            max_priority_fee_per_gas: None,
+            access_list: None,
         };
         let signature = signer.sign_transaction(raw_transaction.clone()).await.unwrap();
         assert_ne!(signature.len(), 1);
@@ -73,4 +97,202 @@ mod test {
         ];
         assert_eq!(signature, precalculated_signature);
     }
+
+    #[tokio::test]
+    async fn test_eip1559_signature() {
+        // The dynamic fee transaction from the `Account.sign_transaction` examples of eth-account.
+        let signer = PrivateKeySigner::new(H256::from_slice(
+            &hex::decode("4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318").unwrap(),
+        ));
+        let raw_transaction = RawTransaction {
+            chain_id: 1900,
+            nonce: U256::from(34),
+            to: Some(H160::from_slice(&hex::decode("09616c3d61b3331fc4109a9e41a8bdb7d9776609").unwrap())),
+            value: U256::from(0x5af3107a4000u64),
+            gas_price: U256::zero(),
+            gas: U256::from(100_000),
+            data: b"abcdef".to_vec(),
+            max_fee_per_gas: Some(U256::from(2_000_000_000)),
+            max_priority_fee_per_gas: Some(U256::from(2_000_000_000)),
+            access_list: Some(vec![AccessListItem {
+                address: H160::from_low_u64_be(1),
+                storage_keys: vec![H256::from_slice(&hex::decode(format!("01{}", "00".repeat(31))).unwrap())],
+            }]),
+        };
+
+        let signed_tx = signer.sign_transaction(raw_transaction).await.unwrap();
+        assert_eq!(
+            hex::encode(&signed_tx),
+            "02f8b282076c2284773594008477359400830186a09409616c3d61b3331fc4109a9e41a8bdb7d9776609865af3107a40008661\
+             6263646566f838f7940000000000000000000000000000000000000001e1a00100000000000000000000000000000000000000\
+             00000000000000000000000080a0f366b34a5c206859b9778b4c909207e53443cca9e0b82e0b94bc4b47e6434d3da04a731eda\
+             413a944d4ea2d2236671e586e57388d0e9d40db53044ae4089f2aec8"
+        );
+        assert_eq!(
+            hex::encode(signed_tx.keccak256()),
+            "126431f2a7fda003aada7c2ce52b0ce3cbdbb1896230d3333b9eea24f42d15b0"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_eip2930_signature() {
+        // The access list transaction from the `Account.sign_transaction` examples of eth-account.
+        let signer = PrivateKeySigner::new(H256::from_slice(
+            &hex::decode("4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318").unwrap(),
+        ));
+        let raw_transaction = RawTransaction {
+            chain_id: 1900,
+            nonce: U256::from(34),
+            to: Some(H160::from_slice(&hex::decode("09616c3d61b3331fc4109a9e41a8bdb7d9776609").unwrap())),
+            value: U256::from(0x5af3107a4000u64),
+            gas_price: U256::from(1_000_000_000),
+            gas: U256::from(100_000),
+            data: b"abcdef".to_vec(),
+            max_fee_per_gas: None,
+            max_priority_fee_per_gas: None,
+            access_list: Some(vec![AccessListItem {
+                address: H160::from_low_u64_be(1),
+                storage_keys: vec![H256::from_slice(&hex::decode(format!("01{}", "00".repeat(31))).unwrap())],
+            }]),
+        };
+
+        let signed_tx = signer.sign_transaction(raw_transaction).await.unwrap();
+        assert_eq!(
+            hex::encode(&signed_tx),
+            "01f8ad82076c22843b9aca00830186a09409616c3d61b3331fc4109a9e41a8bdb7d9776609865af3107a400086616263646566\
+             f838f7940000000000000000000000000000000000000001e1a001000000000000000000000000000000000000000000000000\
+             0000000000000080a0ea38506c4afe4bb402e030877fbe1011fa1da47aabcf215db8da8fee5d3af086a051e9af653b8eb98e74\
+             e894a766cf88904dbdb10b0bc1fbd12f18f661fa2797a4"
+        );
+        assert_eq!(
+            hex::encode(signed_tx.keccak256()),
+            "2864ca20a74ca5e044067ad4139a22ff5a0853434f5f1dc00108f24ef5f1f783"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_large_chain_id_signature() {
+        // The example from the EIP-155 specification.
+        let signer = PrivateKeySigner::new(H256::from([0x46; 32]));
+        let raw_transaction = |chain_id| RawTransaction {
+            chain_id,
+            nonce: U256::from(9),
+            to: Some(H160::from([0x35; 20])),
+            value: U256::from(1_000_000_000_000_000_000u64),
+            gas_price: U256::from(20_000_000_000u64),
+            gas: U256::from(21_000),
+            data: vec![],
+            max_fee_per_gas: None,
+            max_priority_fee_per_gas: None,
+            access_list: None,
+        };
+
+        let mainnet_transaction = raw_transaction(1);
+        assert_eq!(
+            hex::encode(mainnet_transaction.signing_payload()),
+            "ec098504a817c800825208943535353535353535353535353535353535353535880de0b6b3a764000080018080"
+        );
+        assert_eq!(
+            hex::encode(mainnet_transaction.hash()),
+            "daf5a779ae972f972197303d7b574746c7ef83eadac0f2791ad23db92e4c8e53"
+        );
+        let signed_tx = signer.sign_transaction(mainnet_transaction).await.unwrap();
+        assert_eq!(
+            hex::encode(signed_tx),
+            "f86c098504a817c800825208943535353535353535353535353535353535353535880de0b6b3a76400008025a028ef61340bd9\
+             39bc2195fe537567866003e1a15d3c71ff63e1590620aa636276a067cbe9d8997f761aecb703304b3800ccf555c9f3dc64214b\
+             297fb1966a3b6d83"
+        );
+
+        // EIP-155 `v` doesn't fit into a byte for these chains, e.g. Sepolia.
+        let address = signer.get_address().await.unwrap();
+        for &chain_id in &[421_613u64, 11_155_111] {
+            let raw_transaction = raw_transaction(chain_id);
+            let payload = raw_transaction.signing_payload();
+            assert_eq!(Rlp::new(&payload).val_at::<u64>(6).unwrap(), chain_id);
+
+            let signed_tx = signer.sign_transaction(raw_transaction.clone()).await.unwrap();
+            let v: u64 = Rlp::new(&signed_tx).val_at(6).unwrap();
+            let signature = decode_signature(&signed_tx).unwrap();
+            assert_eq!(v, u64::from(signature.v()) + chain_id * 2 + 35);
+            let signer_key = recover(&signature, &raw_transaction.hash().into()).unwrap();
+            assert_eq!(public_to_address(&signer_key), address);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_eip712_signature() {
+        // The example from the EIP-712 specification.
+        let typed_data: TypedData = serde_json::from_value(serde_json::json!({
+            "types": {
+                "EIP712Domain": [
+                    { "name": "name", "type": "string" },
+                    { "name": "version", "type": "string" },
+                    { "name": "chainId", "type": "uint256" },
+                    { "name": "verifyingContract", "type": "address" },
+                ],
+                "Person": [
+                    { "name": "name", "type": "string" },
+                    { "name": "wallet", "type": "address" },
+                ],
+                "Mail": [
+                    { "name": "from", "type": "Person" },
+                    { "name": "to", "type": "Person" },
+                    { "name": "contents", "type": "string" },
+                ],
+            },
+            "primaryType": "Mail",
+            "domain": {
+                "name": "Ether Mail",
+                "version": "1",
+                "chainId": 1,
+                "verifyingContract": "0xCcCCccccCCCCcCCCCCCcCcCccCcCCCcCcccccccC",
+            },
+            "message": {
+                "from": { "name": "Cow", "wallet": "0xCD2a3d9F938E13CD947Ec05AbC7FE734Df8DD826" },
+                "to": { "name": "Bob", "wallet": "0xbBbBBBBbbBBBbbbBbbBbbbbBBbBbbbbBbBbbBBbB" },
+                "contents": "Hello, Bob!",
+            },
+        }))
+        .unwrap();
+
+        assert_eq!(
+            typed_data.encode_type("Mail").unwrap(),
+            "Mail(Person from,Person to,string contents)Person(string name,address wallet)"
+        );
+        assert_eq!(
+            hex::encode(typed_data.domain_separator().unwrap()),
+            "f2cee375fa42b42143804025fc449deafd50cc031ca257e0b194a650a912090f"
+        );
+        assert_eq!(
+            hex::encode(typed_data.hash_struct("Mail", &typed_data.message).unwrap()),
+            "c52c0ee5d84264471806290a3f2c4cecfc5490626bf912d01f240d7a274b371e"
+        );
+        assert_eq!(
+            hex::encode(typed_data.sign_hash().unwrap()),
+            "be609aee343fb3c4b28e1df9e632fca64fcfaede20f02e86244efddf30957bd2"
+        );
+
+        let signer = PrivateKeySigner::new(b"cow".keccak256().into());
+        let signature = match signer.sign_typed_data(&typed_data).await.unwrap() {
+            TxEthSignature::EthereumSignature(signature) => signature,
+            TxEthSignature::EIP1271Signature(_) => panic!("Wrong signature type"),
+        };
+        assert_eq!(
+            hex::encode(&signature.serialize_packed()[..]),
+            "4355c47d63924e8a72e509b65029052eb6c299d53a04e167c5775fd466751c9d\
+             07299936d304c153f6443dfa05f40ff007d72911b6f72307f996231605b915621c"
+        );
+        assert_eq!(
+            signature.typed_data_recover_signer(&typed_data).unwrap(),
+            signer.get_address().await.unwrap()
+        );
+
+        let mut tampered = typed_data;
+        tampered.message["contents"] = "Hello, Alice!".into();
+        assert_ne!(
+            signature.typed_data_recover_signer(&tampered).unwrap(),
+            signer.get_address().await.unwrap()
+        );
+    }
 }
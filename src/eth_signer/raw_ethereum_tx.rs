@@ -1,26 +1,69 @@
-use crate::types::{H160, U256};
+use crate::types::{H160, H256, U256};
 use parity_crypto::{publickey::Signature, Keccak256};
-use rlp::RlpStream;
+use rlp::{DecoderError, Rlp, RlpStream};
 use serde::{Deserialize, Serialize};
 
+/// Envelope type of the transaction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TransactionType {
+    /// Pre-London transaction with a single gas price, signed according to EIP-155.
+    Legacy,
+    /// Type-1 transaction with an access list (EIP-2930).
+    Eip2930,
+    /// Type-2 transaction with dynamic fees (EIP-1559).
+    Eip1559,
+}
+
+impl TransactionType {
+    /// Whether the transaction contains an access list.
+    pub fn has_access_list(self) -> bool {
+        self != Self::Legacy
+    }
+}
+
+/// Storage slots of a single account accessed by the transaction (EIP-2930).
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct AccessListItem {
+    pub address: H160,
+    #[serde(rename = "storageKeys")]
+    pub storage_keys: Vec<H256>,
+}
+
+impl Default for TransactionType {
+    fn default() -> Self {
+        Self::Legacy
+    }
+}
+
 /// Description of a Transaction, pending or in the chain.
 #[derive(Debug, Default, Clone, PartialEq, Deserialize, Serialize)]
 pub struct RawTransaction {
     /// Chain id: mainnet => 1, rinkeby => 4, ropsten => 43, etc.
-    pub chain_id: u8,
+    pub chain_id: u64,
     /// Nonce
     pub nonce: U256,
     /// Recipient (None when contract creation)
     pub to: Option<H160>,
     /// Transfered value
     pub value: U256,
-    /// Gas Price
+    /// Gas Price, only used by the legacy and EIP-2930 transactions.
     #[serde(rename = "gasPrice")]
     pub gas_price: U256,
+    /// Maximum total fee per gas. If set, the transaction is an EIP-1559 one.
+    #[serde(rename = "maxFeePerGas", default)]
+    pub max_fee_per_gas: Option<U256>,
+    /// Maximum fee per gas paid to the miner on top of the base fee (EIP-1559).
+    #[serde(rename = "maxPriorityFeePerGas", default)]
+    pub max_priority_fee_per_gas: Option<U256>,
     /// Gas amount
     pub gas: U256,
     /// Input data
     pub data: Vec<u8>,
+    /// Accounts and storage slots warmed up by the transaction. If set, and no EIP-1559 fees are set,
+    /// the transaction is an EIP-2930 one.
+    #[serde(rename = "accessList", default)]
+    pub access_list: Option<Vec<AccessListItem>>,
 }
 
 fn find_first_nonzero(vector: &[u8]) -> usize {
@@ -37,37 +80,98 @@ fn find_first_nonzero(vector: &[u8]) -> usize {
 }
 
 impl RawTransaction {
+    pub fn tx_type(&self) -> TransactionType {
+        if self.max_fee_per_gas.is_some() {
+            TransactionType::Eip1559
+        } else if self.access_list.is_some() {
+            TransactionType::Eip2930
+        } else {
+            TransactionType::Legacy
+        }
+    }
+
+    /// Encodes the signed transaction: RLP list for the legacy transactions,
+    /// and the typed envelope `type || rlp(fields)` (EIP-2718) for the others.
     pub fn rlp_encode_tx(&self, sig: Signature) -> Vec<u8> {
-        let signature = to_ecdsa(sig, self.chain_id);
         let mut tx = RlpStream::new();
         tx.begin_unbounded_list();
-        self.encode(&mut tx);
-        tx.append(&signature.v);
-        let r_start = find_first_nonzero(&signature.r);
-        let r = &signature.r[r_start..];
-        tx.append(&r);
-        let s_start = find_first_nonzero(&signature.s);
-        let s = &signature.s[s_start..];
-        tx.append(&s);
+        match self.tx_type() {
+            TransactionType::Legacy => {
+                self.encode(&mut tx);
+                let signature = to_ecdsa(sig, self.chain_id);
+                tx.append(&signature.v);
+                append_signature_rs(&mut tx, &signature.r, &signature.s);
+            }
+            TransactionType::Eip2930 | TransactionType::Eip1559 => {
+                self.encode_typed(&mut tx);
+                // Typed transactions contain the plain y-parity of the signature.
+                tx.append(&sig.v());
+                append_signature_rs(&mut tx, sig.r(), sig.s());
+            }
+        }
         tx.finalize_unbounded_list();
-        tx.out()
+        self.with_type_prefix(tx.out())
     }
 
+    /// Hash to be signed.
     pub fn hash(&self) -> [u8; 32] {
-        let mut hash = RlpStream::new();
-        hash.begin_unbounded_list();
-        self.encode(&mut hash);
-        hash.append(&vec![self.chain_id]);
-        hash.append(&U256::zero());
-        hash.append(&U256::zero());
-        hash.finalize_unbounded_list();
-        hash.out().keccak256()
+        self.signing_payload().keccak256()
+    }
+
+    /// Unsigned transaction, which is hashed to get the hash to be signed.
+    /// Remote signers accepting arbitrary data are given this payload.
+    pub fn signing_payload(&self) -> Vec<u8> {
+        let mut payload = RlpStream::new();
+        payload.begin_unbounded_list();
+        match self.tx_type() {
+            TransactionType::Legacy => {
+                self.encode(&mut payload);
+                payload.append(&self.chain_id);
+                payload.append(&U256::zero());
+                payload.append(&U256::zero());
+            }
+            TransactionType::Eip2930 | TransactionType::Eip1559 => self.encode_typed(&mut payload),
+        }
+        payload.finalize_unbounded_list();
+        self.with_type_prefix(payload.out())
     }
 
     pub fn encode(&self, s: &mut RlpStream) {
         s.append(&self.nonce);
         s.append(&self.gas_price);
         s.append(&self.gas);
+        self.encode_call(s);
+    }
+
+    /// Encodes the unsigned fields of the EIP-2930 and EIP-1559 transactions.
+    fn encode_typed(&self, s: &mut RlpStream) {
+        s.append(&self.chain_id);
+        s.append(&self.nonce);
+        if self.tx_type() == TransactionType::Eip1559 {
+            s.append(&self.max_priority_fee_per_gas.unwrap_or_default());
+            s.append(&self.max_fee_per_gas.unwrap_or_default());
+        } else {
+            s.append(&self.gas_price);
+        }
+        s.append(&self.gas);
+        self.encode_call(s);
+        self.encode_access_list(s);
+    }
+
+    fn encode_access_list(&self, s: &mut RlpStream) {
+        let access_list = self.access_list.as_deref().unwrap_or_default();
+        s.begin_list(access_list.len());
+        for item in access_list {
+            s.begin_list(2);
+            s.append(&item.address);
+            s.begin_list(item.storage_keys.len());
+            for key in &item.storage_keys {
+                s.append(key);
+            }
+        }
+    }
+
+    fn encode_call(&self, s: &mut RlpStream) {
         if let Some(ref t) = self.to {
             s.append(t);
         } else {
@@ -76,18 +180,71 @@ impl RawTransaction {
         s.append(&self.value);
         s.append(&self.data);
     }
+
+    fn with_type_prefix(&self, payload: Vec<u8>) -> Vec<u8> {
+        let tx_type = match self.tx_type() {
+            TransactionType::Legacy => return payload,
+            TransactionType::Eip2930 => 0x01,
+            TransactionType::Eip1559 => 0x02,
+        };
+        let mut typed = Vec::with_capacity(payload.len() + 1);
+        typed.push(tx_type);
+        typed.extend(payload);
+        typed
+    }
+}
+
+/// Extracts the signature from the transaction encoded by `RawTransaction::rlp_encode_tx`.
+pub fn decode_signature(signed_tx: &[u8]) -> Result<Signature, DecoderError> {
+    // Typed transactions start with the type, while the legacy ones start with the RLP list.
+    let (typed, payload) = match signed_tx.first() {
+        Some(0x01) | Some(0x02) => (true, &signed_tx[1..]),
+        _ => (false, signed_tx),
+    };
+    let rlp = Rlp::new(payload);
+    let items = rlp.item_count()?;
+    if items < 3 {
+        return Err(DecoderError::RlpIncorrectListLen);
+    }
+    let v: u64 = rlp.val_at(items - 3)?;
+    let y_parity = match (typed, v) {
+        (true, 0..=1) => v,
+        // EIP-155 signature, `{0,1} + chain_id * 2 + 35`.
+        (false, 35..=u64::MAX) => (v - 35) % 2,
+        (false, 27..=28) => v - 27,
+        _ => return Err(DecoderError::Custom("invalid signature v")),
+    };
+
+    let mut signature = [0u8; 65];
+    for &(index, offset) in &[(items - 2, 0), (items - 1, 32)] {
+        let value = rlp.at(index)?.data()?;
+        if value.len() > 32 {
+            return Err(DecoderError::RlpIsTooBig);
+        }
+        signature[offset + 32 - value.len()..offset + 32].copy_from_slice(value);
+    }
+    signature[64] = y_parity as u8;
+    Ok(Signature::from(signature))
+}
+
+fn append_signature_rs(stream: &mut RlpStream, r: &[u8], s: &[u8]) {
+    let r_start = find_first_nonzero(r);
+    stream.append(&&r[r_start..]);
+    let s_start = find_first_nonzero(s);
+    stream.append(&&s[s_start..]);
 }
 
-fn to_ecdsa(sig: Signature, chain_id: u8) -> EcdsaSig {
+/// Converts the signature into the EIP-155 form, where `v` is `{0,1} + chain_id * 2 + 35`.
+fn to_ecdsa(sig: Signature, chain_id: u64) -> EcdsaSig {
     EcdsaSig {
-        v: vec![sig.v() as u8 + chain_id * 2 + 35],
+        v: u64::from(sig.v()) + chain_id * 2 + 35,
         r: sig.r().to_vec(),
         s: sig.s().to_vec(),
     }
 }
 
 pub struct EcdsaSig {
-    v: Vec<u8>,
+    v: u64,
     r: Vec<u8>,
     s: Vec<u8>,
 }
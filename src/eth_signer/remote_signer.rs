@@ -0,0 +1,577 @@
+//! Signer delegating to a dedicated remote signing service: Web3Signer or Clef.
+//!
+//! Unlike `JsonRpcSigner`, the remote signers don't need a full Ethereum node,
+//! and the connection to them may be authenticated with a TLS client certificate.
+
+use crate::config::configs::eth_sender::{RemoteSignerConfig, RemoteSignerKind};
+use crate::eth_signer::error::{RpcSignerError, SignerError};
+use crate::eth_signer::json_rpc_signer::messages::JsonRpcRequest;
+use crate::eth_signer::raw_ethereum_tx::decode_signature;
+use crate::eth_signer::{EthereumSigner, RawTransaction};
+use crate::types::tx::{PackedEthSignature, TxEthSignature, TypedData};
+use crate::types::Address;
+use jsonrpc_core::types::response::Output;
+use parity_crypto::publickey::{public_to_address, recover, Public, Signature};
+use parity_crypto::Keccak256;
+use std::time::Duration;
+use zeroize::Zeroizing;
+
+/// API of the remote signer, together with the identifier of the signing key.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RemoteSignerApi {
+    /// Web3Signer, signing the keccak256 hash of arbitrary data with `POST /api/v1/eth1/sign/{public_key}`.
+    Web3Signer { public_key: String },
+    /// Clef, signing with the `account_signTransaction`, `account_signData` and `account_signTypedData` methods.
+    Clef { address: Address },
+}
+
+#[derive(Debug, Clone)]
+pub struct RemoteSigner {
+    url: String,
+    api: RemoteSignerApi,
+    address: Address,
+    client: reqwest::Client,
+}
+
+fn custom_error(message: String) -> SignerError {
+    SignerError::CustomError(message)
+}
+
+fn read_file(path: &str) -> Result<Vec<u8>, SignerError> {
+    std::fs::read(path).map_err(|err| custom_error(format!("unable to read {}: {}", path, err)))
+}
+
+impl RemoteSigner {
+    /// Creates a signer connecting to the remote signer over plain HTTP, or HTTPS trusted by the system.
+    pub fn new(url: impl Into<String>, api: RemoteSignerApi, request_timeout: Duration) -> Result<Self, SignerError> {
+        Self::with_client(url.into(), api, reqwest::Client::builder().timeout(request_timeout))
+    }
+
+    /// Creates a signer with the TLS settings and timeouts from the config, loading the client identity
+    /// and the CA certificate from their files. `operator_address` identifies the signing account in Clef.
+    pub fn from_config(config: &RemoteSignerConfig, operator_address: Address) -> Result<Self, SignerError> {
+        let api = match config.api {
+            RemoteSignerKind::Web3signer => RemoteSignerApi::Web3Signer {
+                public_key: config
+                    .public_key
+                    .clone()
+                    .ok_or_else(|| custom_error("public key is required by Web3Signer".to_string()))?,
+            },
+            RemoteSignerKind::Clef => RemoteSignerApi::Clef { address: operator_address },
+        };
+
+        let client_identity = match &config.client_identity_file {
+            Some(identity_file) => {
+                let password = match &config.client_identity_password_file {
+                    Some(password_file) => Zeroizing::new(
+                        String::from_utf8(read_file(password_file)?)
+                            .map_err(|_| custom_error(format!("{} is not a valid UTF-8 file", password_file)))?,
+                    ),
+                    None => Zeroizing::new(String::new()),
+                };
+                let identity = reqwest::Identity::from_pkcs12_der(&read_file(identity_file)?, password.trim_end())
+                    .map_err(|err| custom_error(format!("invalid client identity {}: {}", identity_file, err)))?;
+                Some(identity)
+            }
+            None => None,
+        };
+        let ca_cert = match &config.ca_cert_file {
+            Some(ca_cert_file) => Some(
+                reqwest::Certificate::from_pem(&read_file(ca_cert_file)?)
+                    .map_err(|err| custom_error(format!("invalid CA certificate {}: {}", ca_cert_file, err)))?,
+            ),
+            None => None,
+        };
+
+        Self::with_tls(config.url.clone(), api, config.request_timeout(), client_identity, ca_cert)
+    }
+
+    /// Creates a signer authenticating with the TLS client certificate, and trusting the signer
+    /// certificate issued by `ca_cert` in addition to the ones trusted by the system.
+    pub fn with_tls(
+        url: impl Into<String>,
+        api: RemoteSignerApi,
+        request_timeout: Duration,
+        client_identity: Option<reqwest::Identity>,
+        ca_cert: Option<reqwest::Certificate>,
+    ) -> Result<Self, SignerError> {
+        let mut builder = reqwest::Client::builder().timeout(request_timeout);
+        if let Some(identity) = client_identity {
+            builder = builder.identity(identity);
+        }
+        if let Some(certificate) = ca_cert {
+            builder = builder.add_root_certificate(certificate);
+        }
+        Self::with_client(url.into(), api, builder)
+    }
+
+    fn with_client(url: String, api: RemoteSignerApi, builder: reqwest::ClientBuilder) -> Result<Self, SignerError> {
+        let address = match &api {
+            RemoteSignerApi::Web3Signer { public_key } => address_from_public_key(public_key)?,
+            RemoteSignerApi::Clef { address } => *address,
+        };
+        let client = builder
+            .build()
+            .map_err(|err| custom_error(format!("unable to create the HTTP client: {}", err)))?;
+
+        Ok(Self {
+            url: url.trim_end_matches('/').to_string(),
+            api,
+            address,
+            client,
+        })
+    }
+
+    /// Asks Web3Signer to sign `keccak256(data)`.
+    async fn web3signer_sign(&self, public_key: &str, data: &[u8]) -> Result<Signature, SignerError> {
+        let url = format!("{}/api/v1/eth1/sign/{}", self.url, public_key);
+        let body = serde_json::json!({ "data": format!("0x{}", hex::encode(data)) });
+        let response = self
+            .client
+            .post(&url)
+            .json(&body)
+            .send()
+            .await
+            .map_err(|err| SignerError::SigningFailed(RpcSignerError::NetworkError(err.to_string()).to_string()))?;
+        if !response.status().is_success() {
+            return Err(SignerError::SigningFailed(format!("{} responded with {}", url, response.status())));
+        }
+        let signature = response
+            .text()
+            .await
+            .map_err(|err| SignerError::SigningFailed(RpcSignerError::MalformedResponse(err.to_string()).to_string()))?;
+
+        let signature = parse_signature(signature.trim())?;
+        // The signer is not trusted to sign with the right key, or the right data.
+        let signer = recover(&signature, &data.keccak256().into()).map_err(|err| SignerError::RecoverAddress(err.to_string()))?;
+        if public_to_address(&signer) != self.address {
+            return Err(SignerError::SigningFailed("Invalid signature from Web3Signer".to_string()));
+        }
+        Ok(signature)
+    }
+
+    /// Calls the Clef JSON-RPC method, returning its result.
+    async fn clef_call(&self, request: JsonRpcRequest) -> Result<serde_json::Value, SignerError> {
+        let response = self
+            .client
+            .post(&self.url)
+            .json(&request)
+            .send()
+            .await
+            .map_err(|err| SignerError::SigningFailed(RpcSignerError::NetworkError(err.to_string()).to_string()))?;
+        if !response.status().is_success() {
+            return Err(SignerError::SigningFailed(format!(
+                "{} responded with {}",
+                self.url,
+                response.status()
+            )));
+        }
+        let reply: Output = response
+            .json()
+            .await
+            .map_err(|err| SignerError::SigningFailed(RpcSignerError::MalformedResponse(err.to_string()).to_string()))?;
+        match reply {
+            Output::Success(success) => Ok(success.result),
+            Output::Failure(failure) => Err(SignerError::SigningFailed(RpcSignerError::RpcError(failure).to_string())),
+        }
+    }
+
+    async fn clef_signature(&self, request: JsonRpcRequest) -> Result<PackedEthSignature, SignerError> {
+        let ret = self.clef_call(request).await?;
+        serde_json::from_value(ret).map_err(|err| SignerError::SigningFailed(err.to_string()))
+    }
+}
+
+#[async_trait::async_trait]
+impl EthereumSigner for RemoteSigner {
+    /// Signs the message with the `\x19Ethereum Signed Message:\n` prefix, like `eth_sign`.
+    async fn sign_message(&self, message: &[u8]) -> Result<TxEthSignature, SignerError> {
+        let signature = match &self.api {
+            RemoteSignerApi::Web3Signer { public_key } => {
+                let prefix = format!("\x19Ethereum Signed Message:\n{}", message.len());
+                let data = [prefix.as_bytes(), message].concat();
+                let signature = self.web3signer_sign(public_key, &data).await?;
+                PackedEthSignature::deserialize_packed(&signature_bytes(&signature))
+                    .map_err(|err| SignerError::SigningFailed(err.to_string()))?
+            }
+            RemoteSignerApi::Clef { address } => {
+                let signature = self.clef_signature(JsonRpcRequest::account_sign_data(*address, message)).await?;
+                let signer = signature
+                    .signature_recover_signer(message)
+                    .map_err(|err| SignerError::RecoverAddress(err.to_string()))?;
+                if signer != self.address {
+                    return Err(SignerError::SigningFailed("Invalid signature from Clef".to_string()));
+                }
+                signature
+            }
+        };
+        Ok(TxEthSignature::EthereumSignature(signature))
+    }
+
+    /// Signs and returns the RLP-encoded transaction.
+    async fn sign_transaction(&self, raw_tx: RawTransaction) -> Result<Vec<u8>, SignerError> {
+        match &self.api {
+            RemoteSignerApi::Web3Signer { public_key } => {
+                let signature = self.web3signer_sign(public_key, &raw_tx.signing_payload()).await?;
+                Ok(raw_tx.rlp_encode_tx(signature))
+            }
+            RemoteSignerApi::Clef { address } => {
+                let ret = self
+                    .clef_call(JsonRpcRequest::account_sign_transaction(*address, raw_tx.clone()))
+                    .await?;
+                let raw = ret
+                    .get("raw")
+                    .and_then(|value| value.as_str())
+                    .ok_or_else(|| SignerError::SigningFailed("Clef response contains no raw transaction".to_string()))?;
+                let signed_tx =
+                    hex::decode(raw.strip_prefix("0x").unwrap_or(raw)).map_err(|err| SignerError::DecodeRawTxFailed(err.to_string()))?;
+
+                // The signer is not trusted to sign with the right key, or the right transaction.
+                let signature = decode_signature(&signed_tx).map_err(|err| SignerError::DecodeRawTxFailed(err.to_string()))?;
+                let signer = recover(&signature, &raw_tx.hash().into()).map_err(|err| SignerError::RecoverAddress(err.to_string()))?;
+                // Any field changed by the signer (e.g. the chain id of a legacy transaction) makes the encoding differ.
+                if raw_tx.rlp_encode_tx(signature) != signed_tx {
+                    return Err(SignerError::SigningFailed("Clef signed a different transaction".to_string()));
+                }
+                if public_to_address(&signer) != self.address {
+                    return Err(SignerError::SigningFailed("Invalid signature from Clef".to_string()));
+                }
+                Ok(signed_tx)
+            }
+        }
+    }
+
+    async fn sign_typed_data(&self, typed_data: &TypedData) -> Result<TxEthSignature, SignerError> {
+        let signature = match &self.api {
+            RemoteSignerApi::Web3Signer { public_key } => {
+                let payload = typed_data
+                    .signing_payload()
+                    .map_err(|err| SignerError::SigningFailed(err.to_string()))?;
+                let signature = self.web3signer_sign(public_key, &payload).await?;
+                PackedEthSignature::deserialize_packed(&signature_bytes(&signature))
+                    .map_err(|err| SignerError::SigningFailed(err.to_string()))?
+            }
+            RemoteSignerApi::Clef { address } => {
+                let signature = self
+                    .clef_signature(JsonRpcRequest::account_sign_typed_data(*address, typed_data))
+                    .await?;
+                let signer = signature
+                    .typed_data_recover_signer(typed_data)
+                    .map_err(|err| SignerError::RecoverAddress(err.to_string()))?;
+                if signer != self.address {
+                    return Err(SignerError::SigningFailed("Invalid signature from Clef".to_string()));
+                }
+                signature
+            }
+        };
+        Ok(TxEthSignature::EthereumSignature(signature))
+    }
+
+    async fn get_address(&self) -> Result<Address, SignerError> {
+        Ok(self.address)
+    }
+}
+
+/// Parses the hex-encoded `r || s || v` signature, where `v` is either 0/1 or 27/28.
+fn parse_signature(signature: &str) -> Result<Signature, SignerError> {
+    let bytes = hex::decode(signature.strip_prefix("0x").unwrap_or(signature))
+        .map_err(|err| SignerError::SigningFailed(format!("signature is not a hex string: {}", err)))?;
+    if bytes.len() != 65 {
+        return Err(SignerError::SigningFailed("signature must be 65 bytes long".to_string()));
+    }
+    let mut signature = [0u8; 65];
+    signature.copy_from_slice(&bytes);
+    if signature[64] >= 27 {
+        signature[64] -= 27;
+    }
+    Ok(Signature::from(signature))
+}
+
+fn signature_bytes(signature: &Signature) -> [u8; 65] {
+    let mut bytes = [0u8; 65];
+    bytes.copy_from_slice(&signature[..]);
+    bytes
+}
+
+/// Derives the address from the uncompressed public key, with or without the `04` prefix.
+fn address_from_public_key(public_key: &str) -> Result<Address, SignerError> {
+    let bytes = hex::decode(public_key.strip_prefix("0x").unwrap_or(public_key))
+        .map_err(|err| custom_error(format!("public key is not a hex string: {}", err)))?;
+    let bytes = match bytes.len() {
+        64 => &bytes[..],
+        65 if bytes[0] == 0x04 => &bytes[1..],
+        _ => return Err(custom_error("public key must be an uncompressed secp256k1 key".to_string())),
+    };
+    Ok(public_to_address(&Public::from_slice(bytes)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eth_signer::PrivateKeySigner;
+    use crate::types::{H160, H256, U256};
+    use actix_web::{post, web, App, HttpResponse, HttpServer, Responder};
+    use futures::future::{AbortHandle, Abortable};
+    use jsonrpc_core::{Failure, Id, Success, Version};
+    use parity_crypto::publickey::{sign, Generator, KeyPair, Random};
+    use serde_json::json;
+
+    /// Public key making the stand-in Web3Signer answer after the client timeout.
+    const SLOW_KEY: &str = "slow";
+    /// Chain id the stand-in Clef signs the legacy transactions for, like the `--chainid` of Clef.
+    const CLEF_CHAIN_ID: u64 = 421_613;
+    /// Value of the transaction making the stand-in Clef sign a different value.
+    const TAMPERED_VALUE: u64 = 666;
+
+    #[derive(Clone)]
+    struct State {
+        key_pair: KeyPair,
+    }
+
+    #[post("/api/v1/eth1/sign/{public_key}")]
+    async fn web3signer_sign(public_key: web::Path<String>, req: web::Json<serde_json::Value>, state: web::Data<State>) -> impl Responder {
+        if public_key.as_str() == SLOW_KEY {
+            tokio::time::delay_for(Duration::from_secs(5)).await;
+        }
+        let data = req["data"].as_str().unwrap();
+        let hash = hex::decode(&data[2..]).unwrap().keccak256();
+        let signature = sign(state.key_pair.secret(), &hash.into()).unwrap();
+        // Web3Signer returns `v` as 27/28.
+        let mut bytes = signature_bytes(&signature);
+        bytes[64] += 27;
+        HttpResponse::Ok().body(format!("0x{}", hex::encode(&bytes[..])))
+    }
+
+    #[post("/")]
+    async fn clef(req: web::Json<JsonRpcRequest>, state: web::Data<State>) -> impl Responder {
+        let secret = state.key_pair.secret();
+        let result = match req.method.as_str() {
+            "account_signData" => {
+                assert_eq!(req.params[0], "text/plain");
+                let data = req.params[2].as_str().unwrap();
+                json!(PackedEthSignature::sign(secret, &hex::decode(&data[2..]).unwrap()).unwrap())
+            }
+            "account_signTypedData" => {
+                let typed_data: TypedData = serde_json::from_value(req.params[1].clone()).unwrap();
+                json!(PackedEthSignature::sign_typed_data(secret, &typed_data).unwrap())
+            }
+            "account_signTransaction" => {
+                let tx = clef_transaction(&req.params[0]);
+                let signer = PrivateKeySigner::new(H256::from_slice(secret.as_bytes()));
+                let raw = signer.sign_transaction(tx).await.unwrap();
+                json!({ "raw": format!("0x{}", hex::encode(raw)), "tx": req.params[0] })
+            }
+            _ => {
+                return HttpResponse::Ok().json(Output::Failure(Failure {
+                    jsonrpc: Some(Version::V2),
+                    error: jsonrpc_core::Error::method_not_found(),
+                    id: Id::Num(1),
+                }))
+            }
+        };
+        HttpResponse::Ok().json(Output::Success(Success {
+            jsonrpc: Some(Version::V2),
+            result,
+            id: Id::Num(1),
+        }))
+    }
+
+    /// Transaction from the `account_signTransaction` request, as Clef signs it.
+    fn clef_transaction(tx: &serde_json::Value) -> RawTransaction {
+        let field = |name: &str| -> Option<U256> { tx.get(name).map(|value| serde_json::from_value(value.clone()).unwrap()) };
+        let data = tx["data"].as_str().unwrap();
+        let mut value = field("value").unwrap();
+        if value == U256::from(TAMPERED_VALUE) {
+            value += U256::one();
+        }
+        RawTransaction {
+            chain_id: field("chainId").map_or(CLEF_CHAIN_ID, |chain_id| chain_id.as_u64()),
+            nonce: field("nonce").unwrap(),
+            to: tx.get("to").map(|to| serde_json::from_value(to.clone()).unwrap()),
+            value,
+            gas_price: field("gasPrice").unwrap_or_default(),
+            gas: field("gas").unwrap(),
+            data: hex::decode(&data[2..]).unwrap(),
+            max_fee_per_gas: field("maxFeePerGas"),
+            max_priority_fee_per_gas: field("maxPriorityFeePerGas"),
+            access_list: tx.get("accessList").map(|list| serde_json::from_value(list.clone()).unwrap()),
+        }
+    }
+
+    fn run_server(state: State) -> (String, AbortHandle) {
+        let mut url = None;
+        let mut server = None;
+        for i in 9000..9999 {
+            let new_url = format!("127.0.0.1:{}", i);
+            // Try to bind to some port, hope that 999 variants will be enough
+            let tmp_state = state.clone();
+            if let Ok(ser) =
+                HttpServer::new(move || App::new().data(tmp_state.clone()).service(web3signer_sign).service(clef)).bind(new_url.clone())
+            {
+                server = Some(ser);
+                url = Some(new_url);
+                break;
+            }
+        }
+
+        let server = server.expect("Could not bind to port from 9000 to 9999");
+        let (abort_handle, abort_registration) = AbortHandle::new_pair();
+        let future = Abortable::new(server.run(), abort_registration);
+        tokio::spawn(future);
+        let address = format!("http://{}/", &url.unwrap());
+        (address, abort_handle)
+    }
+
+    fn transaction() -> RawTransaction {
+        RawTransaction {
+            chain_id: 421_613,
+            nonce: U256::from(1),
+            to: Some(H160::repeat_byte(0x35)),
+            value: U256::from(10),
+            gas_price: U256::from(1),
+            gas: U256::from(21_000),
+            data: vec![1, 2, 3],
+            max_fee_per_gas: None,
+            max_priority_fee_per_gas: None,
+            access_list: None,
+        }
+    }
+
+    fn typed_data() -> TypedData {
+        serde_json::from_value(json!({
+            "types": {
+                "EIP712Domain": [{ "name": "name", "type": "string" }],
+                "Message": [{ "name": "amount", "type": "uint256" }],
+            },
+            "primaryType": "Message",
+            "domain": { "name": "Fluidex" },
+            "message": { "amount": "1000" },
+        }))
+        .unwrap()
+    }
+
+    #[actix_rt::test]
+    async fn test_web3signer() {
+        let key_pair = Random.generate();
+        let (url, abort_handle) = run_server(State {
+            key_pair: key_pair.clone(),
+        });
+        let public_key = hex::encode(key_pair.public());
+        let signer = RemoteSigner::new(url.clone(), RemoteSignerApi::Web3Signer { public_key }, Duration::from_secs(5)).unwrap();
+        assert_eq!(signer.get_address().await.unwrap(), key_pair.address());
+
+        // Signatures are deterministic, so they must match the locally created ones.
+        let local_signer = PrivateKeySigner::new(H256::from_slice(key_pair.secret().as_bytes()));
+        assert_eq!(
+            signer.sign_transaction(transaction()).await.unwrap(),
+            local_signer.sign_transaction(transaction()).await.unwrap()
+        );
+        assert_eq!(
+            signer.sign_message(b"message").await.unwrap(),
+            local_signer.sign_message(b"message").await.unwrap()
+        );
+        assert_eq!(
+            signer.sign_typed_data(&typed_data()).await.unwrap(),
+            local_signer.sign_typed_data(&typed_data()).await.unwrap()
+        );
+
+        // The signer is not trusted to use the right key.
+        let other_key = hex::encode(Random.generate().public());
+        let signer = RemoteSigner::new(
+            url.clone(),
+            RemoteSignerApi::Web3Signer { public_key: other_key },
+            Duration::from_secs(5),
+        )
+        .unwrap();
+        assert!(signer.sign_message(b"message").await.is_err());
+
+        abort_handle.abort();
+    }
+
+    #[actix_rt::test]
+    async fn test_web3signer_timeout() {
+        let (url, abort_handle) = run_server(State {
+            key_pair: Random.generate(),
+        });
+        let signer = RemoteSigner {
+            url: url.trim_end_matches('/').to_string(),
+            api: RemoteSignerApi::Web3Signer {
+                public_key: SLOW_KEY.to_string(),
+            },
+            address: Address::zero(),
+            client: reqwest::Client::builder().timeout(Duration::from_millis(200)).build().unwrap(),
+        };
+        let error = signer.sign_message(b"message").await.unwrap_err();
+        assert!(matches!(error, SignerError::SigningFailed(_)), "{:?}", error);
+        abort_handle.abort();
+    }
+
+    #[actix_rt::test]
+    async fn test_clef() {
+        let key_pair = Random.generate();
+        let (url, abort_handle) = run_server(State {
+            key_pair: key_pair.clone(),
+        });
+        let signer = RemoteSigner::new(
+            url.clone(),
+            RemoteSignerApi::Clef {
+                address: key_pair.address(),
+            },
+            Duration::from_secs(5),
+        )
+        .unwrap();
+
+        // Signatures are deterministic, so they must match the locally created ones.
+        let local_signer = PrivateKeySigner::new(H256::from_slice(key_pair.secret().as_bytes()));
+        assert_eq!(
+            signer.sign_transaction(transaction()).await.unwrap(),
+            local_signer.sign_transaction(transaction()).await.unwrap()
+        );
+        let eip1559_tx = RawTransaction {
+            max_fee_per_gas: Some(U256::from(3)),
+            max_priority_fee_per_gas: Some(U256::from(2)),
+            access_list: Some(Vec::new()),
+            ..transaction()
+        };
+        assert_eq!(
+            signer.sign_transaction(eip1559_tx.clone()).await.unwrap(),
+            local_signer.sign_transaction(eip1559_tx).await.unwrap()
+        );
+
+        // The signer is not trusted to sign the right transaction, or to use the right key.
+        let tampered_tx = RawTransaction {
+            value: U256::from(TAMPERED_VALUE),
+            ..transaction()
+        };
+        assert!(signer.sign_transaction(tampered_tx).await.is_err());
+        let other_chain_tx = RawTransaction {
+            chain_id: CLEF_CHAIN_ID + 1,
+            ..transaction()
+        };
+        assert!(signer.sign_transaction(other_chain_tx).await.is_err());
+        let other_signer = RemoteSigner::new(
+            url,
+            RemoteSignerApi::Clef {
+                address: Random.generate().address(),
+            },
+            Duration::from_secs(5),
+        )
+        .unwrap();
+        assert!(other_signer.sign_transaction(transaction()).await.is_err());
+
+        match signer.sign_message(b"message").await.unwrap() {
+            TxEthSignature::EthereumSignature(signature) => {
+                assert_eq!(signature.signature_recover_signer(b"message").unwrap(), key_pair.address())
+            }
+            TxEthSignature::EIP1271Signature(_) => panic!("Wrong signature type"),
+        }
+        match signer.sign_typed_data(&typed_data()).await.unwrap() {
+            TxEthSignature::EthereumSignature(signature) => {
+                assert_eq!(signature.typed_data_recover_signer(&typed_data()).unwrap(), key_pair.address())
+            }
+            TxEthSignature::EIP1271Signature(_) => panic!("Wrong signature type"),
+        }
+
+        abort_handle.abort();
+    }
+}
@@ -0,0 +1,118 @@
+use super::{client::EthClient, eth_state::ETHState, ops_index::OpsIndex, token_registry::TokenRegistry, EthWatch, WatcherMode};
+use crate::config::configs::eth_watch::{DEFAULT_IMMEDIATE_REPOLL_THRESHOLD, DEFAULT_MAX_BLOCKS_PER_BATCH};
+use crate::config::ETHWatchConfig;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Interval between the scheduled polls of the Ethereum node used when none is provided.
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_millis(300);
+
+/// Builder for the `EthWatch`, intended for embedding the watcher into other services.
+///
+/// ```ignore
+/// let watcher = EthWatch::builder(client)
+///     .confirmations_for_eth_event(5)
+///     .poll_interval(Duration::from_secs(1))
+///     .build();
+/// tokio::spawn(watcher.run(eth_req_receiver));
+/// ```
+pub struct EthWatchBuilder<W: EthClient> {
+    client: W,
+    number_of_confirmations_for_event: u64,
+    poll_interval: Duration,
+    immediate_repoll_threshold: u64,
+    max_blocks_per_batch: u64,
+    state_file: Option<PathBuf>,
+    token_registry_file: Option<PathBuf>,
+}
+
+impl<W: EthClient> EthWatchBuilder<W> {
+    pub fn new(client: W) -> Self {
+        Self {
+            client,
+            number_of_confirmations_for_event: 0,
+            poll_interval: DEFAULT_POLL_INTERVAL,
+            immediate_repoll_threshold: DEFAULT_IMMEDIATE_REPOLL_THRESHOLD,
+            max_blocks_per_batch: DEFAULT_MAX_BLOCKS_PER_BATCH,
+            state_file: None,
+            token_registry_file: None,
+        }
+    }
+
+    /// Creates a builder with all the options taken from the configuration.
+    pub fn from_config(client: W, config: &ETHWatchConfig) -> Self {
+        let mut builder = Self::new(client)
+            .confirmations_for_eth_event(config.confirmations_for_eth_event)
+            .poll_interval(config.poll_interval())
+            .immediate_repoll_threshold(config.immediate_repoll_threshold)
+            .max_blocks_per_batch(config.max_blocks_per_batch);
+
+        if let Some(state_file) = &config.state_file {
+            builder = builder.state_file(state_file);
+        }
+        if let Some(token_registry_file) = &config.token_registry_file {
+            builder = builder.token_registry_file(token_registry_file);
+        }
+        builder
+    }
+
+    /// Amount of confirmations required for the event to be accepted.
+    pub fn confirmations_for_eth_event(mut self, number_of_confirmations_for_event: u64) -> Self {
+        self.number_of_confirmations_for_event = number_of_confirmations_for_event;
+        self
+    }
+
+    /// Interval between the end of one scheduled poll and the start of the next one.
+    pub fn poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// If a poll finds more new blocks than this threshold, the next poll is performed immediately.
+    pub fn immediate_repoll_threshold(mut self, immediate_repoll_threshold: u64) -> Self {
+        self.immediate_repoll_threshold = immediate_repoll_threshold;
+        self
+    }
+
+    /// Maximum amount of blocks processed by a single poll. If the watcher is further behind the
+    /// chain head, it catches up in batches of this size, answering requests in between.
+    pub fn max_blocks_per_batch(mut self, max_blocks_per_batch: u64) -> Self {
+        // Zero-sized batches would never make any progress.
+        self.max_blocks_per_batch = max_blocks_per_batch.max(1);
+        self
+    }
+
+    /// Enables persisting the watcher state to the provided file on shutdown.
+    /// Upon the next start, the state is restored from this file instead of
+    /// rescanning the Ethereum node.
+    pub fn state_file(mut self, state_file: impl Into<PathBuf>) -> Self {
+        self.state_file = Some(state_file.into());
+        self
+    }
+
+    /// Enables persisting the registry of the tokens added to the network to the provided file,
+    /// whenever a new token is registered. Unlike the state, the registry is restored regardless
+    /// of how long the watcher was down.
+    pub fn token_registry_file(mut self, token_registry_file: impl Into<PathBuf>) -> Self {
+        self.token_registry_file = Some(token_registry_file.into());
+        self
+    }
+
+    pub fn build(self) -> EthWatch<W> {
+        EthWatch {
+            client: self.client,
+            eth_state: ETHState::default(),
+            number_of_confirmations_for_event: self.number_of_confirmations_for_event,
+            mode: WatcherMode::Working,
+            ops_index: OpsIndex::default(),
+            state_file: self.state_file,
+            token_registry: TokenRegistry::default(),
+            token_registry_file: self.token_registry_file,
+            poll_interval: self.poll_interval,
+            immediate_repoll_threshold: self.immediate_repoll_threshold,
+            max_blocks_per_batch: self.max_blocks_per_batch,
+            chain_head: 0,
+            confirmations_gap: 0,
+        }
+    }
+}
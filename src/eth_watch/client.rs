@@ -1,11 +1,14 @@
 use crate::contracts::fluidex_contract;
 use crate::eth_client::ethereum_gateway::EthereumGateway;
-use crate::types::{AddTokenOp, PriorityOp, RegUserOp, H160};
+use crate::eth_client::{TokenInfo, TokenInquirer};
+use crate::eth_signer::ReadOnly;
+use crate::types::{AddTokenOp, Address, PriorityOp, RegUserOp, H160};
 use ethabi::Hash;
 use std::convert::TryFrom;
 use std::fmt::Debug;
+use std::sync::{Arc, RwLock};
 // use std::time::Instant;
-use web3::types::{BlockNumber, FilterBuilder, Log};
+use web3::types::{BlockNumber, Filter, FilterBuilder, Log};
 
 struct ContractTopics {
     new_token: Hash,
@@ -32,59 +35,118 @@ impl ContractTopics {
     }
 }
 
+/// Events of all the watched kinds emitted in a range of blocks.
+#[derive(Debug, Default)]
+pub struct ContractEvents {
+    pub priority_ops: Vec<PriorityOp>,
+    pub addtoken_ops: Vec<AddTokenOp>,
+    pub registeruser_ops: Vec<RegUserOp>,
+}
+
 #[async_trait::async_trait]
 pub trait EthClient {
     async fn get_new_token_events(&self, from: BlockNumber, to: BlockNumber) -> anyhow::Result<Vec<AddTokenOp>>;
     async fn get_register_user_events(&self, from: BlockNumber, to: BlockNumber) -> anyhow::Result<Vec<RegUserOp>>;
     async fn get_priority_op_events(&self, from: BlockNumber, to: BlockNumber) -> anyhow::Result<Vec<PriorityOp>>;
     async fn block_number(&self) -> anyhow::Result<u64>;
+    /// Queries the info of the token contracts, in the same order. The tokens fail to be queried independently.
+    async fn get_tokens_info(&self, tokens: &[Address]) -> Vec<anyhow::Result<TokenInfo>>;
+
+    /// Fetches the events of all the watched kinds. The clients able to do it in a single
+    /// request are expected to override it.
+    async fn get_all_events(&self, from: BlockNumber, to: BlockNumber) -> anyhow::Result<ContractEvents> {
+        Ok(ContractEvents {
+            priority_ops: self.get_priority_op_events(from, to).await?,
+            addtoken_ops: self.get_new_token_events(from, to).await?,
+            registeruser_ops: self.get_register_user_events(from, to).await?,
+        })
+    }
 }
 
-pub struct EthHttpClient {
-    client: EthereumGateway,
+/// Client fetching the Fluidex contract events. It only needs to read from the Ethereum
+/// nodes, so by default it works with a read-only gateway.
+pub struct EthHttpClient<S = ReadOnly> {
+    client: GatewayHandle<S>,
     topics: ContractTopics,
     fluidex_contract_addr: H160,
 }
 
-impl EthHttpClient {
-    pub fn new(client: EthereumGateway, fluidex_contract_addr: H160) -> Self {
+/// Shared handle to the gateway used by the `EthHttpClient`, allowing to replace
+/// the gateway (e.g. with a different set of endpoints) while the watcher is running.
+#[derive(Debug)]
+pub struct GatewayHandle<S = ReadOnly>(Arc<RwLock<EthereumGateway<S>>>);
+
+// Derived implementation would require `S: Clone`, while only the `Arc` is cloned.
+impl<S> Clone for GatewayHandle<S> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<S: Clone> GatewayHandle<S> {
+    /// Replaces the gateway. Requests which are already in progress complete with the old one.
+    pub fn replace(&self, gateway: EthereumGateway<S>) {
+        *self.0.write().unwrap() = gateway;
+    }
+
+    /// Returns the current gateway. The lock is released right away, so it's never held across `await`.
+    fn get(&self) -> EthereumGateway<S> {
+        self.0.read().unwrap().clone()
+    }
+}
+
+impl<S: Clone + Send + Sync> EthHttpClient<S> {
+    pub fn new(client: EthereumGateway<S>, fluidex_contract_addr: H160) -> Self {
         let topics = ContractTopics::new(&fluidex_contract());
         Self {
-            client,
+            client: GatewayHandle(Arc::new(RwLock::new(client))),
             topics,
             fluidex_contract_addr,
         }
     }
 
+    /// Returns the handle allowing to replace the gateway of the running client.
+    pub fn gateway_handle(&self) -> GatewayHandle<S> {
+        self.client.clone()
+    }
+
+    fn filter(&self, from: BlockNumber, to: BlockNumber, topics: Vec<Hash>) -> Filter {
+        FilterBuilder::default()
+            .address(vec![self.fluidex_contract_addr])
+            .from_block(from)
+            .to_block(to)
+            .topics(Some(topics), None, None, None)
+            .build()
+    }
+
     async fn get_events<T>(&self, from: BlockNumber, to: BlockNumber, topics: Vec<Hash>) -> anyhow::Result<Vec<T>>
     where
         T: TryFrom<Log>,
         T::Error: Debug,
     {
-        let filter = FilterBuilder::default()
-            .address(vec![self.fluidex_contract_addr])
-            .from_block(from)
-            .to_block(to)
-            .topics(Some(topics), None, None, None)
-            .build();
-
-        self.client
-            .logs(filter)
-            .await?
-            .into_iter()
-            .filter_map(|event| match T::try_from(event) {
-                Ok(ev) => Some(Ok(ev)),
-                Err(e) => {
-                    log::error!("{:?}", e);
-                    None
-                }
-            })
-            .collect()
+        let logs = self.client.get().logs(self.filter(from, to, topics)).await?;
+        Ok(parse_events(logs))
     }
 }
 
+fn parse_events<T>(logs: Vec<Log>) -> Vec<T>
+where
+    T: TryFrom<Log>,
+    T::Error: Debug,
+{
+    logs.into_iter()
+        .filter_map(|event| match T::try_from(event) {
+            Ok(ev) => Some(ev),
+            Err(e) => {
+                log::error!("{:?}", e);
+                None
+            }
+        })
+        .collect()
+}
+
 #[async_trait::async_trait]
-impl EthClient for EthHttpClient {
+impl<S: Clone + Send + Sync> EthClient for EthHttpClient<S> {
     async fn get_new_token_events(&self, from: BlockNumber, to: BlockNumber) -> anyhow::Result<Vec<AddTokenOp>> {
         // let start = Instant::now();
 
@@ -110,6 +172,26 @@ impl EthClient for EthHttpClient {
     }
 
     async fn block_number(&self) -> anyhow::Result<u64> {
-        Ok(self.client.block_number().await?.as_u64())
+        Ok(self.client.get().block_number().await?.as_u64())
+    }
+
+    async fn get_tokens_info(&self, tokens: &[Address]) -> Vec<anyhow::Result<TokenInfo>> {
+        TokenInquirer::new(self.client.get()).get_tokens_info(tokens).await
+    }
+
+    async fn get_all_events(&self, from: BlockNumber, to: BlockNumber) -> anyhow::Result<ContractEvents> {
+        let filters = vec![
+            self.filter(from, to, vec![self.topics.new_priority_request]),
+            self.filter(from, to, vec![self.topics.new_token]),
+            self.filter(from, to, vec![self.topics.new_user]),
+        ];
+        // The queries are sent in a single batch if the gateway is configured to.
+        let mut logs = self.client.get().logs_batch(filters).await?.into_iter();
+        let mut next_logs = || logs.next().expect("logs are returned for each filter");
+        Ok(ContractEvents {
+            priority_ops: parse_events(next_logs()),
+            addtoken_ops: parse_events(next_logs()),
+            registeruser_ops: parse_events(next_logs()),
+        })
     }
 }
@@ -1,6 +1,10 @@
 use super::received_ops::ReceivedPriorityOp;
-use crate::types::{PriorityOp, SerialId};
-use std::collections::HashMap;
+use crate::types::{AccountId, AddTokenOp, PriorityOp, RegUserOp, SerialId, TokenId};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
 
 /// Gathered state of the Ethereum network.
 /// Contains information about the known token types and incoming
@@ -10,7 +14,7 @@ use std::collections::HashMap;
 /// observed state of the contract on Ethereum, it should never be
 /// "partially updated". The state is either updated completely, or not
 /// updated at all.
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct ETHState {
     /// The last block of the Ethereum network known to the Ethereum watcher.
     last_ethereum_block: u64,
@@ -25,6 +29,12 @@ pub struct ETHState {
     /// Queue of priority operations that passed the confirmation
     /// threshold and are waiting to be executed.
     priority_queue: HashMap<u64, ReceivedPriorityOp>,
+    /// Confirmed `NewToken` operations, kept to restore the lookup index.
+    #[serde(default)]
+    addtoken_ops: BTreeMap<TokenId, AddTokenOp>,
+    /// Confirmed `RegisterUser` operations, kept to restore the lookup index.
+    #[serde(default)]
+    registeruser_ops: BTreeMap<AccountId, RegUserOp>,
 }
 
 impl ETHState {
@@ -32,11 +42,15 @@ impl ETHState {
         last_ethereum_block: u64,
         unconfirmed_queue: Vec<PriorityOp>,
         priority_queue: HashMap<SerialId, ReceivedPriorityOp>,
+        addtoken_ops: BTreeMap<TokenId, AddTokenOp>,
+        registeruser_ops: BTreeMap<AccountId, RegUserOp>,
     ) -> Self {
         Self {
             last_ethereum_block,
             unconfirmed_queue,
             priority_queue,
+            addtoken_ops,
+            registeruser_ops,
         }
     }
 
@@ -52,4 +66,34 @@ impl ETHState {
     pub fn unconfirmed_queue(&self) -> &[PriorityOp] {
         &self.unconfirmed_queue
     }
+
+    pub fn addtoken_ops(&self) -> &BTreeMap<TokenId, AddTokenOp> {
+        &self.addtoken_ops
+    }
+
+    pub fn registeruser_ops(&self) -> &BTreeMap<AccountId, RegUserOp> {
+        &self.registeruser_ops
+    }
+
+    /// Loads the state persisted by `ETHState::save`.
+    /// Returns `None` if nothing was persisted yet.
+    pub fn load(path: &Path) -> anyhow::Result<Option<Self>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let contents = fs::read_to_string(path).with_context(|| format!("Unable to read ETH state from {}", path.display()))?;
+        let state = serde_json::from_str(&contents).with_context(|| format!("Malformed ETH state in {}", path.display()))?;
+        Ok(Some(state))
+    }
+
+    /// Persists the state to the provided path.
+    ///
+    /// The state is written to a temporary file first and then renamed,
+    /// so that an interrupted write never leaves a truncated state behind.
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, serde_json::to_vec(self)?).with_context(|| format!("Unable to write ETH state to {}", tmp_path.display()))?;
+        fs::rename(&tmp_path, path).with_context(|| format!("Unable to move ETH state to {}", path.display()))?;
+        Ok(())
+    }
 }
@@ -2,32 +2,47 @@
 //! such as PriorityQueue events or NewToken events.
 //! New events are accepted to the fluidex network once they have the sufficient amount of confirmations.
 //!
-//! Poll interval is configured using the `ETH_POLL_INTERVAL` constant.
-//! Number of confirmations is configured using the `CONFIRMATIONS_FOR_ETH_EVENT` environment variable.
+//! The watcher polls the node on its own schedule, configured through `EthWatchBuilder`,
+//! and additionally polls it on every `EthWatchRequest::PollETHNode` request.
+//!
+//! The info of the tokens added by the confirmed `NewToken` events is queried from the token
+//! contracts, and is available through `EthWatchRequest::GetTokenInfo` and `EthWatchRequest::GetTokens`.
 
 use self::{
     client::EthClient,
     eth_state::ETHState,
+    ops_index::OpsIndex,
     received_ops::{sift_outdated_ops, ReceivedPriorityOp},
+    token_registry::TokenRegistry,
 };
+use crate::config::ETHWatchConfig;
 use crate::params;
-use crate::types::{AddTokenOp, PriorityOp, RegUserOp};
+use crate::types::{AddTokenOp, Address, L2Pubkey, PriorityOp, RegUserOp, SerialId, TokenId, H256};
 use futures::{
     channel::{mpsc, oneshot},
     StreamExt,
 };
+use serde::{Deserialize, Serialize};
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
+    path::PathBuf,
     time::{Duration, Instant},
 };
 use tokio::time;
 use web3::types::BlockNumber;
 
-pub use client::EthHttpClient;
+pub use crate::config::configs::eth_watch::{DEFAULT_IMMEDIATE_REPOLL_THRESHOLD, DEFAULT_MAX_BLOCKS_PER_BATCH};
+pub use builder::{EthWatchBuilder, DEFAULT_POLL_INTERVAL};
+pub use client::{ContractEvents, EthHttpClient, GatewayHandle};
+pub use ops_index::{OpLookupEntry, OpState, WatchedOp};
+pub use token_registry::RegisteredToken;
 
+mod builder;
 mod client;
 mod eth_state;
+mod ops_index;
 mod received_ops;
+mod token_registry;
 
 /// As `infura` may limit the requests, upon error we need to wait for a while
 /// before repeating the request.
@@ -56,6 +71,58 @@ pub enum EthWatchRequest {
         max_chunks: usize,
         resp: oneshot::Sender<Vec<PriorityOp>>,
     },
+    GetOpsByL1Address {
+        address: Address,
+        resp: oneshot::Sender<Vec<OpLookupEntry>>,
+    },
+    GetOpsByL2Pubkey {
+        pubkey: L2Pubkey,
+        resp: oneshot::Sender<Vec<OpLookupEntry>>,
+    },
+    GetOpsByEthHash {
+        eth_hash: H256,
+        resp: oneshot::Sender<Vec<OpLookupEntry>>,
+    },
+    GetOpsByToken {
+        token: TokenId,
+        resp: oneshot::Sender<Vec<OpLookupEntry>>,
+    },
+    GetSyncStatus {
+        resp: oneshot::Sender<SyncStatus>,
+    },
+    /// Returns `None` if the token is not confirmed yet, or its info is not queried yet.
+    GetTokenInfo {
+        token: TokenId,
+        resp: oneshot::Sender<Option<RegisteredToken>>,
+    },
+    /// Returns all the registered tokens, in the order of their ids.
+    GetTokens {
+        resp: oneshot::Sender<Vec<RegisteredToken>>,
+    },
+    /// Applies the changed settings to the running watcher.
+    Reconfigure {
+        config: ETHWatchConfig,
+    },
+}
+
+/// Progress of the watcher in following the Ethereum chain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SyncStatus {
+    /// The last block processed by the watcher.
+    pub last_processed_block: u64,
+    /// The latest block of the Ethereum chain known to the watcher.
+    pub chain_head: u64,
+}
+
+impl SyncStatus {
+    pub fn blocks_behind(&self) -> u64 {
+        self.chain_head.saturating_sub(self.last_processed_block)
+    }
+
+    /// Whether the watcher is processing the blocks in batches to catch up with the chain head.
+    pub fn is_catching_up(&self) -> bool {
+        self.blocks_behind() > 0
+    }
 }
 
 pub struct EthWatch<W: EthClient> {
@@ -64,8 +131,28 @@ pub struct EthWatch<W: EthClient> {
     /// All ethereum events are accepted after sufficient confirmations to eliminate risk of block reorg.
     number_of_confirmations_for_event: u64,
     mode: WatcherMode,
+    /// Secondary indexes over the known operations, used for lookup requests.
+    ops_index: OpsIndex,
+    /// File the state is persisted to on shutdown and restored from on start.
+    state_file: Option<PathBuf>,
+    /// Info of the tokens added by the confirmed `NewToken` events.
+    token_registry: TokenRegistry,
+    /// File the token registry is persisted to whenever it changes.
+    token_registry_file: Option<PathBuf>,
+    /// Interval between the end of one scheduled poll and the start of the next one.
+    poll_interval: Duration,
+    /// If a poll finds more new blocks than this threshold, the next poll is performed immediately.
+    immediate_repoll_threshold: u64,
+    /// Maximum amount of blocks processed by a single poll.
+    max_blocks_per_batch: u64,
+    /// The latest block of the Ethereum chain known to the watcher.
+    chain_head: u64,
+    /// Amount of blocks the confirmation threshold was lowered by since the last poll.
+    /// These blocks have to be rescanned, otherwise their events would never be accepted.
+    confirmations_gap: u64,
 }
 
+#[derive(Default)]
 struct UnconfirmedOps {
     priority_ops: Vec<PriorityOp>,
     addtoken_ops: Vec<AddTokenOp>,
@@ -80,12 +167,13 @@ struct AcceptedOps {
 
 impl<W: EthClient> EthWatch<W> {
     pub fn new(client: W, number_of_confirmations_for_event: u64) -> Self {
-        Self {
-            client,
-            eth_state: ETHState::default(),
-            mode: WatcherMode::Working,
-            number_of_confirmations_for_event,
-        }
+        EthWatchBuilder::new(client)
+            .confirmations_for_eth_event(number_of_confirmations_for_event)
+            .build()
+    }
+
+    pub fn builder(client: W) -> EthWatchBuilder<W> {
+        EthWatchBuilder::new(client)
     }
 
     /// Atomically replaces the stored Ethereum state.
@@ -93,6 +181,32 @@ impl<W: EthClient> EthWatch<W> {
         self.eth_state = new_state;
     }
 
+    /// Updates the lookup indexes with the freshly received operations.
+    fn update_ops_index(&mut self, unconfirmed_ops: &UnconfirmedOps, accepted_ops: &AcceptedOps, expired_ops: &[SerialId]) {
+        for op in accepted_ops.priority_ops.values() {
+            self.ops_index.insert(WatchedOp::Priority(op.as_ref().clone()), OpState::Confirmed);
+        }
+        for op in &accepted_ops.registeruser_ops {
+            self.ops_index.insert(WatchedOp::RegisterUser(op.clone()), OpState::Confirmed);
+        }
+        for op in &accepted_ops.addtoken_ops {
+            self.ops_index.insert(WatchedOp::AddToken(op.clone()), OpState::Confirmed);
+        }
+
+        let unconfirmed = unconfirmed_ops
+            .priority_ops
+            .iter()
+            .cloned()
+            .map(WatchedOp::Priority)
+            .chain(unconfirmed_ops.registeruser_ops.iter().cloned().map(WatchedOp::RegisterUser))
+            .chain(unconfirmed_ops.addtoken_ops.iter().cloned().map(WatchedOp::AddToken));
+        self.ops_index.update_unconfirmed(unconfirmed);
+
+        for serial_id in expired_ops {
+            self.ops_index.mark_expired(*serial_id);
+        }
+    }
+
     async fn get_unconfirmed_ops(&mut self, current_ethereum_block: u64) -> anyhow::Result<UnconfirmedOps> {
         // We want to scan the interval of blocks from the latest one up to the oldest one which may
         // have unconfirmed priority ops.
@@ -103,13 +217,11 @@ impl<W: EthClient> EthWatch<W> {
         let block_from = BlockNumber::Number(block_from_number.into());
         let block_to = BlockNumber::Latest;
 
-        let unconfirmed_priority_ops = self.client.get_priority_op_events(block_from, block_to).await?;
-        let unconfirmed_addtoken_ops = self.client.get_new_token_events(block_from, block_to).await?;
-        let unconfirmed_registeruser_ops = self.client.get_register_user_events(block_from, block_to).await?;
+        let events = self.client.get_all_events(block_from, block_to).await?;
         Ok(UnconfirmedOps {
-            priority_ops: unconfirmed_priority_ops,
-            addtoken_ops: unconfirmed_addtoken_ops,
-            registeruser_ops: unconfirmed_registeruser_ops,
+            priority_ops: events.priority_ops,
+            addtoken_ops: events.addtoken_ops,
+            registeruser_ops: events.registeruser_ops,
         })
     }
 
@@ -118,80 +230,244 @@ impl<W: EthClient> EthWatch<W> {
         previous_block_with_accepted_events: u64,
         new_block_with_accepted_events: u64,
     ) -> anyhow::Result<AcceptedOps> {
-        let accepted_priority_queue = self
+        let events = self
             .client
-            .get_priority_op_events(
+            .get_all_events(
                 BlockNumber::Number(previous_block_with_accepted_events.into()),
                 BlockNumber::Number(new_block_with_accepted_events.into()),
             )
-            .await?
+            .await?;
+        let accepted_priority_queue = events
+            .priority_ops
             .into_iter()
             .map(|priority_op| (priority_op.serial_id, priority_op.into()))
             .collect();
-        let accepted_addtoken_queue = self
-            .client
-            .get_new_token_events(
-                BlockNumber::Number(previous_block_with_accepted_events.into()),
-                BlockNumber::Number(new_block_with_accepted_events.into()),
-            )
-            .await?;
-        let accepted_registeruser_queue = self
-            .client
-            .get_register_user_events(
-                BlockNumber::Number(previous_block_with_accepted_events.into()),
-                BlockNumber::Number(new_block_with_accepted_events.into()),
-            )
-            .await?;
 
         Ok(AcceptedOps {
             priority_ops: accepted_priority_queue,
-            addtoken_ops: accepted_addtoken_queue,
-            registeruser_ops: accepted_registeruser_queue,
+            addtoken_ops: events.addtoken_ops,
+            registeruser_ops: events.registeruser_ops,
         })
     }
 
-    async fn process_new_blocks(&mut self, last_ethereum_block: u64) -> anyhow::Result<()> {
-        debug_assert!(self.eth_state.last_ethereum_block() < last_ethereum_block);
+    /// Processes the blocks up to the provided chain head.
+    ///
+    /// If the watcher is far behind the chain head (e.g. after a downtime or the backoff mode), only
+    /// the next batch of at most `max_blocks_per_batch` blocks is processed, so that a single poll
+    /// doesn't issue a gigantic range query and doesn't block the request handling for long.
+    async fn process_new_blocks(&mut self, chain_head: u64) -> anyhow::Result<()> {
+        let last_processed_block = self.eth_state.last_ethereum_block();
+        debug_assert!(last_processed_block < chain_head);
+
+        let batch_end = chain_head.min(last_processed_block.saturating_add(self.max_blocks_per_batch));
+        let catching_up = batch_end < chain_head;
 
         // We have to process every block between the current and previous known values.
         // This is crucial since `eth_watch` may enter the backoff mode in which it will skip many blocks.
         // Note that we don't have to add `number_of_confirmations_for_event` here, because the check function takes
         // care of it on its own. Here we calculate "how many blocks should we watch", and the offsets with respect
         // to the `number_of_confirmations_for_event` are calculated by `update_eth_state`.
-        let block_difference = last_ethereum_block.saturating_sub(self.eth_state.last_ethereum_block());
+        let block_difference = batch_end - last_processed_block + self.confirmations_gap;
 
-        let (unconfirmed_queue, accepted_queue) = self.update_eth_state(last_ethereum_block, block_difference).await?;
+        // Unconfirmed operations only make sense near the chain head, so they are not requested while catching up.
+        let (unconfirmed_queue, accepted_queue) = self.update_eth_state(batch_end, block_difference, !catching_up).await?;
 
         // Extend the existing priority operations with the new ones.
         let mut priority_queue = sift_outdated_ops(self.eth_state.priority_queue());
+        let expired_ops: Vec<SerialId> = self
+            .eth_state
+            .priority_queue()
+            .keys()
+            .filter(|serial_id| !priority_queue.contains_key(*serial_id))
+            .copied()
+            .collect();
+        self.update_ops_index(&unconfirmed_queue, &accepted_queue, &expired_ops);
+        self.token_registry.add_pending(&accepted_queue.addtoken_ops);
         for (serial_id, op) in accepted_queue.priority_ops {
             priority_queue.insert(serial_id, op);
         }
+        // Rescanned blocks may contain the already known operations, which are replaced.
+        let mut addtoken_ops = self.eth_state.addtoken_ops().clone();
+        addtoken_ops.extend(accepted_queue.addtoken_ops.into_iter().map(|op| (op.data.token_id, op)));
+        let mut registeruser_ops = self.eth_state.registeruser_ops().clone();
+        registeruser_ops.extend(accepted_queue.registeruser_ops.into_iter().map(|op| (op.data.user_id, op)));
 
-        let new_state = ETHState::new(last_ethereum_block, unconfirmed_queue.priority_ops, priority_queue);
+        let new_state = ETHState::new(
+            batch_end,
+            unconfirmed_queue.priority_ops,
+            priority_queue,
+            addtoken_ops,
+            registeruser_ops,
+        );
         self.set_new_state(new_state);
+        self.confirmations_gap = 0;
+
+        if catching_up {
+            log::info!(
+                "Catching up with the Ethereum chain: processed blocks up to {} of {} ({} blocks behind)",
+                batch_end,
+                chain_head,
+                chain_head - batch_end
+            );
+            // Commit the progress, so that it isn't lost if the watcher is interrupted while catching up.
+            self.persist_state();
+        }
         Ok(())
     }
 
-    async fn restore_state_from_eth(&mut self, last_ethereum_block: u64) -> anyhow::Result<()> {
-        let (unconfirmed_queue, accepted_queue) = self.update_eth_state(last_ethereum_block, params::PRIORITY_EXPIRATION).await?;
+    /// Initializes the state to rescan the last `PRIORITY_EXPIRATION` blocks, since older priority
+    /// operations are not interesting anymore. The blocks are then processed by the regular polls
+    /// in the catch-up mode.
+    fn restore_state_from_eth(&mut self, last_ethereum_block: u64) {
+        let start_block = last_ethereum_block.saturating_sub(params::PRIORITY_EXPIRATION);
 
-        let new_state = ETHState::new(last_ethereum_block, unconfirmed_queue.priority_ops, accepted_queue.priority_ops);
+        self.ops_index = OpsIndex::default();
+        self.set_new_state(ETHState::new(
+            start_block,
+            Vec::new(),
+            HashMap::new(),
+            BTreeMap::new(),
+            BTreeMap::new(),
+        ));
+        log::info!(
+            "ETH state will be restored from the blocks {}..{}",
+            start_block,
+            last_ethereum_block
+        );
+    }
 
-        self.set_new_state(new_state);
-        log::debug!("ETH state: {:#?}", self.eth_state);
-        Ok(())
+    /// Restores the state persisted on the previous shutdown.
+    /// Returns `false` if there is no usable persisted state, so it has to be restored from Ethereum.
+    fn restore_persisted_state(&mut self, last_ethereum_block: u64) -> bool {
+        let state_file = match &self.state_file {
+            Some(state_file) => state_file,
+            None => return false,
+        };
+
+        let state = match ETHState::load(state_file) {
+            Ok(Some(state)) => state,
+            Ok(None) => return false,
+            Err(error) => {
+                log::warn!("Unable to load the persisted ETH state: {:#}", error);
+                return false;
+            }
+        };
+
+        // Priority operations older than `PRIORITY_EXPIRATION` blocks are not interesting anymore,
+        // so there is no reason to catch up from a state that old. A state from the future means
+        // that the node is not synced yet or points to a different chain.
+        let persisted_block = state.last_ethereum_block();
+        if persisted_block > last_ethereum_block || last_ethereum_block - persisted_block > params::PRIORITY_EXPIRATION {
+            log::warn!(
+                "Persisted ETH state at block {} is not usable with the node at block {}, ignoring it",
+                persisted_block,
+                last_ethereum_block
+            );
+            return false;
+        }
+
+        self.ops_index = OpsIndex::default();
+        for op in state.priority_queue().values() {
+            self.ops_index.insert(WatchedOp::Priority(op.as_ref().clone()), OpState::Confirmed);
+        }
+        for op in state.addtoken_ops().values() {
+            self.ops_index.insert(WatchedOp::AddToken(op.clone()), OpState::Confirmed);
+        }
+        for op in state.registeruser_ops().values() {
+            self.ops_index.insert(WatchedOp::RegisterUser(op.clone()), OpState::Confirmed);
+        }
+        self.ops_index
+            .update_unconfirmed(state.unconfirmed_queue().iter().cloned().map(WatchedOp::Priority));
+
+        log::info!("Restored ETH state at block {} from {}", persisted_block, state_file.display());
+        self.set_new_state(state);
+        true
+    }
+
+    /// Restores the token registry persisted before. Unlike the state, it's never outdated,
+    /// as the tokens are not removed from the network.
+    fn restore_token_registry(&mut self) {
+        let token_registry_file = match &self.token_registry_file {
+            Some(token_registry_file) => token_registry_file,
+            None => return,
+        };
+        match TokenRegistry::load(token_registry_file) {
+            Ok(Some(registry)) => {
+                log::info!("Restored token registry from {}", token_registry_file.display());
+                self.token_registry = registry;
+            }
+            Ok(None) => {}
+            Err(error) => log::warn!("Unable to load the persisted token registry: {:#}", error),
+        }
+    }
+
+    fn persist_token_registry(&self) {
+        if let Some(token_registry_file) = &self.token_registry_file {
+            if let Err(error) = self.token_registry.save(token_registry_file) {
+                log::error!("Failed to persist token registry: {:#}", error);
+            }
+        }
+    }
+
+    /// Queries the info of the tokens confirmed since the last poll. The tokens which failed
+    /// to be queried stay pending, and are queried again after the next block.
+    async fn update_token_registry(&mut self) {
+        let pending = self.token_registry.pending();
+        if pending.is_empty() {
+            return;
+        }
+
+        let addresses: Vec<Address> = pending.iter().map(|(_, address)| *address).collect();
+        let infos = self.client.get_tokens_info(&addresses).await;
+        for ((token, address), info) in pending.into_iter().zip(infos) {
+            match info {
+                Ok(info) => {
+                    log::info!(
+                        "Token {} ({:#x}) registered as {:?} with {} decimals",
+                        token,
+                        address,
+                        info.symbol,
+                        info.decimals
+                    );
+                    for (field, reason) in &info.defaulted_fields {
+                        log::warn!("Token {} ({:#x}) {:?} is defaulted: {}", token, address, field, reason);
+                    }
+                    self.token_registry.insert(token, address, info);
+                }
+                Err(error) => log::warn!("Unable to query the info of the token {} ({:#x}): {:#}", token, address, error),
+            }
+        }
+        self.persist_token_registry();
+    }
+
+    /// Persists the current state, if the state file is configured.
+    fn persist_state(&self) {
+        if let Some(state_file) = &self.state_file {
+            match self.eth_state.save(state_file) {
+                Ok(()) => log::info!(
+                    "ETH state at block {} persisted to {}",
+                    self.eth_state.last_ethereum_block(),
+                    state_file.display()
+                ),
+                Err(error) => log::error!("Failed to persist ETH state: {:#}", error),
+            }
+        }
     }
 
     async fn update_eth_state(
         &mut self,
         current_ethereum_block: u64,
         unprocessed_blocks_amount: u64,
+        with_unconfirmed_ops: bool,
     ) -> anyhow::Result<(UnconfirmedOps, AcceptedOps)> {
         let new_block_with_accepted_events = current_ethereum_block.saturating_sub(self.number_of_confirmations_for_event);
         let previous_block_with_accepted_events = new_block_with_accepted_events.saturating_sub(unprocessed_blocks_amount);
 
-        let unconfirmed_ops = self.get_unconfirmed_ops(current_ethereum_block).await?;
+        let unconfirmed_ops = if with_unconfirmed_ops {
+            self.get_unconfirmed_ops(current_ethereum_block).await?
+        } else {
+            UnconfirmedOps::default()
+        };
         let accepted_ops = self
             .get_accepted_ops(previous_block_with_accepted_events, new_block_with_accepted_events)
             .await?;
@@ -217,16 +493,81 @@ impl<W: EthClient> EthWatch<W> {
         result
     }
 
-    async fn poll_eth_node(&mut self) -> anyhow::Result<()> {
+    /// Applies the changed settings. The state and token registry files are only picked up on restart.
+    fn reconfigure(&mut self, config: &ETHWatchConfig) {
+        let confirmations = config.confirmations_for_eth_event;
+        if confirmations < self.number_of_confirmations_for_event {
+            self.confirmations_gap += self.number_of_confirmations_for_event - confirmations;
+        }
+        self.number_of_confirmations_for_event = confirmations;
+        self.poll_interval = config.poll_interval();
+        self.immediate_repoll_threshold = config.immediate_repoll_threshold;
+        self.max_blocks_per_batch = config.max_blocks_per_batch.max(1);
+
+        if config.state_file.as_ref().map(PathBuf::from) != self.state_file {
+            log::warn!("Changed eth_watch.state_file will only be used after restart");
+        }
+        if config.token_registry_file.as_ref().map(PathBuf::from) != self.token_registry_file {
+            log::warn!("Changed eth_watch.token_registry_file will only be used after restart");
+        }
+        log::info!(
+            "ETH watcher reconfigured: {} confirmations, {}ms poll interval",
+            self.number_of_confirmations_for_event,
+            self.poll_interval.as_millis()
+        );
+    }
+
+    fn sync_status(&self) -> SyncStatus {
+        SyncStatus {
+            last_processed_block: self.eth_state.last_ethereum_block(),
+            chain_head: self.chain_head,
+        }
+    }
+
+    /// Processes the blocks appeared since the last poll.
+    /// Returns the amount of processed blocks.
+    async fn poll_eth_node(&mut self) -> anyhow::Result<u64> {
         // let start = Instant::now();
+        // The block number isn't batched with the event queries, since their ranges depend on it.
         let last_block_number = self.client.block_number().await?;
+        let previous_block_number = self.eth_state.last_ethereum_block();
+        self.chain_head = last_block_number;
 
-        if last_block_number > self.eth_state.last_ethereum_block() {
+        if last_block_number > previous_block_number {
             self.process_new_blocks(last_block_number).await?;
+            self.update_token_registry().await;
         }
 
         // metrics::histogram!("eth_watcher.poll_eth_node", start.elapsed());
-        Ok(())
+        Ok(self.eth_state.last_ethereum_block().saturating_sub(previous_block_number))
+    }
+
+    /// Polls the Ethereum node, unless polling is currently disabled.
+    /// Returns `true` if the watcher is behind the chain head and should poll again right away.
+    async fn handle_poll(&mut self) -> bool {
+        if !self.polling_allowed() {
+            // Polling is currently disabled, skip it.
+            return false;
+        }
+
+        match self.poll_eth_node().await {
+            // Processing a lot of blocks takes a while, and new blocks are likely to appear meanwhile.
+            Ok(new_blocks) => self.sync_status().is_catching_up() || new_blocks > self.immediate_repoll_threshold,
+            Err(error) => {
+                if self.is_backoff_requested(&error) {
+                    log::warn!(
+                        "Rate limit was reached, as reported by Ethereum node. \
+                        Entering the backoff mode"
+                    );
+                    self.enter_backoff_mode();
+                } else {
+                    // Some unexpected kind of error, we won't shutdown the node because of it,
+                    // but rather expect node administrators to handle the situation.
+                    log::error!("Failed to process new blocks {}", error);
+                }
+                false
+            }
+        }
     }
 
     // TODO try to move it to eth client
@@ -282,36 +623,34 @@ impl<W: EthClient> EthWatch<W> {
         };
 
         // Code above is prepared for the possible rate limiting by `infura`, and will wait until we
-        // can interact with the node again. We're not expecting the rate limiting to be applied
-        // immediately after that, thus any error on this stage is considered critical and
-        // irrecoverable.
-        self.restore_state_from_eth(block)
-            .await
-            .expect("Unable to restore ETHWatcher state");
-
-        while let Some(request) = eth_watch_req.next().await {
+        // can interact with the node again. The blocks since the restored state are then processed
+        // by the polls below in the catch-up mode.
+        self.chain_head = block;
+        self.restore_token_registry();
+        if !self.restore_persisted_state(block) {
+            self.restore_state_from_eth(block);
+        }
+
+        // Polls are scheduled relatively to the end of the previous one, so a slow poll
+        // never causes the subsequent ones to pile up.
+        let mut next_poll = time::Instant::now();
+        loop {
+            let request = tokio::select! {
+                request = eth_watch_req.next() => match request {
+                    Some(request) => request,
+                    None => break,
+                },
+                _ = time::delay_until(next_poll) => EthWatchRequest::PollETHNode,
+            };
+
             match request {
                 EthWatchRequest::PollETHNode => {
-                    if !self.polling_allowed() {
-                        // Polling is currently disabled, skip it.
-                        continue;
-                    }
-
-                    let poll_result = self.poll_eth_node().await;
-
-                    if let Err(error) = poll_result {
-                        if self.is_backoff_requested(&error) {
-                            log::warn!(
-                                "Rate limit was reached, as reported by Ethereum node. \
-                                Entering the backoff mode"
-                            );
-                            self.enter_backoff_mode();
-                        } else {
-                            // Some unexpected kind of error, we won't shutdown the node because of it,
-                            // but rather expect node administrators to handle the situation.
-                            log::error!("Failed to process new blocks {}", error);
-                        }
-                    }
+                    let repoll_immediately = self.handle_poll().await;
+                    next_poll = if repoll_immediately {
+                        time::Instant::now()
+                    } else {
+                        time::Instant::now() + self.poll_interval
+                    };
                 }
                 EthWatchRequest::GetPriorityQueueOps {
                     op_start_id,
@@ -320,7 +659,499 @@ impl<W: EthClient> EthWatch<W> {
                 } => {
                     resp.send(self.get_priority_requests(op_start_id, max_chunks)).unwrap_or_default();
                 }
+                EthWatchRequest::GetOpsByL1Address { address, resp } => {
+                    resp.send(self.ops_index.by_l1_address(&address)).unwrap_or_default();
+                }
+                EthWatchRequest::GetOpsByL2Pubkey { pubkey, resp } => {
+                    resp.send(self.ops_index.by_l2_pubkey(&pubkey)).unwrap_or_default();
+                }
+                EthWatchRequest::GetOpsByEthHash { eth_hash, resp } => {
+                    resp.send(self.ops_index.by_eth_hash(&eth_hash)).unwrap_or_default();
+                }
+                EthWatchRequest::GetOpsByToken { token, resp } => {
+                    resp.send(self.ops_index.by_token(&token)).unwrap_or_default();
+                }
+                EthWatchRequest::GetSyncStatus { resp } => {
+                    resp.send(self.sync_status()).unwrap_or_default();
+                }
+                EthWatchRequest::GetTokenInfo { token, resp } => {
+                    resp.send(self.token_registry.get(token).cloned()).unwrap_or_default();
+                }
+                EthWatchRequest::GetTokens { resp } => {
+                    resp.send(self.token_registry.tokens()).unwrap_or_default();
+                }
+                EthWatchRequest::Reconfigure { config } => {
+                    self.reconfigure(&config);
+                    // Don't wait for the rest of the previous, possibly much longer, interval.
+                    next_poll = next_poll.min(time::Instant::now() + self.poll_interval);
+                }
+            }
+        }
+
+        // All the request senders are dropped, which means that the watcher is being shut down.
+        log::info!("ETH watcher is shutting down");
+        self.persist_state();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eth_client::TokenInfo;
+    use crate::types::add_token_op::FluidexAddTokenOp;
+    use crate::utils::panic_notify::ThreadPanicNotify;
+    use futures::SinkExt;
+    use std::sync::{Arc, Mutex};
+
+    /// Chain with the `NewToken` events only, whose tokens' info is available once it's set.
+    #[derive(Clone, Default)]
+    struct FakeEthClient {
+        block_number: Arc<Mutex<u64>>,
+        addtoken_ops: Arc<Mutex<Vec<AddTokenOp>>>,
+        token_infos: Arc<Mutex<HashMap<Address, TokenInfo>>>,
+        /// Ranges of the event queries, `u64::MAX` standing for the latest block.
+        queried_blocks: Arc<Mutex<Vec<(u64, u64)>>>,
+        /// Querying the events of this block panics.
+        panic_at_block: Arc<Mutex<Option<u64>>>,
+        /// Amount of the block number requests, made on start and by every poll.
+        block_number_requests: Arc<Mutex<usize>>,
+    }
+
+    impl FakeEthClient {
+        /// Ranges of the queries for the accepted events, i.e. the ones not ending with the latest block.
+        fn accepted_ranges(&self) -> Vec<(u64, u64)> {
+            let queried_blocks = self.queried_blocks.lock().unwrap();
+            queried_blocks.iter().filter(|(_, to)| *to != u64::MAX).copied().collect()
+        }
+    }
+
+    fn block(number: BlockNumber) -> u64 {
+        match number {
+            BlockNumber::Number(number) => number.as_u64(),
+            _ => u64::MAX,
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl EthClient for FakeEthClient {
+        async fn get_new_token_events(&self, from: BlockNumber, to: BlockNumber) -> anyhow::Result<Vec<AddTokenOp>> {
+            let blocks = block(from)..=block(to);
+            self.queried_blocks.lock().unwrap().push((block(from), block(to)));
+            let panic_at_block = *self.panic_at_block.lock().unwrap();
+            if panic_at_block.map_or(false, |panic_at_block| blocks.contains(&panic_at_block)) {
+                panic!("events of the block {:?} are unavailable", panic_at_block);
+            }
+            let ops = self.addtoken_ops.lock().unwrap();
+            Ok(ops.iter().filter(|op| blocks.contains(&op.eth_block)).cloned().collect())
+        }
+
+        async fn get_register_user_events(&self, _from: BlockNumber, _to: BlockNumber) -> anyhow::Result<Vec<RegUserOp>> {
+            Ok(Vec::new())
+        }
+
+        async fn get_priority_op_events(&self, _from: BlockNumber, _to: BlockNumber) -> anyhow::Result<Vec<PriorityOp>> {
+            Ok(Vec::new())
+        }
+
+        async fn block_number(&self) -> anyhow::Result<u64> {
+            *self.block_number_requests.lock().unwrap() += 1;
+            Ok(*self.block_number.lock().unwrap())
+        }
+
+        async fn get_tokens_info(&self, tokens: &[Address]) -> Vec<anyhow::Result<TokenInfo>> {
+            let infos = self.token_infos.lock().unwrap();
+            tokens
+                .iter()
+                .map(|token| {
+                    infos
+                        .get(token)
+                        .cloned()
+                        .ok_or_else(|| anyhow::anyhow!("token {:#x} is unavailable", token))
+                })
+                .collect()
+        }
+    }
+
+    fn add_token_op(token_id: u16, token_address: Address, eth_block: u64) -> AddTokenOp {
+        AddTokenOp {
+            data: FluidexAddTokenOp {
+                token_address,
+                token_id: TokenId(token_id),
+            },
+            eth_hash: H256::zero(),
+            eth_block,
+        }
+    }
+
+    fn token_info(symbol: &str) -> TokenInfo {
+        TokenInfo {
+            symbol: symbol.into(),
+            name: String::new(),
+            decimals: 18,
+            total_supply: 1000.into(),
+            defaulted_fields: Default::default(),
+        }
+    }
+
+    async fn poll(requests: &mut mpsc::Sender<EthWatchRequest>) {
+        requests.send(EthWatchRequest::PollETHNode).await.unwrap();
+    }
+
+    async fn get_token(requests: &mut mpsc::Sender<EthWatchRequest>, token: u16) -> Option<RegisteredToken> {
+        let (resp, response) = oneshot::channel();
+        let token = TokenId(token);
+        requests.send(EthWatchRequest::GetTokenInfo { token, resp }).await.unwrap();
+        response.await.unwrap()
+    }
+
+    async fn get_token_ids(requests: &mut mpsc::Sender<EthWatchRequest>) -> Vec<TokenId> {
+        let (resp, response) = oneshot::channel();
+        requests.send(EthWatchRequest::GetTokens { resp }).await.unwrap();
+        response.await.unwrap().into_iter().map(|token| token.id).collect()
+    }
+
+    async fn sync_status(requests: &mut mpsc::Sender<EthWatchRequest>) -> SyncStatus {
+        let (resp, response) = oneshot::channel();
+        requests.send(EthWatchRequest::GetSyncStatus { resp }).await.unwrap();
+        response.await.unwrap()
+    }
+
+    /// Waits for the watcher polling on its own to process the blocks up to `block`.
+    async fn wait_for_block(requests: &mut mpsc::Sender<EthWatchRequest>, block: u64) {
+        for _ in 0..100 {
+            if sync_status(requests).await.last_processed_block == block {
+                return;
+            }
+            time::delay_for(Duration::from_millis(10)).await;
+        }
+        panic!("The block {} is not processed", block);
+    }
+
+    /// Waits for the condition to hold, e.g. for the watcher polling on its own to poll again.
+    async fn wait_until(condition: impl Fn() -> bool) {
+        for _ in 0..100 {
+            if condition() {
+                return;
             }
+            time::delay_for(Duration::from_millis(10)).await;
+        }
+        panic!("The condition doesn't hold");
+    }
+
+    fn temp_file(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    #[tokio::test]
+    async fn test_token_registry_updates() {
+        let client = FakeEthClient::default();
+        let (usdt, broken) = (Address::from_low_u64_be(10), Address::from_low_u64_be(20));
+        *client.block_number.lock().unwrap() = 10;
+        client
+            .addtoken_ops
+            .lock()
+            .unwrap()
+            .extend(vec![add_token_op(1, usdt, 5), add_token_op(2, broken, 6)]);
+        client.token_infos.lock().unwrap().insert(usdt, token_info("USDT"));
+
+        let watcher = EthWatch::builder(client.clone())
+            .confirmations_for_eth_event(0)
+            .poll_interval(Duration::from_secs(3600))
+            .build();
+        let (mut requests, receiver) = mpsc::channel(1);
+        let watcher = tokio::spawn(watcher.run(receiver));
+
+        // The token failed to be queried doesn't hold the other one back.
+        poll(&mut requests).await;
+        assert_eq!(get_token_ids(&mut requests).await, vec![TokenId(1)]);
+        assert_eq!(get_token(&mut requests, 1).await.unwrap().info, token_info("USDT"));
+        assert!(get_token(&mut requests, 2).await.is_none());
+
+        // The failed token is queried again once there is a new block.
+        client.token_infos.lock().unwrap().insert(broken, token_info("BRK"));
+        poll(&mut requests).await;
+        assert!(get_token(&mut requests, 2).await.is_none());
+        *client.block_number.lock().unwrap() = 11;
+        poll(&mut requests).await;
+        let token = get_token(&mut requests, 2).await.unwrap();
+        assert_eq!((token.address, token.info.symbol.as_str()), (broken, "BRK"));
+        assert_eq!(get_token_ids(&mut requests).await, vec![TokenId(1), TokenId(2)]);
+
+        drop(requests);
+        watcher.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_restart_after_panic() {
+        let state_file = temp_file("eth_watch_panic_test");
+        let client = FakeEthClient::default();
+        *client.block_number.lock().unwrap() = 100;
+        *client.panic_at_block.lock().unwrap() = Some(70);
+        let watcher = || {
+            EthWatch::builder(client.clone())
+                .max_blocks_per_batch(30)
+                .poll_interval(Duration::from_secs(3600))
+                .state_file(&state_file)
+                .build()
+        };
+
+        // The supervisor is notified once the watcher panics while catching up.
+        let (panic_sender, mut panic_receiver) = mpsc::channel(1);
+        let panic_notify = ThreadPanicNotify(panic_sender);
+        let (requests, receiver) = mpsc::channel(1);
+        let watcher_task = watcher();
+        let handle = tokio::spawn(async move {
+            let _panic_notify = panic_notify;
+            watcher_task.run(receiver).await;
+        });
+        assert_eq!(panic_receiver.next().await, Some(true));
+        assert!(handle.await.is_err());
+        drop(requests);
+
+        // The restarted watcher resumes from the progress persisted before the panic.
+        *client.panic_at_block.lock().unwrap() = None;
+        client.queried_blocks.lock().unwrap().clear();
+        let (mut requests, receiver) = mpsc::channel(1);
+        let handle = tokio::spawn(watcher().run(receiver));
+        wait_for_block(&mut requests, 100).await;
+        assert_eq!(client.accepted_ranges(), vec![(60, 90), (90, 100)]);
+
+        drop(requests);
+        handle.await.unwrap();
+        std::fs::remove_file(&state_file).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_state_is_persisted_on_shutdown() {
+        let state_file = temp_file("eth_watch_shutdown_test");
+        let client = FakeEthClient::default();
+        let usdt = Address::from_low_u64_be(10);
+        *client.block_number.lock().unwrap() = 10;
+        client.addtoken_ops.lock().unwrap().push(add_token_op(1, usdt, 5));
+        let watcher = || {
+            EthWatch::builder(client.clone())
+                .poll_interval(Duration::from_secs(3600))
+                .state_file(&state_file)
+                .build()
+        };
+
+        let (mut requests, receiver) = mpsc::channel(1);
+        let handle = tokio::spawn(watcher().run(receiver));
+        wait_for_block(&mut requests, 10).await;
+        // The state is only persisted once the watcher is stopped.
+        assert!(!state_file.exists());
+        drop(requests);
+        handle.await.unwrap();
+        assert!(state_file.exists());
+
+        // The restarted watcher only processes the blocks appeared since the shutdown.
+        *client.block_number.lock().unwrap() = 12;
+        client.queried_blocks.lock().unwrap().clear();
+        let (mut requests, receiver) = mpsc::channel(1);
+        let handle = tokio::spawn(watcher().run(receiver));
+        wait_for_block(&mut requests, 12).await;
+        assert_eq!(client.accepted_ranges(), vec![(10, 12)]);
+        let (resp, response) = oneshot::channel();
+        let token = TokenId(1);
+        requests.send(EthWatchRequest::GetOpsByToken { token, resp }).await.unwrap();
+        assert_eq!(response.await.unwrap().len(), 1);
+
+        drop(requests);
+        handle.await.unwrap();
+        std::fs::remove_file(&state_file).unwrap();
+    }
+
+    #[test]
+    fn test_builder() {
+        let watcher = EthWatch::builder(FakeEthClient::default()).max_blocks_per_batch(0).build();
+        assert_eq!(watcher.number_of_confirmations_for_event, 0);
+        assert_eq!(watcher.poll_interval, DEFAULT_POLL_INTERVAL);
+        assert_eq!(watcher.immediate_repoll_threshold, DEFAULT_IMMEDIATE_REPOLL_THRESHOLD);
+        // Zero-sized batches would never make any progress.
+        assert_eq!(watcher.max_blocks_per_batch, 1);
+        assert!(watcher.state_file.is_none());
+
+        let config = ETHWatchConfig {
+            confirmations_for_eth_event: 5,
+            eth_node_poll_interval: 1500,
+            immediate_repoll_threshold: 3,
+            max_blocks_per_batch: 50,
+            state_file: Some("eth_watch_state.json".into()),
+            token_registry_file: Some("eth_watch_tokens.json".into()),
+        };
+        let watcher = EthWatchBuilder::from_config(FakeEthClient::default(), &config).build();
+        assert_eq!(watcher.number_of_confirmations_for_event, 5);
+        assert_eq!(watcher.poll_interval, Duration::from_millis(1500));
+        assert_eq!(watcher.immediate_repoll_threshold, 3);
+        assert_eq!(watcher.max_blocks_per_batch, 50);
+        assert_eq!(watcher.state_file, Some(PathBuf::from("eth_watch_state.json")));
+        assert_eq!(watcher.token_registry_file, Some(PathBuf::from("eth_watch_tokens.json")));
+    }
+
+    #[tokio::test]
+    async fn test_scheduled_polls() {
+        let client = FakeEthClient::default();
+        *client.block_number.lock().unwrap() = 10;
+        let (mut requests, receiver) = mpsc::channel(1);
+        let watcher = EthWatch::builder(client.clone()).poll_interval(Duration::from_millis(20)).build();
+        let handle = tokio::spawn(watcher.run(receiver));
+
+        // The new blocks are processed without the poll requests.
+        wait_for_block(&mut requests, 10).await;
+        *client.block_number.lock().unwrap() = 11;
+        wait_for_block(&mut requests, 11).await;
+        drop(requests);
+        handle.await.unwrap();
+
+        // Until the poll interval passes, the blocks are only processed on request.
+        let (mut requests, receiver) = mpsc::channel(1);
+        let watcher = EthWatch::builder(client.clone()).poll_interval(Duration::from_secs(3600)).build();
+        let handle = tokio::spawn(watcher.run(receiver));
+        wait_for_block(&mut requests, 11).await;
+        *client.block_number.lock().unwrap() = 12;
+        time::delay_for(Duration::from_millis(50)).await;
+        assert_eq!(sync_status(&mut requests).await.last_processed_block, 11);
+        poll(&mut requests).await;
+        assert_eq!(sync_status(&mut requests).await.last_processed_block, 12);
+
+        drop(requests);
+        handle.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_immediate_repoll() {
+        let client = FakeEthClient::default();
+        *client.block_number.lock().unwrap() = 3;
+        let block_number_requests = || *client.block_number_requests.lock().unwrap();
+        let (mut requests, receiver) = mpsc::channel(1);
+        let watcher = EthWatch::builder(client.clone())
+            .poll_interval(Duration::from_secs(3600))
+            .immediate_repoll_threshold(5)
+            .build();
+        let handle = tokio::spawn(watcher.run(receiver));
+
+        // The block number is requested on start, and by the first poll.
+        wait_for_block(&mut requests, 3).await;
+        *client.block_number.lock().unwrap() = 5;
+        poll(&mut requests).await;
+        assert_eq!(sync_status(&mut requests).await.last_processed_block, 5);
+        time::delay_for(Duration::from_millis(50)).await;
+        assert_eq!(block_number_requests(), 3);
+
+        // New blocks are likely to appear while many of them are processed, so the node is polled again right away.
+        *client.block_number.lock().unwrap() = 15;
+        poll(&mut requests).await;
+        wait_until(|| block_number_requests() == 5).await;
+        assert_eq!(sync_status(&mut requests).await.last_processed_block, 15);
+        // The repoll found no new blocks, so the next poll waits for the interval.
+        time::delay_for(Duration::from_millis(50)).await;
+        assert_eq!(block_number_requests(), 5);
+
+        drop(requests);
+        handle.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_catch_up_in_batches() {
+        let client = FakeEthClient::default();
+        *client.block_number.lock().unwrap() = 100;
+        client
+            .addtoken_ops
+            .lock()
+            .unwrap()
+            .push(add_token_op(1, Address::from_low_u64_be(10), 45));
+        let mut watcher = EthWatch::builder(client.clone()).max_blocks_per_batch(30).build();
+        watcher.restore_state_from_eth(100);
+
+        // Every poll processes a single batch, and the next one is polled right away until the chain head is reached.
+        for &(last_processed_block, repoll) in &[(30, true), (60, true), (90, true), (100, false)] {
+            assert_eq!(watcher.handle_poll().await, repoll);
+            let status = watcher.sync_status();
+            assert_eq!(
+                status,
+                SyncStatus {
+                    last_processed_block,
+                    chain_head: 100
+                }
+            );
+            assert_eq!(status.is_catching_up(), repoll);
         }
+        assert_eq!(client.accepted_ranges(), vec![(0, 30), (30, 60), (60, 90), (90, 100)]);
+        // The unconfirmed operations are only requested near the chain head.
+        let latest_queries: Vec<_> = client
+            .queried_blocks
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, to)| *to == u64::MAX)
+            .copied()
+            .collect();
+        assert_eq!(latest_queries, vec![(101, u64::MAX)]);
+        assert!(watcher.eth_state.addtoken_ops().contains_key(&TokenId(1)));
+    }
+
+    #[tokio::test]
+    async fn test_sync_status() {
+        let client = FakeEthClient::default();
+        *client.block_number.lock().unwrap() = 100;
+        let (mut requests, receiver) = mpsc::channel(1);
+        let watcher = EthWatch::builder(client.clone())
+            .max_blocks_per_batch(30)
+            .poll_interval(Duration::from_secs(3600))
+            .build();
+        let handle = tokio::spawn(watcher.run(receiver));
+
+        // The watcher catches up on its own, and the chain head is updated by the polls only.
+        wait_for_block(&mut requests, 100).await;
+        *client.block_number.lock().unwrap() = 104;
+        let status = sync_status(&mut requests).await;
+        assert_eq!((status.chain_head, status.blocks_behind()), (100, 0));
+        poll(&mut requests).await;
+        let status = sync_status(&mut requests).await;
+        assert_eq!((status.last_processed_block, status.chain_head), (104, 104));
+        assert!(!status.is_catching_up());
+
+        drop(requests);
+        handle.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_lowered_confirmations() {
+        let client = FakeEthClient::default();
+        *client.block_number.lock().unwrap() = 100;
+        client
+            .addtoken_ops
+            .lock()
+            .unwrap()
+            .push(add_token_op(1, Address::from_low_u64_be(10), 95));
+        let mut watcher = EthWatch::builder(client.clone()).confirmations_for_eth_event(10).build();
+        watcher.restore_state_from_eth(100);
+        watcher.handle_poll().await;
+        assert_eq!(client.accepted_ranges(), vec![(0, 90)]);
+        assert!(watcher.eth_state.addtoken_ops().is_empty());
+
+        let config = ETHWatchConfig {
+            confirmations_for_eth_event: 2,
+            eth_node_poll_interval: 3_600_000,
+            immediate_repoll_threshold: DEFAULT_IMMEDIATE_REPOLL_THRESHOLD,
+            max_blocks_per_batch: DEFAULT_MAX_BLOCKS_PER_BATCH,
+            state_file: None,
+            token_registry_file: None,
+        };
+        watcher.reconfigure(&config);
+        assert_eq!(watcher.confirmations_gap, 8);
+
+        // The blocks which got enough confirmations because of the lowered threshold are scanned as well.
+        *client.block_number.lock().unwrap() = 101;
+        watcher.handle_poll().await;
+        assert_eq!(client.accepted_ranges()[1..], [(90, 99)]);
+        assert!(watcher.eth_state.addtoken_ops().contains_key(&TokenId(1)));
+        assert_eq!(watcher.confirmations_gap, 0);
+
+        *client.block_number.lock().unwrap() = 102;
+        watcher.handle_poll().await;
+        assert_eq!(client.accepted_ranges()[2..], [(99, 100)]);
     }
 }
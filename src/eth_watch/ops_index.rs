@@ -0,0 +1,268 @@
+use crate::types::{AccountId, AddTokenOp, Address, FluidexPriorityOp, L2Pubkey, PriorityOp, RegUserOp, SerialId, TokenId, H256};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+
+/// State of the operation as observed by the Ethereum watcher.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OpState {
+    /// Operation is included into an Ethereum block, but doesn't have enough confirmations yet.
+    Unconfirmed,
+    /// Operation has passed the confirmation threshold.
+    Confirmed,
+    /// Priority operation was removed from the queue as outdated.
+    Expired,
+}
+
+/// Any operation emitted by the Fluidex contract that the watcher keeps track of.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum WatchedOp {
+    Priority(PriorityOp),
+    RegisterUser(RegUserOp),
+    AddToken(AddTokenOp),
+}
+
+impl WatchedOp {
+    /// Hash of the Ethereum transaction which emitted the operation.
+    pub fn eth_hash(&self) -> H256 {
+        match self {
+            Self::Priority(op) => op.eth_hash,
+            Self::RegisterUser(op) => op.eth_hash,
+            Self::AddToken(op) => op.eth_hash,
+        }
+    }
+
+    /// Block in which the Ethereum transaction was included.
+    pub fn eth_block(&self) -> u64 {
+        match self {
+            Self::Priority(op) => op.eth_block,
+            Self::RegisterUser(op) => op.eth_block,
+            Self::AddToken(op) => op.eth_block,
+        }
+    }
+
+    fn key(&self) -> OpKey {
+        match self {
+            Self::Priority(op) => OpKey::Priority(op.serial_id),
+            Self::RegisterUser(op) => OpKey::RegisterUser(op.data.user_id),
+            Self::AddToken(op) => OpKey::AddToken(op.data.token_id),
+        }
+    }
+
+    fn l1_address(&self) -> Option<Address> {
+        match self {
+            Self::Priority(op) => match &op.data {
+                FluidexPriorityOp::Deposit(deposit) => Some(deposit.from),
+                FluidexPriorityOp::FullExit(full_exit) => Some(full_exit.eth_address),
+            },
+            Self::RegisterUser(op) => Some(op.data.l1_address),
+            Self::AddToken(_) => None,
+        }
+    }
+
+    fn l2_pubkey(&self) -> Option<L2Pubkey> {
+        match self {
+            Self::Priority(op) => match &op.data {
+                FluidexPriorityOp::Deposit(deposit) => Some(deposit.to),
+                FluidexPriorityOp::FullExit(_) => None,
+            },
+            Self::RegisterUser(op) => Some(op.data.l2_pubkey),
+            Self::AddToken(_) => None,
+        }
+    }
+
+    fn token(&self) -> Option<TokenId> {
+        match self {
+            Self::Priority(op) => match &op.data {
+                FluidexPriorityOp::Deposit(deposit) => Some(deposit.token),
+                FluidexPriorityOp::FullExit(full_exit) => Some(full_exit.token),
+            },
+            Self::RegisterUser(_) => None,
+            Self::AddToken(op) => Some(op.data.token_id),
+        }
+    }
+}
+
+/// Single lookup result: the operation together with its current state.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OpLookupEntry {
+    pub op: WatchedOp,
+    pub state: OpState,
+}
+
+/// Unique identifier of the operation within its kind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum OpKey {
+    Priority(SerialId),
+    RegisterUser(AccountId),
+    AddToken(TokenId),
+}
+
+/// Secondary indexes over all the operations known to the Ethereum watcher,
+/// used to answer "did operation X from account Y arrive?" kind of questions.
+#[derive(Debug, Default)]
+pub struct OpsIndex {
+    entries: HashMap<OpKey, OpLookupEntry>,
+    by_l1_address: HashMap<Address, HashSet<OpKey>>,
+    by_l2_pubkey: HashMap<L2Pubkey, HashSet<OpKey>>,
+    by_eth_hash: HashMap<H256, HashSet<OpKey>>,
+    by_token: HashMap<TokenId, HashSet<OpKey>>,
+}
+
+fn link<K: Eq + Hash>(index: &mut HashMap<K, HashSet<OpKey>>, value: Option<K>, key: OpKey) {
+    if let Some(value) = value {
+        index.entry(value).or_default().insert(key);
+    }
+}
+
+fn unlink<K: Eq + Hash>(index: &mut HashMap<K, HashSet<OpKey>>, value: Option<K>, key: OpKey) {
+    if let Some(value) = value {
+        if let Some(keys) = index.get_mut(&value) {
+            keys.remove(&key);
+            if keys.is_empty() {
+                index.remove(&value);
+            }
+        }
+    }
+}
+
+impl OpsIndex {
+    /// Inserts the operation into the index, replacing the previously known version of it.
+    pub fn insert(&mut self, op: WatchedOp, state: OpState) {
+        let key = op.key();
+        self.remove(key);
+
+        link(&mut self.by_l1_address, op.l1_address(), key);
+        link(&mut self.by_l2_pubkey, op.l2_pubkey(), key);
+        link(&mut self.by_eth_hash, Some(op.eth_hash()), key);
+        link(&mut self.by_token, op.token(), key);
+        self.entries.insert(key, OpLookupEntry { op, state });
+    }
+
+    /// Replaces the set of unconfirmed operations with the provided one.
+    ///
+    /// Unconfirmed operations may disappear because of block reverts, so the
+    /// previously known ones are dropped unless they got confirmed since.
+    pub fn update_unconfirmed(&mut self, ops: impl IntoIterator<Item = WatchedOp>) {
+        let stale: Vec<OpKey> = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| entry.state == OpState::Unconfirmed)
+            .map(|(key, _)| *key)
+            .collect();
+        for key in stale {
+            self.remove(key);
+        }
+
+        for op in ops {
+            if !self.entries.contains_key(&op.key()) {
+                self.insert(op, OpState::Unconfirmed);
+            }
+        }
+    }
+
+    /// Marks the priority operation as expired.
+    pub fn mark_expired(&mut self, serial_id: SerialId) {
+        if let Some(entry) = self.entries.get_mut(&OpKey::Priority(serial_id)) {
+            entry.state = OpState::Expired;
+        }
+    }
+
+    pub fn by_l1_address(&self, address: &Address) -> Vec<OpLookupEntry> {
+        self.collect(self.by_l1_address.get(address))
+    }
+
+    pub fn by_l2_pubkey(&self, pubkey: &L2Pubkey) -> Vec<OpLookupEntry> {
+        self.collect(self.by_l2_pubkey.get(pubkey))
+    }
+
+    pub fn by_eth_hash(&self, eth_hash: &H256) -> Vec<OpLookupEntry> {
+        self.collect(self.by_eth_hash.get(eth_hash))
+    }
+
+    pub fn by_token(&self, token: &TokenId) -> Vec<OpLookupEntry> {
+        self.collect(self.by_token.get(token))
+    }
+
+    fn remove(&mut self, key: OpKey) -> Option<OpLookupEntry> {
+        let entry = self.entries.remove(&key)?;
+
+        unlink(&mut self.by_l1_address, entry.op.l1_address(), key);
+        unlink(&mut self.by_l2_pubkey, entry.op.l2_pubkey(), key);
+        unlink(&mut self.by_eth_hash, Some(entry.op.eth_hash()), key);
+        unlink(&mut self.by_token, entry.op.token(), key);
+        Some(entry)
+    }
+
+    /// Returns the entries for the provided keys, ordered by the Ethereum block they were included in.
+    fn collect(&self, keys: Option<&HashSet<OpKey>>) -> Vec<OpLookupEntry> {
+        let mut result: Vec<OpLookupEntry> = keys
+            .into_iter()
+            .flatten()
+            .filter_map(|key| self.entries.get(key))
+            .cloned()
+            .collect();
+        result.sort_by_key(|entry| entry.op.eth_block());
+        result
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::types::{Deposit, FluidexPriorityOp};
+    use num::BigUint;
+
+    fn deposit(serial_id: SerialId, from: Address, token: u16, eth_block: u64) -> WatchedOp {
+        WatchedOp::Priority(PriorityOp {
+            serial_id,
+            data: FluidexPriorityOp::Deposit(Deposit {
+                from,
+                token: TokenId(token),
+                amount: BigUint::from(100u32),
+                to: L2Pubkey::from_low_u64_be(serial_id),
+            }),
+            deadline_block: 0,
+            eth_hash: H256::from_low_u64_be(serial_id),
+            eth_block,
+        })
+    }
+
+    #[test]
+    fn test_lookup_by_keys() {
+        let alice = Address::from_low_u64_be(1);
+        let bob = Address::from_low_u64_be(2);
+
+        let mut index = OpsIndex::default();
+        index.insert(deposit(1, alice, 0, 10), OpState::Confirmed);
+        index.insert(deposit(2, bob, 1, 11), OpState::Confirmed);
+        index.insert(deposit(3, alice, 1, 12), OpState::Confirmed);
+
+        let found: Vec<u64> = index.by_l1_address(&alice).iter().map(|entry| entry.op.eth_block()).collect();
+        assert_eq!(found, vec![10, 12]);
+        assert_eq!(index.by_token(&TokenId(1)).len(), 2);
+        assert_eq!(index.by_eth_hash(&H256::from_low_u64_be(2)).len(), 1);
+        assert_eq!(index.by_l2_pubkey(&L2Pubkey::from_low_u64_be(3)).len(), 1);
+        assert!(index.by_l1_address(&Address::from_low_u64_be(3)).is_empty());
+    }
+
+    #[test]
+    fn test_state_transitions() {
+        let alice = Address::from_low_u64_be(1);
+
+        let mut index = OpsIndex::default();
+        index.update_unconfirmed(vec![deposit(1, alice, 0, 10), deposit(2, alice, 0, 11)]);
+        assert!(index.by_l1_address(&alice).iter().all(|entry| entry.state == OpState::Unconfirmed));
+
+        // The first operation got confirmed, and the second one was reverted.
+        index.insert(deposit(1, alice, 0, 10), OpState::Confirmed);
+        index.update_unconfirmed(vec![]);
+        let found = index.by_l1_address(&alice);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].state, OpState::Confirmed);
+        assert!(index.by_eth_hash(&H256::from_low_u64_be(2)).is_empty());
+
+        index.mark_expired(1);
+        assert_eq!(index.by_token(&TokenId(0))[0].state, OpState::Expired);
+    }
+}
@@ -1,7 +1,8 @@
 use crate::types::PriorityOp;
+use serde::{Deserialize, Serialize};
 use std::{
     collections::HashMap,
-    time::{Duration, Instant},
+    time::{Duration, SystemTime},
 };
 
 pub const SECS_IN_HOUR: u64 = 3600;
@@ -19,15 +20,22 @@ pub const PRIORITY_OP_EXPIRATION: Duration = Duration::from_secs(PRIORITY_EXPIRA
 
 /// Received `PriorityOp` with additional metainformation required
 /// for efficient management of the operations queue.
-#[derive(Debug, Clone)]
+///
+/// Wall-clock time is used for `received_at`, so that the expiration
+/// keeps working for the operations restored from the persisted state.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ReceivedPriorityOp {
     op: PriorityOp,
-    received_at: Instant,
+    received_at: SystemTime,
 }
 
 impl ReceivedPriorityOp {
     pub fn is_outdated(&self) -> bool {
-        self.received_at.elapsed() >= PRIORITY_OP_EXPIRATION
+        // If the clock went backwards, the operation is considered fresh.
+        self.received_at
+            .elapsed()
+            .map(|elapsed| elapsed >= PRIORITY_OP_EXPIRATION)
+            .unwrap_or(false)
     }
 }
 
@@ -35,7 +43,7 @@ impl From<PriorityOp> for ReceivedPriorityOp {
     fn from(op: PriorityOp) -> Self {
         Self {
             op,
-            received_at: Instant::now(),
+            received_at: SystemTime::now(),
         }
     }
 }
@@ -0,0 +1,146 @@
+//! Registry of the tokens added to the Fluidex network.
+//!
+//! `NewToken` events only carry the token id and address, so the symbol, name and decimals
+//! are queried from the token contract once the event is confirmed. Tokens whose info couldn't
+//! be queried yet stay pending, and are queried again on the next poll.
+
+use crate::eth_client::TokenInfo;
+use crate::types::{AddTokenOp, Address, TokenId};
+use crate::utils::format_units;
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// Token of the Fluidex network together with the info provided by its contract.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RegisteredToken {
+    pub id: TokenId,
+    pub address: Address,
+    pub info: TokenInfo,
+}
+
+impl RegisteredToken {
+    /// Formats the amount in the smallest units of the token (e.g. wei) according to its decimals.
+    pub fn format_amount(&self, amount: impl ToString) -> String {
+        format_units(amount, self.info.decimals)
+    }
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct TokenRegistry {
+    tokens: BTreeMap<TokenId, RegisteredToken>,
+    /// Confirmed tokens whose info is not queried yet.
+    pending: BTreeMap<TokenId, Address>,
+}
+
+impl TokenRegistry {
+    /// Schedules the info of the newly confirmed tokens to be queried.
+    pub fn add_pending<'a>(&mut self, ops: impl IntoIterator<Item = &'a AddTokenOp>) {
+        for op in ops {
+            let token = &op.data;
+            if !self.tokens.contains_key(&token.token_id) {
+                self.pending.insert(token.token_id, token.token_address);
+            }
+        }
+    }
+
+    /// Tokens whose info has to be queried, in the order of their ids.
+    pub fn pending(&self) -> Vec<(TokenId, Address)> {
+        self.pending.iter().map(|(id, address)| (*id, *address)).collect()
+    }
+
+    pub fn insert(&mut self, id: TokenId, address: Address, info: TokenInfo) {
+        self.pending.remove(&id);
+        self.tokens.insert(id, RegisteredToken { id, address, info });
+    }
+
+    pub fn get(&self, id: TokenId) -> Option<&RegisteredToken> {
+        self.tokens.get(&id)
+    }
+
+    /// All the registered tokens, in the order of their ids.
+    pub fn tokens(&self) -> Vec<RegisteredToken> {
+        self.tokens.values().cloned().collect()
+    }
+
+    /// Loads the registry persisted by `TokenRegistry::save`.
+    /// Returns `None` if nothing was persisted yet.
+    pub fn load(path: &Path) -> anyhow::Result<Option<Self>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let contents = fs::read_to_string(path).with_context(|| format!("Unable to read token registry from {}", path.display()))?;
+        let registry = serde_json::from_str(&contents).with_context(|| format!("Malformed token registry in {}", path.display()))?;
+        Ok(Some(registry))
+    }
+
+    /// Persists the registry to the provided path, through a temporary file like `ETHState::save`.
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, serde_json::to_vec(self)?)
+            .with_context(|| format!("Unable to write token registry to {}", tmp_path.display()))?;
+        fs::rename(&tmp_path, path).with_context(|| format!("Unable to move token registry to {}", path.display()))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eth_client::token_inquirer::{DefaultReason, TokenField};
+    use crate::types::add_token_op::FluidexAddTokenOp;
+    use crate::types::H256;
+    use std::env;
+
+    fn add_token_op(token_id: u16, address: u64) -> AddTokenOp {
+        AddTokenOp {
+            data: FluidexAddTokenOp {
+                token_address: Address::from_low_u64_be(address),
+                token_id: TokenId(token_id),
+            },
+            eth_hash: H256::zero(),
+            eth_block: 1,
+        }
+    }
+
+    #[test]
+    fn test_pending_tokens_are_registered() {
+        let mut registry = TokenRegistry::default();
+        let ops = vec![add_token_op(1, 10), add_token_op(2, 20)];
+        registry.add_pending(&ops);
+        registry.add_pending(&ops[..1]);
+        assert_eq!(
+            registry.pending(),
+            vec![
+                (TokenId(1), Address::from_low_u64_be(10)),
+                (TokenId(2), Address::from_low_u64_be(20))
+            ]
+        );
+
+        let mut defaulted_fields = BTreeMap::new();
+        defaulted_fields.insert(TokenField::Name, DefaultReason::NotImplemented);
+        let info = TokenInfo {
+            symbol: "USDT".into(),
+            name: String::new(),
+            decimals: 6,
+            total_supply: 1_000_000.into(),
+            defaulted_fields,
+        };
+        registry.insert(TokenId(1), Address::from_low_u64_be(10), info);
+        assert_eq!(registry.pending(), vec![(TokenId(2), Address::from_low_u64_be(20))]);
+        // Rescanned events of the registered tokens are ignored.
+        registry.add_pending(&ops[..1]);
+        assert_eq!(registry.pending(), vec![(TokenId(2), Address::from_low_u64_be(20))]);
+        assert_eq!(registry.get(TokenId(1)).unwrap().format_amount(1_500_000), "1.5");
+        assert!(registry.get(TokenId(2)).is_none());
+
+        let path = env::temp_dir().join(format!("eth_watch_token_registry_test_{}.json", std::process::id()));
+        registry.save(&path).unwrap();
+        let restored = TokenRegistry::load(&path).unwrap().unwrap();
+        assert_eq!(restored.tokens(), registry.tokens());
+        assert_eq!(restored.pending(), registry.pending());
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -7,6 +7,7 @@ pub mod basic_types;
 pub mod config;
 pub mod contracts;
 pub mod eth_client;
+pub mod eth_sender;
 pub mod eth_signer;
 pub mod eth_watch;
 pub mod params;
@@ -2,6 +2,11 @@ mod primitives;
 
 // Re-export primitives associated with transactions.
 pub use self::primitives::{
-    eip1271_signature::EIP1271Signature, eth_batch_signature::EthBatchSignatures, eth_signature::TxEthSignature,
-    packed_eth_signature::PackedEthSignature, time_range::TimeRange, tx_hash::TxHash,
+    eip1271_signature::EIP1271Signature,
+    eip712_typed_data::{TypedData, TypedDataField},
+    eth_batch_signature::EthBatchSignatures,
+    eth_signature::TxEthSignature,
+    packed_eth_signature::PackedEthSignature,
+    time_range::TimeRange,
+    tx_hash::TxHash,
 };
@@ -0,0 +1,206 @@
+use crate::basic_types::{H256, U256};
+use anyhow::{anyhow, bail, ensure, Context};
+use parity_crypto::Keccak256;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Name of the type describing the signing domain.
+pub const DOMAIN_TYPE: &str = "EIP712Domain";
+
+/// Member of a struct type.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TypedDataField {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub type_name: String,
+}
+
+/// Structured data signed according to EIP-712, in the JSON format accepted by `eth_signTypedData_v4`.
+///
+/// `types` must contain the `EIP712Domain` type describing the `domain`, and the `primary_type`
+/// describing the `message`, together with all the struct types they reference.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TypedData {
+    pub types: BTreeMap<String, Vec<TypedDataField>>,
+    #[serde(rename = "primaryType")]
+    pub primary_type: String,
+    pub domain: Value,
+    pub message: Value,
+}
+
+impl TypedData {
+    /// Hash which is actually signed: `keccak256("\x19\x01" || domainSeparator || hashStruct(message))`.
+    pub fn sign_hash(&self) -> Result<H256, anyhow::Error> {
+        Ok(self.signing_payload()?.keccak256().into())
+    }
+
+    /// `"\x19\x01" || domainSeparator || hashStruct(message)`, which is hashed to get the hash to be signed.
+    pub fn signing_payload(&self) -> Result<Vec<u8>, anyhow::Error> {
+        let mut bytes = Vec::with_capacity(2 + 32 + 32);
+        bytes.extend_from_slice(b"\x19\x01");
+        bytes.extend_from_slice(self.domain_separator()?.as_bytes());
+        bytes.extend_from_slice(self.hash_struct(&self.primary_type, &self.message)?.as_bytes());
+        Ok(bytes)
+    }
+
+    pub fn domain_separator(&self) -> Result<H256, anyhow::Error> {
+        self.hash_struct(DOMAIN_TYPE, &self.domain)
+    }
+
+    /// `keccak256(typeHash || encodeData(data))` for the value of the struct type.
+    pub fn hash_struct(&self, type_name: &str, data: &Value) -> Result<H256, anyhow::Error> {
+        let mut encoded = self.type_hash(type_name)?.as_bytes().to_vec();
+        for field in self.fields(type_name)? {
+            let value = data
+                .get(&field.name)
+                .ok_or_else(|| anyhow!("{}.{} is missing", type_name, field.name))?;
+            let word = self
+                .encode_value(&field.type_name, value)
+                .with_context(|| format!("{}.{} is invalid", type_name, field.name))?;
+            encoded.extend_from_slice(word.as_bytes());
+        }
+        Ok(encoded.keccak256().into())
+    }
+
+    /// Encodes the struct type followed by the referenced struct types in alphabetical order,
+    /// e.g. `Mail(Person from,Person to,string contents)Person(string name,address wallet)`.
+    pub fn encode_type(&self, type_name: &str) -> Result<String, anyhow::Error> {
+        let mut dependencies = BTreeSet::new();
+        self.collect_dependencies(type_name, &mut dependencies)?;
+        dependencies.remove(type_name);
+
+        let mut encoded = String::new();
+        for name in std::iter::once(type_name).chain(dependencies.iter().map(String::as_str)) {
+            let members: Vec<String> = self
+                .fields(name)?
+                .iter()
+                .map(|field| format!("{} {}", field.type_name, field.name))
+                .collect();
+            encoded.push_str(&format!("{}({})", name, members.join(",")));
+        }
+        Ok(encoded)
+    }
+
+    fn type_hash(&self, type_name: &str) -> Result<H256, anyhow::Error> {
+        Ok(self.encode_type(type_name)?.as_bytes().keccak256().into())
+    }
+
+    fn fields(&self, type_name: &str) -> Result<&[TypedDataField], anyhow::Error> {
+        self.types
+            .get(type_name)
+            .map(Vec::as_slice)
+            .ok_or_else(|| anyhow!("type {} is not defined", type_name))
+    }
+
+    fn collect_dependencies(&self, type_name: &str, dependencies: &mut BTreeSet<String>) -> Result<(), anyhow::Error> {
+        if !dependencies.insert(type_name.to_string()) {
+            return Ok(());
+        }
+        for field in self.fields(type_name)? {
+            let base_type = base_type(&field.type_name);
+            if self.types.contains_key(base_type) {
+                self.collect_dependencies(base_type, dependencies)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Encodes the value as a single 32-byte word: arrays, structs and dynamic types are hashed.
+    fn encode_value(&self, type_name: &str, value: &Value) -> Result<H256, anyhow::Error> {
+        if let Some((item_type, size)) = split_array_type(type_name) {
+            let items = value.as_array().ok_or_else(|| anyhow!("expected an array"))?;
+            if let Some(size) = size {
+                ensure!(items.len() == size, "expected {} items, got {}", size, items.len());
+            }
+            let mut encoded = Vec::with_capacity(items.len() * 32);
+            for item in items {
+                encoded.extend_from_slice(self.encode_value(item_type, item)?.as_bytes());
+            }
+            return Ok(encoded.keccak256().into());
+        }
+
+        if self.types.contains_key(type_name) {
+            return self.hash_struct(type_name, value);
+        }
+        encode_atomic(type_name, value)
+    }
+}
+
+/// Strips the array suffixes, e.g. `Person[][2]` becomes `Person`.
+fn base_type(type_name: &str) -> &str {
+    type_name.find('[').map_or(type_name, |pos| &type_name[..pos])
+}
+
+/// Splits the array type into the item type and the fixed size, e.g. `Person[][2]` into `Person[]` and `2`.
+fn split_array_type(type_name: &str) -> Option<(&str, Option<usize>)> {
+    let inner = type_name.strip_suffix(']')?;
+    let pos = inner.rfind('[')?;
+    Some((&type_name[..pos], inner[pos + 1..].parse().ok()))
+}
+
+fn encode_atomic(type_name: &str, value: &Value) -> Result<H256, anyhow::Error> {
+    let mut word = [0u8; 32];
+    match type_name {
+        "string" => {
+            let string = value.as_str().ok_or_else(|| anyhow!("expected a string"))?;
+            return Ok(string.as_bytes().keccak256().into());
+        }
+        "bytes" => return Ok(decode_hex(value)?.keccak256().into()),
+        "bool" => {
+            let flag = value.as_bool().ok_or_else(|| anyhow!("expected a boolean"))?;
+            word[31] = flag as u8;
+        }
+        "address" => {
+            let bytes = decode_hex(value)?;
+            ensure!(bytes.len() == 20, "expected a 20 bytes address");
+            word[12..].copy_from_slice(&bytes);
+        }
+        _ if type_name.starts_with("bytes") => {
+            let size: usize = type_name["bytes".len()..].parse()?;
+            ensure!((1..=32).contains(&size), "unsupported type {}", type_name);
+            let bytes = decode_hex(value)?;
+            ensure!(bytes.len() == size, "expected {} bytes", size);
+            word[..size].copy_from_slice(&bytes);
+        }
+        _ if type_name.starts_with("uint") => parse_integer(value, false)?.to_big_endian(&mut word),
+        _ if type_name.starts_with("int") => parse_integer(value, true)?.to_big_endian(&mut word),
+        _ => bail!("unsupported type {}", type_name),
+    }
+    Ok(H256(word))
+}
+
+fn decode_hex(value: &Value) -> Result<Vec<u8>, anyhow::Error> {
+    let string = value.as_str().ok_or_else(|| anyhow!("expected a hex string"))?;
+    Ok(hex::decode(string.strip_prefix("0x").unwrap_or(string))?)
+}
+
+/// Parses the integer given either as a JSON number, or as a decimal or `0x`-prefixed hex string.
+/// Negative values of the signed types are encoded in two's complement.
+fn parse_integer(value: &Value, signed: bool) -> Result<U256, anyhow::Error> {
+    let (negative, abs) = match value {
+        Value::Number(number) => match (number.as_u64(), number.as_i64()) {
+            (Some(number), _) => (false, U256::from(number)),
+            (None, Some(number)) => (true, U256::from(number.unsigned_abs())),
+            _ => bail!("expected an integer"),
+        },
+        Value::String(string) => {
+            let (negative, digits) = match string.strip_prefix('-') {
+                Some(digits) => (true, digits),
+                None => (false, string.as_str()),
+            };
+            let abs = match digits.strip_prefix("0x") {
+                Some(hex_digits) => hex_digits.parse::<U256>().map_err(|_| anyhow!("invalid hex integer {}", string))?,
+                None => U256::from_dec_str(digits).map_err(|_| anyhow!("invalid integer {}", string))?,
+            };
+            (negative, abs)
+        }
+        _ => bail!("expected an integer"),
+    };
+
+    if !negative || abs.is_zero() {
+        return Ok(abs);
+    }
+    ensure!(signed, "negative value of an unsigned type");
+    Ok((!abs).overflowing_add(U256::one()).0)
+}
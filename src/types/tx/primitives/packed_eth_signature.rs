@@ -1,3 +1,4 @@
+use super::eip712_typed_data::TypedData;
 use crate::basic_types::{Address, H256};
 use crate::utils::ZeroPrefixHexSerde;
 use anyhow::ensure;
@@ -71,6 +72,21 @@ impl PackedEthSignature {
         Ok(public_to_address(&public_key))
     }
 
+    /// Signs the EIP-712 typed data using ethereum private key, results are identical to signature
+    /// created using `eth_signTypedData_v4`.
+    pub fn sign_typed_data(private_key: &H256, typed_data: &TypedData) -> Result<PackedEthSignature, anyhow::Error> {
+        let secret_key = (*private_key).into();
+        let signature = sign(&secret_key, &typed_data.sign_hash()?)?;
+        Ok(PackedEthSignature(signature))
+    }
+
+    /// Checks the EIP-712 signature and returns ethereum address of the signer.
+    /// typed_data should be the same data that was passed to `eth_signTypedData_v4`(or similar) method.
+    pub fn typed_data_recover_signer(&self, typed_data: &TypedData) -> Result<Address, anyhow::Error> {
+        let public_key = recover(&self.0, &typed_data.sign_hash()?)?;
+        Ok(public_to_address(&public_key))
+    }
+
     /// Get Ethereum address from private key.
     pub fn address_from_private_key(private_key: &H256) -> Result<Address, anyhow::Error> {
         Ok(KeyPair::from_secret((*private_key).into())?.address())